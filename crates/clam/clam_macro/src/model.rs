@@ -1,3 +1,6 @@
+// `darling`'s generated code for `#[darling(default)]` trips this lint
+#![allow(clippy::manual_unwrap_or_default)]
+
 pub fn parse(input: syn::DeriveInput) -> crate::Result<OptionsData> {
     use darling::FromDeriveInput;
     Ok(OptionsData::from_derive_input(&input)?)
@@ -33,6 +36,7 @@ pub enum ArrayConvention {
     #[default]
     Repeat,
     /// `--param=arg1:arg2:arg3`
+    #[allow(dead_code)]
     Sep(char),
 }
 
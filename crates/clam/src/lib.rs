@@ -94,31 +94,29 @@ impl<T: ArgValue> ArgValue for Option<T> {
 impl ArgValue for std::path::Path {
     fn set_cmd_arg<C: Command>(&self, name: &str, cmd: &mut C) {
         let name: &std::ffi::OsStr = name.as_ref();
-        cmd.args(&[name, &self.as_ref()]);
+        cmd.args([name, self.as_ref()]);
     }
 }
 
 impl ArgValue for std::path::PathBuf {
     fn set_cmd_arg<C: Command>(&self, name: &str, cmd: &mut C) {
         let name: &std::ffi::OsStr = name.as_ref();
-        cmd.args(&[name, &self.as_ref()]);
+        cmd.args([name, self.as_ref()]);
     }
 }
 
 impl ArgValue for str {
     fn set_cmd_arg<C: Command>(&self, name: &str, cmd: &mut C) {
-        cmd.args(&[name, &self]);
+        cmd.args([name, self]);
     }
 }
 
 impl ArgValue for String {
     fn set_cmd_arg<C: Command>(&self, name: &str, cmd: &mut C) {
-        cmd.args(&[name, &self]);
+        cmd.args([name, self]);
     }
 }
 
 impl<T: ArgValue> ArgValue for Vec<T> {
-    fn set_cmd_arg<C: Command>(&self, _name: &str, _cmd: &mut C) {
-        ()
-    }
+    fn set_cmd_arg<C: Command>(&self, _name: &str, _cmd: &mut C) {}
 }
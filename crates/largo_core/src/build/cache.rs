@@ -0,0 +1,174 @@
+//! A content-addressed cache of build outputs in `~/.largo/cache`, shared by
+//! every working copy of a project on this machine. Entries are keyed by a
+//! fingerprint of everything that goes into a build, and are never modified
+//! once written.
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use typedir::{Extend, PathBuf as P};
+
+use crate::dirs;
+
+/// Changing this invalidates every existing cache entry
+const FINGERPRINT_VERSION: &str = "1";
+
+/// Accumulates the inputs of a build into a fingerprint. Nothing that depends
+/// on where the working copy lives should go in here, or builds won't be
+/// shared between working copies.
+pub struct Fingerprinter(Sha256);
+
+impl Fingerprinter {
+    pub fn new() -> Self {
+        let mut fp = Self(Sha256::new());
+        fp.add_str("version", FINGERPRINT_VERSION);
+        fp
+    }
+
+    fn add_bytes(&mut self, label: &str, bytes: &[u8]) {
+        // Length-prefix everything, so that adjacent inputs can't run together
+        for part in [label.as_bytes(), bytes] {
+            self.0.update((part.len() as u64).to_le_bytes());
+            self.0.update(part);
+        }
+    }
+
+    pub fn add_str(&mut self, label: &str, value: &str) {
+        self.add_bytes(label, value.as_bytes());
+    }
+
+    /// Add the contents of a file, or note that it's missing
+    pub fn add_file(&mut self, label: &str, path: &std::path::Path) -> Result<()> {
+        match std::fs::read(path) {
+            Ok(contents) => self.add_bytes(label, &contents),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                self.add_str(label, "<missing>")
+            }
+            Err(err) => return Err(err.into()),
+        }
+        Ok(())
+    }
+
+    /// Add every file beneath a directory, by its relative path and contents
+    pub fn add_tree(&mut self, label: &str, dir: &std::path::Path) -> Result<()> {
+        let mut files = Vec::new();
+        collect_files(dir, &mut files)?;
+        files.sort();
+        self.add_str(label, &files.len().to_string());
+        for file in files {
+            let rel_path = file.strip_prefix(dir)?;
+            self.add_str("path", &rel_path.to_string_lossy());
+            self.add_file("contents", &file)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> String {
+        crate::util::to_hex(&self.0.finalize())
+    }
+}
+
+fn collect_files(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// A cache entry, which may or may not exist yet
+#[derive(Debug)]
+pub struct CacheEntry {
+    cache_dir: P<dirs::BuildCacheDir>,
+    fingerprint: String,
+}
+
+impl CacheEntry {
+    pub fn new(cache_dir: P<dirs::BuildCacheDir>, fingerprint: String) -> Self {
+        Self {
+            cache_dir,
+            fingerprint,
+        }
+    }
+
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    fn dir(&self) -> P<dirs::CachedBuildDir> {
+        self.cache_dir.clone().extend(self.fingerprint.as_str())
+    }
+
+    /// Copy the cached outputs into the build directory, if this entry
+    /// contains the artifact. Returns whether anything was restored.
+    pub fn try_restore(&self, build_dir: &P<dirs::BuildDir>, artifact: &str) -> Result<bool> {
+        let entry_dir = self.dir();
+        let cached_artifact: P<dirs::CachedBuildFile> = entry_dir.clone().extend(artifact);
+        if !cached_artifact.exists() {
+            return Ok(false);
+        }
+        for entry in std::fs::read_dir(&entry_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            let cached_file: P<dirs::CachedBuildFile> = entry_dir.clone().extend(file_name);
+            let build_file: P<dirs::BuildFile> = build_dir.clone().extend(file_name);
+            std::fs::copy(&cached_file, &build_file)?;
+        }
+        Ok(true)
+    }
+
+    /// Copy the job's outputs in the build directory, like `main.pdf`, and the
+    /// variables' package into this entry, except for those with one of the
+    /// excluded extensions. The entry is assembled off to the side and moved
+    /// into place, so that concurrent builds never see a partial entry.
+    pub fn try_store(
+        &self,
+        build_dir: &P<dirs::BuildDir>,
+        jobname: &str,
+        exclude: &[&str],
+    ) -> Result<()> {
+        let entry_dir = self.dir();
+        if entry_dir.exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.cache_dir)?;
+        let staging_name = format!(".{}.{}", self.fingerprint, std::process::id());
+        let staging_dir: P<dirs::CachedBuildDir> =
+            self.cache_dir.clone().extend(staging_name.as_str());
+        std::fs::create_dir_all(&staging_dir)?;
+        for entry in std::fs::read_dir(build_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            let outputs = file_name == dirs::LARGO_VARS_FILE
+                || file_name.starts_with(&format!("{}.", jobname));
+            let excluded = exclude
+                .iter()
+                .any(|ext| file_name.ends_with(&format!(".{}", ext)));
+            if !outputs || excluded {
+                continue;
+            }
+            let build_file: P<dirs::BuildFile> = build_dir.clone().extend(file_name);
+            let cached_file: P<dirs::CachedBuildFile> = staging_dir.clone().extend(file_name);
+            std::fs::copy(&build_file, &cached_file)?;
+        }
+        // Someone else may have stored the same build in the meantime, in
+        // which case theirs is as good as ours.
+        if std::fs::rename(&staging_dir, &entry_dir).is_err() {
+            dirs::remove_dir_all(&staging_dir)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+//! Writing out a build as a Makefile, so that a project can be built the same
+//! way without largo.
+
+use std::fmt::Write;
+
+use anyhow::anyhow;
+
+use super::{BuildRunner, BIB_RERUNS};
+use crate::conf::{Dependencies, Dependency};
+use crate::Result;
+
+/// How an ejected Makefile starts, so that it can be told from anyone else's
+pub const MAKEFILE_HEADER: &str = "# Builds `";
+
+/// Check that a Makefile can build a project with these dependencies. It
+/// can't fetch CTAN packages that the TeX distribution doesn't have, clone git
+/// repositories, or build Largo projects, so projects that depend on them
+/// aren't ejected.
+pub fn check_dependencies(deps: &Dependencies) -> Result<()> {
+    let unbuildable: Vec<String> = deps
+        .into_iter()
+        .filter(|(name, dep)| match dep {
+            Dependency::Version(_) | Dependency::Ctan { .. } => {
+                crate::dependencies::find_in_distribution(name).is_none()
+            }
+            Dependency::Path { largo, .. } => *largo,
+            Dependency::Member { .. } | Dependency::Git { .. } => true,
+        })
+        .map(|(name, _)| format!("`{}`", name))
+        .collect();
+    if unbuildable.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "a Makefile can't fetch or build {}; install the packages in your TeX \
+         distribution, or keep building with largo",
+        unbuildable.join(", ")
+    ))
+}
+
+/// The day of the build, worked out by make: from `SOURCE_DATE_EPOCH`, with
+/// either GNU or BSD `date`, or else today
+const BUILD_DATE_RULE: &str = "\
+ifdef SOURCE_DATE_EPOCH
+BUILD_DATE := $(shell date -u -d @$(SOURCE_DATE_EPOCH) +%Y-%m-%d 2>/dev/null || date -u -r $(SOURCE_DATE_EPOCH) +%Y-%m-%d)
+else
+BUILD_DATE := $(shell date +%Y-%m-%d)
+endif
+";
+
+impl<'c> BuildRunner<'c> {
+    /// A Makefile that runs the same commands as this build, in the same
+    /// order, from the project's root. Paths in the project are written
+    /// relative to it, so that the Makefile works in any copy of the project.
+    pub fn makefile(&self) -> Result<String> {
+        let root = self.ctx.root_dir.to_string_lossy().into_owned();
+        let build_dir = self.ctx.build_dir.relative_to(&self.ctx.root_dir);
+        let build_dir = build_dir.to_string_lossy();
+        let artifact = format!("$(BUILD_DIR)/{}", self.ctx.artifact_name());
+        let mut start_file = Vec::new();
+        self.write_start_file(&mut start_file)?;
+        let start_file = String::from_utf8_lossy(&start_file);
+        let vars_package: Vec<_> = self
+            .ctx
+            .vars
+            .to_package()?
+            .lines()
+            .map(|line| quote(line, &root))
+            .collect();
+
+        // Unless the project fixes it, the date is that of each build
+        let build_date = self.ctx.vars.build_date.to_string();
+        let dated = |line: String| match self.ctx.fixed_build_date {
+            true => line,
+            false => line.replace(&build_date, "$(BUILD_DATE)"),
+        };
+        let mut commands = vec![
+            "mkdir -p $(BUILD_DIR)".to_string(),
+            format!(
+                "printf '%s' {} > $(BUILD_DIR)/{}",
+                quote(&start_file, &root),
+                crate::dirs::START_FILE
+            ),
+            dated(format!(
+                "printf '%s\\n' {} > $(BUILD_DIR)/{}",
+                vars_package.join(" "),
+                crate::dirs::LARGO_VARS_FILE
+            )),
+        ];
+        if let Some(docstrip) = &self.docstrip {
+            commands.push(dated(command_line(docstrip, &root)));
+        }
+        let engine = dated(command_line(self.engine.command(), &root));
+        commands.push(engine.clone());
+        if let Some(bib_engine) = &self.bib_engine {
+            commands.push(dated(command_line(bib_engine.command(), &root)));
+            for _ in 0..BIB_RERUNS {
+                commands.push(engine.clone());
+            }
+        }
+        if let Some(output_dir) = &self.ctx.output_dir {
+            let output_dir = output_dir.relative_to(&self.ctx.root_dir);
+            let output_dir = quote(&output_dir.to_string_lossy(), &root);
+            commands.push(format!("mkdir -p {}", output_dir));
+            commands.push(format!("cp {} {}", artifact, output_dir));
+        }
+
+        let mut makefile = String::new();
+        writeln!(
+            makefile,
+            "{}{}` as `largo build --profile {}` does, for anyone without largo.",
+            MAKEFILE_HEADER, self.ctx.project_name, self.ctx.profile_name
+        )?;
+        writeln!(
+            makefile,
+            "# Written by `largo eject`; change `largo.toml` and eject again, rather than"
+        )?;
+        writeln!(
+            makefile,
+            "# editing this. Dependencies are expected in the TeX distribution."
+        )?;
+        writeln!(makefile)?;
+        writeln!(makefile, "BUILD_DIR := {}", build_dir)?;
+        if !self.ctx.fixed_build_date {
+            makefile.push_str(BUILD_DATE_RULE);
+        }
+        writeln!(makefile)?;
+        writeln!(makefile, ".PHONY: all clean")?;
+        writeln!(makefile)?;
+        writeln!(makefile, "all: {}", artifact)?;
+        writeln!(makefile)?;
+        writeln!(
+            makefile,
+            "{}: $(shell find {} -type f)",
+            artifact,
+            crate::dirs::SRC_DIR
+        )?;
+        for command in commands {
+            writeln!(makefile, "\t{}", command)?;
+        }
+        writeln!(makefile)?;
+        writeln!(makefile, "clean:")?;
+        writeln!(makefile, "\trm -rf $(BUILD_DIR)")?;
+        Ok(makefile)
+    }
+}
+
+/// A command as a line of a recipe, with its environment, run in its
+/// working directory
+fn command_line(cmd: &crate::Command, root: &str) -> String {
+    let cmd = cmd.as_std();
+    let mut line = String::new();
+    if let Some(dir) = cmd.get_current_dir() {
+        line.push_str(&format!("cd {} && ", quote(&dir.to_string_lossy(), root)));
+    }
+    let mut envs: Vec<_> = cmd
+        .get_envs()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect();
+    envs.sort();
+    for (key, value) in envs {
+        line.push_str(&format!(
+            "{}={} ",
+            key.to_string_lossy(),
+            quote(&value.to_string_lossy(), root)
+        ));
+    }
+    line.push_str(&quote(&cmd.get_program().to_string_lossy(), root));
+    for arg in cmd.get_args() {
+        line.push(' ');
+        line.push_str(&quote(&arg.to_string_lossy(), root));
+    }
+    line
+}
+
+/// Quote a word for the shell, and escape it from make, writing the project's
+/// root as `$(CURDIR)`
+fn quote(word: &str, root: &str) -> String {
+    let is_plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    let escape = |part: &str| part.replace('$', "$$").replace('\'', r"'\''");
+    let quoted = word
+        .split(root)
+        .map(escape)
+        .collect::<Vec<_>>()
+        .join("$(CURDIR)");
+    if is_plain && !word.contains(root) {
+        quoted
+    } else {
+        format!("'{}'", quoted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_quoted_for_make() {
+        assert_eq!(
+            quote("-interaction=nonstopmode", "/p"),
+            "-interaction=nonstopmode"
+        );
+        assert_eq!(
+            quote("/p/src:/p/figures:", "/p"),
+            "'$(CURDIR)/src:$(CURDIR)/figures:'"
+        );
+        assert_eq!(quote(r"\def\x{it's $5}", "/p"), r"'\def\x{it'\''s $$5}'");
+    }
+}
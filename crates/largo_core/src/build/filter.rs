@@ -1,35 +1,250 @@
-//! Utilities for filtering output from tex engines, etc.
+//! Utilities for filtering output from tex engines, etc. Output is classified
+//! line by line, as it streams in; an item that spans several lines, like an
+//! error and the line of source it was found on, is held back until it's
+//! complete.
 
-struct UndefinedControlSequence {
-    file: String,
-    linum: usize,
-    /// The source line containing the error.
-    src: String,
+use std::collections::VecDeque;
+
+/// What a line of output, or a few lines together, amount to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InfoItem {
+    /// An error, like `! Undefined control sequence.`, and the line of the
+    /// source it was found on, if the engine said
+    Error { msg: String, line: Option<usize> },
+    /// A file the engine couldn't find, like ``! LaTeX Error: File `foo.sty'
+    /// not found.``
+    FileNotFound { file: String, msg: String },
+    /// A warning from LaTeX, a package or class, or the bibliography processor
+    Warning { msg: String },
+    /// An overfull or underfull box
+    BadBox { msg: String },
+    /// Any other line of output
+    Output { line: String },
 }
 
-pub trait InfoItem {}
+/// How many lines after an error TeX may take to say where it was found, as
+/// in `l.12 \foo`. LaTeX's errors are followed by a few lines of help first.
+const SOURCE_LINE_WINDOW: usize = 8;
 
-impl InfoItem for UndefinedControlSequence {}
+/// An item that the lines after it may yet complete
+#[derive(Debug)]
+enum Open {
+    /// An error, until the line it was found on is reported
+    Error {
+        item: InfoItem,
+        continuation: Option<String>,
+        lines_left: usize,
+    },
+    /// A warning, while the lines after it begin with its continuation, like
+    /// `(hyperref)`
+    Warning { msg: String, continuation: String },
+}
 
-pub struct Info {
-    items: Vec<Box<dyn InfoItem>>,
+/// Classifies the lines of an engine's output into [`InfoItem`]s
+#[derive(Debug, Default)]
+pub struct Filter {
+    ready: VecDeque<InfoItem>,
+    open: Option<Open>,
+    /// Lines that came after the open item, which wait for it so that the
+    /// output stays in order
+    held: Vec<InfoItem>,
 }
 
-impl Info {
-    fn push<I: InfoItem>(&mut self, item: I) {
-        self.items.push(Box::new(item));
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classify the next line of output
+    pub fn push(&mut self, line: String) {
+        match &mut self.open {
+            Some(Open::Warning { msg, continuation }) => {
+                if let Some(rest) = line.strip_prefix(continuation.as_str()) {
+                    msg.push(' ');
+                    msg.push_str(rest.trim_start());
+                    return;
+                }
+                self.close();
+            }
+            Some(Open::Error {
+                item,
+                continuation,
+                lines_left,
+            }) => {
+                let continued = continuation
+                    .as_deref()
+                    .and_then(|continuation| line.strip_prefix(continuation));
+                if let (Some(rest), InfoItem::Error { msg, .. }) = (continued, &mut *item) {
+                    msg.push(' ');
+                    msg.push_str(rest.trim_start());
+                    return;
+                }
+                if let (Some(found), InfoItem::Error { line: at, .. }) = (source_line(&line), item)
+                {
+                    *at = Some(found);
+                    self.held.push(InfoItem::Output { line });
+                    self.close();
+                    return;
+                }
+                *lines_left = lines_left.saturating_sub(1);
+                if *lines_left == 0 {
+                    self.close();
+                }
+            }
+            None => (),
+        }
+        match classify(line) {
+            (item @ (InfoItem::Error { .. } | InfoItem::FileNotFound { .. }), continuation) => {
+                self.close();
+                self.open = Some(Open::Error {
+                    item,
+                    continuation,
+                    lines_left: SOURCE_LINE_WINDOW,
+                });
+            }
+            (InfoItem::Warning { msg }, Some(continuation)) => {
+                self.close();
+                self.open = Some(Open::Warning { msg, continuation });
+            }
+            (item, _) if self.open.is_some() => self.held.push(item),
+            (item, _) => self.ready.push_back(item),
+        }
+    }
+
+    /// Release whatever is still held back, at the end of the output
+    pub fn finish(&mut self) {
+        self.close();
+    }
+
+    /// The next complete item, if there is one
+    pub fn next_item(&mut self) -> Option<InfoItem> {
+        self.ready.pop_front()
+    }
+
+    /// Complete the open item, and release the lines held behind it
+    fn close(&mut self) {
+        match self.open.take() {
+            Some(Open::Error { item, .. }) => self.ready.push_back(item),
+            Some(Open::Warning { msg, .. }) => self.ready.push_back(InfoItem::Warning { msg }),
+            None => (),
+        }
+        self.ready.extend(self.held.drain(..));
+    }
+}
+
+/// What a single line is, and the prefix of any lines that continue it
+fn classify(mut line: String) -> (InfoItem, Option<String>) {
+    if let Some(msg) = line.strip_prefix("ERROR - ") {
+        // Biber's errors
+        let msg = msg.to_string();
+        (InfoItem::Error { msg, line: None }, None)
+    } else if line.starts_with("! ") {
+        let msg = line.split_off(2);
+        let continuation = latex_message(&msg, "Error: ").and_then(|(source, _)| source);
+        let item = match crate::diagnostics::missing_file(&msg) {
+            Some(file) => InfoItem::FileNotFound {
+                file: file.to_string(),
+                msg,
+            },
+            None => InfoItem::Error { msg, line: None },
+        };
+        (item, continuation)
+    } else if let Some(msg) = line.strip_prefix("WARN - ") {
+        // Biber's warnings
+        let msg = msg.to_string();
+        (InfoItem::Warning { msg }, None)
+    } else if let Some((continuation, msg)) = latex_message(&line, "Warning: ") {
+        let msg = msg.to_string();
+        (InfoItem::Warning { msg }, continuation)
+    } else if line.starts_with(r"Overfull \") || line.starts_with(r"Underfull \") {
+        (InfoItem::BadBox { msg: line }, None)
+    } else {
+        (InfoItem::Output { line }, None)
     }
 }
 
-trait FilterParser {}
+/// The message of an error or warning from LaTeX, or from a package or class,
+/// which look like `LaTeX Warning: ...` or `Package foo Warning: ...`, and the
+/// prefix of its continuation lines, like `(foo)`, if it has them
+fn latex_message<'l>(line: &'l str, kind: &str) -> Option<(Option<String>, &'l str)> {
+    let (source, msg) = line.split_once(kind)?;
+    let source = source.trim_end();
+    let continuation = if source == "LaTeX" {
+        None
+    } else if let Some(name) = source
+        .strip_prefix("Package ")
+        .or_else(|| source.strip_prefix("Class "))
+        .or_else(|| source.strip_prefix("LaTeX "))
+    {
+        Some(format!("({})", name))
+    } else {
+        return None;
+    };
+    Some((continuation, msg))
+}
 
-struct Filter {
+/// The line number in TeX's report of where an error was found, like
+/// `l.12 \foo`
+fn source_line(line: &str) -> Option<usize> {
+    let rest = line.strip_prefix("l.")?;
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..digits].parse().ok()
 }
 
-pub fn filter_errors<R: std::io::BufRead>(output: R) -> crate::Result<Info> {
-    let mut lines = output.lines();
-    for line in lines.into_iter() {
-        self.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(output: &str) -> Vec<InfoItem> {
+        let mut filter = Filter::new();
+        for line in output.lines() {
+            filter.push(line.to_string());
+        }
+        filter.finish();
+        std::iter::from_fn(|| filter.next_item()).collect()
+    }
+
+    #[test]
+    fn output_is_classified() {
+        let items = filter(
+            "(./main.tex\n\
+             Package hyperref Warning: Token not allowed in a PDF string (Unicode):\n\
+             (hyperref)                removing `math shift' on input line 5.\n\
+             ! Undefined control sequence.\n\
+             l.7 \\foo\n\
+             Overfull \\hbox (1.2pt too wide) in paragraph at lines 9--10\n\
+             ! LaTeX Error: File `missing.sty' not found.\n",
+        );
+        assert_eq!(
+            items,
+            vec![
+                InfoItem::Output {
+                    line: "(./main.tex".to_string()
+                },
+                InfoItem::Warning {
+                    msg: "Token not allowed in a PDF string (Unicode): \
+                          removing `math shift' on input line 5."
+                        .to_string()
+                },
+                InfoItem::Error {
+                    msg: "Undefined control sequence.".to_string(),
+                    line: Some(7)
+                },
+                InfoItem::Output {
+                    line: "l.7 \\foo".to_string()
+                },
+                InfoItem::BadBox {
+                    msg: "Overfull \\hbox (1.2pt too wide) in paragraph at lines 9--10".to_string()
+                },
+                InfoItem::FileNotFound {
+                    file: "missing.sty".to_string(),
+                    msg: "LaTeX Error: File `missing.sty' not found.".to_string()
+                },
+            ]
+        );
+        assert_eq!(source_line("l.12 \\begin{document}"), Some(12));
+        assert_eq!(source_line("l.a"), None);
     }
-    Ok(())
 }
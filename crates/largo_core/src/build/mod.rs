@@ -1,34 +1,73 @@
 use anyhow::{anyhow, Result};
+use merge::Merge;
+use serde::Serialize;
 use tokio_stream as stream;
 
 use typedir::{Extend, PathBuf as P};
 
 use crate::conf::LargoConfig;
-use crate::conf::{Dependencies, ProfileName, Project, ProjectSettings, SystemSettings};
+use crate::conf::{
+    Dependencies, EnvVars, ProfileName, Project, ProjectMetadata, ProjectSettings, SystemSettings,
+    UserVars,
+};
 use crate::dirs;
 use crate::engines;
 use crate::vars::LargoVars;
 
+mod cache;
+mod eject;
+pub(crate) mod filter;
+
+pub use eject::{check_dependencies as check_ejectable, MAKEFILE_HEADER};
+
+/// Extensions of the intermediate files that are pruned from the build
+/// directory when `keep-intermediates = false`.
+pub const INTERMEDIATE_EXTENSIONS: &[&str] = &["aux", "toc", "out", "synctex.gz"];
+
+/// How many times the engine is rerun after the bibliography pass, to resolve
+/// citations and references
+const BIB_RERUNS: usize = 2;
+
+/// Extensions of the job's files that may be left half-written by a cancelled
+/// build
+const PARTIAL_OUTPUT_EXTENSIONS: &[&str] = &["pdf", "aux", "toc", "out", "synctex.gz"];
+
+/// Extensions of the outputs that aren't shared through the build cache.
+/// SyncTeX data holds absolute paths into the working copy it was built in.
+const UNCACHED_EXTENSIONS: &[&str] = &["synctex.gz"];
+
 impl<'a> crate::vars::LargoVars<'a> {
-    fn from_build_settings<'b>(settings: &'b BuildBuilderUnpacked<'a>) -> Self {
-        Self {
-            profile: settings.profile_name,
-            bibliography: settings.conf.bib.bibliography,
+    fn from_build_settings<'b>(settings: &'b BuildBuilderUnpacked<'a>) -> Result<Self> {
+        let user = crate::vars::user_macros(&settings.vars)?;
+        Ok(Self {
+            profile: settings.profile_name.clone(),
+            profiles: settings.profile_names.clone(),
+            project_name: settings.project_name.clone(),
+            bibliography: settings
+                .bibliography
+                .iter()
+                .filter_map(|bib| bib.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect(),
             // FIXME: unnecessary allocation
             output_directory: settings.dirs.build.clone(),
-        }
+            metadata: settings.metadata.clone(),
+            user,
+            git: settings.git.clone(),
+            build_date: settings.build_date,
+        })
     }
 }
 
 /// Level of severity of information to forward from TeX engine
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum LogLevel {
     #[default]
     Warning,
     Error,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Verbosity {
     /// Never emit anything, even on failure
     #[default]
@@ -39,12 +78,36 @@ pub enum Verbosity {
     Noisy,
 }
 
+impl Verbosity {
+    /// Whether to pass on this piece of engine output
+    fn forwards(&self, info: &engines::EngineInfo) -> bool {
+        use engines::EngineInfo;
+        match (self, info) {
+            (Verbosity::Silent, _) => false,
+            (Verbosity::Info(_), EngineInfo::Error { .. }) => true,
+            (Verbosity::Info(level), EngineInfo::Warning { .. }) => *level == LogLevel::Warning,
+            (Verbosity::Info(_), EngineInfo::Output { .. }) => false,
+            (Verbosity::Noisy, _) => true,
+        }
+    }
+}
+
 pub struct BuildBuilder<'a> {
     conf: &'a LargoConfig<'a>,
     project: Project<'a>,
     verbosity: Verbosity,
     /// Which profile to build in
     profile: Option<crate::conf::ProfileName<'a>>,
+    /// Which document to build, if the project has several
+    document: Option<crate::conf::DocumentName<'a>>,
+    /// A test document to build instead of the project's own documents
+    test: Option<&'a str>,
+    /// Whether to only check the document for errors, without producing it
+    check: bool,
+    /// Whether to report how long each phase of the build took
+    timings: bool,
+    /// The outputs of largo projects this one depends on, already built
+    input_dirs: Vec<std::path::PathBuf>,
 }
 
 impl<'a> BuildBuilder<'a> {
@@ -52,13 +115,37 @@ impl<'a> BuildBuilder<'a> {
         Self {
             conf,
             project,
-            verbosity: Verbosity::Silent,
+            verbosity: Verbosity::default(),
             profile: None,
+            document: None,
+            test: None,
+            check: false,
+            timings: false,
+            input_dirs: Vec::new(),
         }
     }
 
     pub fn with_profile(mut self, name: Option<crate::conf::ProfileName<'a>>) -> Self {
-        self.profile = name.as_ref().copied();
+        self.profile = name;
+        self
+    }
+
+    pub fn with_document(mut self, name: Option<crate::conf::DocumentName<'a>>) -> Self {
+        self.document = name;
+        self
+    }
+
+    /// Build a file from the `tests` directory instead of a document
+    pub fn with_test(mut self, test: Option<&'a str>) -> Self {
+        self.test = test;
+        self
+    }
+
+    /// Check the document for errors as quickly as possible: a single draft
+    /// mode pass that stops at the first error, with no bibliography pass and
+    /// no PDF.
+    pub fn with_check(mut self, check: bool) -> Self {
+        self.check = check;
         self
     }
 
@@ -67,49 +154,159 @@ impl<'a> BuildBuilder<'a> {
         self
     }
 
+    pub fn with_timings(mut self, timings: bool) -> Self {
+        self.timings = timings;
+        self
+    }
+
+    /// Search these directories for inputs, as well as those of the
+    /// dependencies; for the build directories of largo dependencies
+    pub fn with_input_dirs(mut self, input_dirs: Vec<std::path::PathBuf>) -> Self {
+        self.input_dirs = input_dirs;
+        self
+    }
+
     /// Unpack the data we've been passed into a more convenient shape
     fn try_finish_unpack(self) -> Result<BuildBuilderUnpacked<'a>> {
-        use merge::Merge;
         let conf = self.conf;
         let project = self.project;
-        let profile_name = self.profile.unwrap_or(self.conf.default_profile);
-        let project_name = project.config.project.name;
+        let profile_name = self
+            .profile
+            .unwrap_or_else(|| self.conf.default_profile.clone());
+        let project_name = project.config.project.name.to_string();
+        let metadata = project.config.project.metadata.clone();
+        // Without an explicit choice, the first listed document is built
+        let document = match self.test {
+            Some(_) => None,
+            None => project.config.select_document(self.document)?,
+        };
+        let main_file = match (self.test, &document) {
+            (Some(test), _) => test.to_string(),
+            (None, Some(doc)) => doc.main_file(),
+            (None, None) => project.config.project.main_file().to_string(),
+        };
+        // Name the outputs after the main file, as if it were compiled directly
+        let jobname = std::path::Path::new(&main_file)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("invalid main file `{}`", main_file))?
+            .to_string();
+        // A package written in docstrip's format is generated from its `.ins`
+        // file, which is named for the project's main file, before any
+        // document or test that uses it is built
+        let ins_file =
+            std::path::Path::new(project.config.project.main_file()).with_extension("ins");
+
+        let profiles = project.config.all_profiles();
+        let profile_names = profiles.iter().map(|(name, _)| name.clone()).collect();
+        let profile = profiles
+            .select_profile(&profile_name)
+            .ok_or_else(|| anyhow!("profile `{}` not found", profile_name))?;
+        let crate::conf::Profile {
+            mut project_settings,
+            system_settings: profile_system_settings,
+            env,
+            vars,
+        } = project.config.effective_profile(profile);
+        if self.check {
+            project_settings.draft_mode = Some(true);
+            project_settings.skip_bib = Some(true);
+            project_settings.synctex = Some(false);
+            project_settings.cache = Some(false);
+        }
+        let proj_conf = project.config.project;
+        let deps_layout = proj_conf.deps_layout.unwrap_or_default();
+        // The profile's choice of engine and format take precedence
+        let mut system_settings = proj_conf.system_settings;
+        profile_system_settings.apply(&mut system_settings);
 
         let root = project.root;
+        // A document's own bibliography replaces the global one
+        let bibliography = match document.as_ref().and_then(|doc| doc.bibliography.as_ref()) {
+            Some(bibs) => bibs.iter().map(|bib| root.join(bib)).collect(),
+            // Already resolved against the global configuration's directory
+            None => conf.bib.bibliography.iter().map(Into::into).collect(),
+        };
         let src = root.clone().extend(());
         let target = root.clone().extend(());
-        let build = target.clone().extend(&profile_name).extend(());
+        let tests: Option<P<dirs::TestsDir>> = self.test.map(|_| root.clone().extend(()));
+        let build = match self.test {
+            None if self.check => {
+                let profile_dir: P<dirs::ProfileTargetDir> = target.clone().extend(&profile_name);
+                dirs::CheckDir::into_build_dir(profile_dir.extend(()))
+            }
+            Some(_) => {
+                let profile_dir: P<dirs::ProfileTargetDir> = target.clone().extend(&profile_name);
+                let tests_target_dir: P<dirs::TestsTargetDir> = profile_dir.extend(());
+                dirs::TestBuildDir::into_build_dir(tests_target_dir.extend(jobname.as_str()))
+            }
+            None => target.clone().extend(&profile_name).extend(()),
+        };
+        // Only real builds of the document are copied out of `target`
+        let output = match &project_settings.output_dir {
+            Some(output_dir) if self.test.is_none() && !self.check => {
+                Some(dirs::OutputDir::try_for_project(&root, output_dir)?)
+            }
+            _ => None,
+        };
+        let (build, output) = match &document {
+            Some(doc) => (
+                build.extend(&doc.name),
+                output.map(|output| output.extend(&doc.name)),
+            ),
+            None => (build, output),
+        };
         let dirs = BuildDirs {
             root,
             src,
+            tests,
             target,
             build,
+            output,
         };
-        let mut profiles = project.config.profiles.unwrap_or_default();
-        profiles.merge_left(crate::conf::Profiles::standard());
-        let profile = profiles
-            .select_profile(&profile_name)
-            .ok_or_else(|| anyhow!("profile `{}` not found", profile_name))?;
-        let proj_conf = project.config.project;
-        let mut project_settings = proj_conf.project_settings;
-        project_settings.merge_right(profile.project_settings);
         let dependencies = project.config.dependencies;
+        let ins_file = Some(dirs.src.join(ins_file)).filter(|ins_file| ins_file.is_file());
+        let build_date = crate::conf::BuildDate::resolve(project_settings.build_date, &env)?;
         Ok(BuildBuilderUnpacked {
             conf,
             dirs,
             project_name,
+            metadata,
             profile_name,
-            system_settings: proj_conf.system_settings,
+            profile_names,
+            main_file,
+            jobname,
+            ins_file,
+            // Only read when the build is actually run
+            git: None,
+            build_date,
+            system_settings,
             project_settings,
             dependencies,
+            deps_layout,
+            bibliography,
+            env,
+            vars,
+            input_dirs: self.input_dirs,
             verbosity: self.verbosity,
+            halt_on_error: self.check,
+            timings: self.timings,
         })
     }
 
     pub fn try_finish(self) -> Result<BuildRunner<'a>> {
-        let unpacked = self.try_finish_unpack()?;
+        let mut unpacked = self.try_finish_unpack()?;
+        unpacked.git = crate::vars::GitInfo::read(&unpacked.dirs.root);
         unpacked.into_runner()
     }
+
+    /// Where the build would leave the finished document. Nothing is run, and
+    /// the project's files aren't read, so this is cheap.
+    pub fn try_artifact(self) -> Result<P<dirs::ArtifactFile>> {
+        let unpacked = self.try_finish_unpack()?;
+        let file_name = format!("{}.pdf", unpacked.jobname);
+        Ok(unpacked.dirs.build.extend(file_name.as_str()))
+    }
 }
 
 /// Build directories
@@ -117,8 +314,12 @@ impl<'a> BuildBuilder<'a> {
 struct BuildDirs {
     root: P<dirs::RootDir>,
     src: P<dirs::SrcDir>,
+    /// The tests directory, when building a test document
+    tests: Option<P<dirs::TestsDir>>,
     target: P<dirs::TargetDir>,
     build: P<dirs::BuildDir>,
+    /// Where the finished document is copied to, if it's copied anywhere
+    output: Option<P<dirs::OutputDir>>,
 }
 
 /// An intermediate state of unpackaging and treating all the data we've
@@ -128,85 +329,357 @@ struct BuildBuilderUnpacked<'a> {
     conf: &'a LargoConfig<'a>,
     dirs: BuildDirs,
     profile_name: ProfileName<'a>,
-    project_name: &'a str,
+    /// Every profile the project has, for `\ifLargoProfile...`
+    profile_names: Vec<ProfileName<'a>>,
+    project_name: String,
+    metadata: ProjectMetadata<'a>,
+    /// The entry point of the document being built
+    main_file: String,
+    /// The name given to the engine's output files
+    jobname: String,
+    /// The docstrip file that generates the project's package, if it has one
+    ins_file: Option<std::path::PathBuf>,
     system_settings: SystemSettings,
     project_settings: ProjectSettings,
     dependencies: Dependencies<'a>,
+    deps_layout: crate::conf::DepsLayout,
+    /// The bibliography files, as absolute paths
+    bibliography: Vec<std::path::PathBuf>,
+    /// Environment variables for the engine and bibliography processor
+    env: EnvVars,
+    /// Variables defined for the document
+    vars: UserVars,
+    /// The commit being built, for `\LargoGitCommit`
+    git: Option<crate::vars::GitInfo>,
+    /// The day the build says it was made on, for `\LargoBuildDate`
+    build_date: crate::conf::BuildDate,
+    /// The build directories of largo dependencies
+    input_dirs: Vec<std::path::PathBuf>,
     verbosity: Verbosity,
+    halt_on_error: bool,
+    timings: bool,
 }
 
 impl<'a> BuildBuilderUnpacked<'a> {
-    fn engine_builder(&self) -> engines::pdflatex::PdflatexBuilder {
-        let tex_engine = &self.system_settings.tex_engine;
-        let tex_format = &self.system_settings.tex_format;
-        match (tex_engine, tex_format) {
-            (crate::conf::TexEngine::Pdftex, crate::conf::TexFormat::Latex) => {
-                engines::pdflatex::PdflatexBuilder::new(self.conf)
-            }
-            (_, _) => {
-                unimplemented!();
-            }
+    fn engine_builder(&self) -> Result<engines::pdflatex::PdflatexBuilder> {
+        let tex_engine = self.system_settings.tex_engine;
+        let tex_format = self.system_settings.tex_format;
+        if !engines::is_supported(tex_engine, tex_format) {
+            return Err(anyhow!(
+                "engine `{}` with format `{}` isn't supported yet",
+                tex_engine,
+                tex_format
+            ));
         }
+        Ok(engines::pdflatex::PdflatexBuilder::new(self.conf))
     }
 
-    fn get_engine(&self) -> Result<engines::Engine> {
+    /// Where this profile's CTAN dependencies are installed
+    fn deps_dir(&self) -> P<dirs::DepsDir> {
+        dirs::DepsDir::for_profile(&self.dirs.target, &self.profile_name, self.deps_layout)
+    }
+
+    fn get_engine(&self, env: &EnvVars) -> Result<engines::Engine> {
         use engines::EngineBuilder;
+        let deps_dir = self.deps_dir();
+        let mut input_dirs = crate::dependencies::get_dependency_paths(
+            &self.dirs.root,
+            &deps_dir,
+            &self.dependencies,
+        )?;
+        input_dirs.extend(self.input_dirs.iter().cloned());
+        input_dirs.extend(self.asset_dirs());
+        // Beamer looks for themes on the input path
+        let themes: P<dirs::ThemesDir> = self.dirs.src.clone().extend(());
+        if themes.exists() {
+            input_dirs.push(themes.to_path_buf());
+        }
+        // Test documents can input their neighbours in the tests directory
+        if let Some(tests) = &self.dirs.tests {
+            input_dirs.push(tests.to_path_buf());
+        }
         let eng = self
-            .engine_builder()
+            .engine_builder()?
             // Yes, these are extraneous clones. I want to be sure first what
             // lifetime the `Engine` should really have.
             .with_src_dir(self.dirs.src.clone())
             .with_build_dir(self.dirs.build.clone())
             .with_verbosity(&self.verbosity)
             .with_draft_mode(self.project_settings.draft_mode.unwrap_or_default())?
+            .with_halt_on_error(self.halt_on_error)?
             .with_synctex(self.project_settings.synctex.unwrap_or_default())?
             .with_shell_escape(self.project_settings.shell_escape)?
-            .with_dependencies(&crate::dependencies::get_dependency_paths(
+            .with_jobname(self.jobname.clone())?
+            .with_env(env)
+            .with_dependencies(&input_dirs)
+            .with_texmf_trees(&crate::dependencies::get_texmf_trees(
+                &deps_dir,
                 &self.dependencies,
             ))
-            .finish();
+            .finish()?;
         Ok(eng)
     }
 
-    fn into_ctx(self) -> BuildCtx<'a> {
+    fn get_bib_engine(&self, env: &EnvVars) -> Option<engines::Engine> {
+        if self.project_settings.skip_bib.unwrap_or_default() {
+            return None;
+        }
+        let bib_engine = self.system_settings.bib_engine?;
+        Some(engines::bib_engine(
+            self.conf,
+            bib_engine,
+            &self.dirs.build,
+            &self.jobname,
+            env,
+            &self.bibliography_dirs(),
+        ))
+    }
+
+    /// The project's figures and data directories, if it has them, for the
+    /// engine to search
+    fn asset_dirs(&self) -> Vec<std::path::PathBuf> {
+        let figures: P<dirs::FiguresDir> = self.dirs.root.clone().extend(());
+        let data: P<dirs::DataDir> = self.dirs.root.clone().extend(());
+        [figures.to_path_buf(), data.to_path_buf()]
+            .into_iter()
+            .filter(|dir| dir.is_dir())
+            .collect()
+    }
+
+    /// The directories containing the bibliography files, and the project's
+    /// bib directory if it has one, for the bibliography processor to search
+    fn bibliography_dirs(&self) -> Vec<std::path::PathBuf> {
+        let mut dirs: Vec<std::path::PathBuf> = Vec::new();
+        let bib: P<dirs::BibDir> = self.dirs.root.clone().extend(());
+        if bib.is_dir() {
+            dirs.push(bib.to_path_buf());
+        }
+        for dir in self.bibliography.iter().filter_map(|bib| bib.parent()) {
+            if !dirs.iter().any(|seen| seen == dir) {
+                dirs.push(dir.to_path_buf());
+            }
+        }
+        dirs
+    }
+
+    /// The entry in the shared build cache for this build, if caching is
+    /// enabled
+    fn cache_entry(&self) -> Result<Option<cache::CacheEntry>> {
+        if !self.project_settings.cache.unwrap_or_default() {
+            return Ok(None);
+        }
+        let exec = self.conf.choose_program(
+            self.system_settings.tex_engine,
+            self.system_settings.tex_format,
+        );
+        let mut fp = cache::Fingerprinter::new();
+        fp.add_str("engine", &crate::probe::program_version(exec.as_ref())?);
+        fp.add_str("profile", self.profile_name.as_ref());
+        fp.add_str("main-file", &self.main_file);
+        fp.add_str("jobname", &self.jobname);
+        fp.add_str("system-settings", &format!("{:?}", self.system_settings));
+        fp.add_str("project-settings", &format!("{:?}", self.project_settings));
+        fp.add_str("env", &format!("{:?}", self.env));
+        fp.add_str("vars", &format!("{:?}", self.vars));
+        fp.add_str("metadata", &format!("{:?}", self.metadata));
+        fp.add_str("git", &format!("{:?}", self.git));
+        fp.add_str("build-date", &self.build_date.to_string());
+        fp.add_tree("src", &self.dirs.src)?;
+        if let Some(tests) = &self.dirs.tests {
+            fp.add_tree("tests", tests)?;
+        }
+        let deps_dir = self.deps_dir();
+        for dep_path in crate::dependencies::get_dependency_paths(
+            &self.dirs.root,
+            &deps_dir,
+            &self.dependencies,
+        )? {
+            fp.add_tree("dependency", &dep_path)?;
+        }
+        for texmf in crate::dependencies::get_texmf_trees(&deps_dir, &self.dependencies) {
+            fp.add_tree("dependency", &texmf)?;
+        }
+        for input_dir in &self.input_dirs {
+            fp.add_tree("dependency-output", input_dir)?;
+        }
+        for asset_dir in self.asset_dirs() {
+            fp.add_tree("assets", &asset_dir)?;
+        }
+        let bib: P<dirs::BibDir> = self.dirs.root.clone().extend(());
+        if bib.is_dir() {
+            fp.add_tree("assets", &bib)?;
+        }
+        for bibliography in &self.bibliography {
+            // A document's bibliography is named as it is in the manifest,
+            // and a global one, which may be anywhere, by its file name
+            let name = bibliography
+                .strip_prefix(&self.dirs.root)
+                .ok()
+                .or_else(|| bibliography.file_name().map(std::path::Path::new))
+                .unwrap_or(bibliography);
+            fp.add_str("bibliography-name", &name.to_string_lossy());
+            fp.add_file("bibliography", bibliography)?;
+        }
+        let entry = cache::CacheEntry::new(dirs::BuildCacheDir::global()?, fp.finish());
+        Ok(Some(entry))
+    }
+
+    fn into_ctx(self) -> Result<BuildCtx<'a>> {
+        let cache = self.cache_entry()?;
         // FIXME this should happen *at build time*, right?
-        let largo_vars = LargoVars::from_build_settings(&self);
-        BuildCtx {
+        let largo_vars = LargoVars::from_build_settings(&self)?;
+        Ok(BuildCtx {
             root_dir: self.dirs.root,
             src_dir: self.dirs.src,
             target_dir: self.dirs.target,
             build_dir: self.dirs.build,
+            output_dir: self.dirs.output,
             profile_name: self.profile_name,
             project_name: self.project_name,
+            main_file: self.main_file,
+            jobname: self.jobname,
             vars: largo_vars,
+            fixed_build_date: self.project_settings.build_date.is_some()
+                || self.env.get("SOURCE_DATE_EPOCH").is_some(),
+            keep_intermediates: self.project_settings.keep_intermediates.unwrap_or(true),
+            synctex: self.project_settings.synctex.unwrap_or_default(),
+            timings: self.timings,
+            cache,
             verbosity: self.verbosity,
-        }
+        })
+    }
+
+    fn get_docstrip(&self, env: &EnvVars) -> Option<crate::Command> {
+        let ins_file = self.ins_file.as_ref()?;
+        Some(engines::docstrip(
+            self.conf,
+            ins_file,
+            &self.dirs.src,
+            &self.dirs.build,
+            env,
+        ))
+    }
+
+    /// The environment of the build's commands: the Largo vars, as `LARGO_...`
+    /// variables, and the project's own, which take precedence
+    fn child_env(&self) -> Result<EnvVars> {
+        let mut env = self.env.clone();
+        env.merge_left(LargoVars::from_build_settings(self)?.to_env());
+        Ok(env)
     }
 
     fn into_runner(self) -> Result<BuildRunner<'a>> {
-        let engine = self.get_engine()?;
-        let ctx = self.into_ctx();
-        Ok(BuildRunner { ctx, engine })
+        let env = self.child_env()?;
+        let engine = self.get_engine(&env)?;
+        let bib_engine = self.get_bib_engine(&env);
+        let docstrip = self.get_docstrip(&env);
+        let ctx = self.into_ctx()?;
+        Ok(BuildRunner {
+            ctx,
+            engine,
+            bib_engine,
+            docstrip,
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct BuildCtx<'a> {
     root_dir: P<dirs::RootDir>,
-    #[allow(unused)]
     src_dir: P<dirs::SrcDir>,
     target_dir: P<dirs::TargetDir>,
     build_dir: P<dirs::BuildDir>,
+    /// Where the finished document is copied to, by `output-dir`
+    output_dir: Option<P<dirs::OutputDir>>,
     profile_name: ProfileName<'a>,
-    project_name: &'a str,
+    project_name: String,
+    main_file: String,
+    jobname: String,
     vars: LargoVars<'a>,
-    #[allow(unused)]
+    /// Whether the build date is fixed, by `build-date` or by
+    /// `SOURCE_DATE_EPOCH` in `[env]`, rather than the day of the build
+    fixed_build_date: bool,
+    /// Whether to leave intermediate files in the build directory
+    keep_intermediates: bool,
+    /// Whether the engine produces SyncTeX data
+    synctex: bool,
+    /// Whether to report how long each phase of the build took
+    timings: bool,
+    /// Where this build lives in the shared build cache, if caching is enabled
+    cache: Option<cache::CacheEntry>,
     verbosity: Verbosity,
 }
 
+impl<'a> BuildCtx<'a> {
+    /// Post-build steps, run after a successful build
+    fn finish(&self) -> Result<()> {
+        if self.synctex {
+            let synctex_file = format!("{}.synctex.gz", self.jobname);
+            let synctex_file: P<dirs::BuildFile> =
+                self.build_dir.clone().extend(synctex_file.as_str());
+            let main_file: P<dirs::SrcFile> = self.src_dir.clone().extend(self.main_file.as_str());
+            crate::synctex::relocate(&synctex_file, &self.build_dir, &main_file)?;
+        }
+        if !self.keep_intermediates {
+            // SyncTeX data belongs with the PDF if we asked for it
+            let extensions: Vec<&str> = INTERMEDIATE_EXTENSIONS
+                .iter()
+                .copied()
+                .filter(|ext| !(self.synctex && *ext == "synctex.gz"))
+                .collect();
+            dirs::BuildDir::try_prune(&self.build_dir, &self.jobname, &extensions)?;
+        }
+        if let Some(cache) = &self.cache {
+            cache.try_store(&self.build_dir, &self.jobname, UNCACHED_EXTENSIONS)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the finished document, and its SyncTeX data if there is any,
+    /// into the output directory. Nothing else is put there: the directory
+    /// is the user's.
+    fn export(&self) -> Result<()> {
+        let Some(output_dir) = &self.output_dir else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(output_dir)?;
+        let mut files = vec![self.artifact_name()];
+        if self.synctex {
+            files.push(format!("{}.synctex.gz", self.jobname));
+        }
+        for file in files {
+            let built: P<dirs::BuildFile> = self.build_dir.clone().extend(file.as_str());
+            let exported: P<dirs::ExportedFile> = output_dir.clone().extend(file.as_str());
+            std::fs::copy(&built, &exported).map_err(|err| {
+                anyhow!(
+                    "cannot copy `{}` to `{}`: {}",
+                    file,
+                    output_dir.display(),
+                    err
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    fn artifact_name(&self) -> String {
+        format!("{}.pdf", self.jobname)
+    }
+
+    /// Restore the outputs of an identical earlier build from the cache,
+    /// returning whether there was one.
+    fn try_restore(&self) -> Result<bool> {
+        match &self.cache {
+            Some(cache) => cache.try_restore(&self.build_dir, &self.artifact_name()),
+            None => Ok(false),
+        }
+    }
+}
+
 // FIXME: this will incur a lot of unnecessary clones. Figure out the lifetimes
 // and fix it!
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
 pub enum LargoInfo<'c> {
     Compiling {
         project: &'c str,
@@ -216,13 +689,70 @@ pub enum LargoInfo<'c> {
     Running {
         exec: &'static str,
     },
+    /// Part of a dependency has been downloaded, out of `total` bytes if the
+    /// mirror said how many there are
+    Downloading {
+        name: &'c str,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    /// A dependency has been downloaded
+    Downloaded {
+        name: &'c str,
+        bytes: u64,
+    },
+    /// The outputs were restored from the build cache
+    Fresh {
+        fingerprint: &'c str,
+    },
+    PhaseTimings {
+        timings: Vec<PhaseTiming>,
+    },
     Finished {
         profile_name: ProfileName<'c>,
         duration: std::time::Duration,
     },
+    /// The references and citations the document was left with undefined,
+    /// after every pass
+    Undefined {
+        references: Vec<String>,
+        citations: Vec<String>,
+    },
 }
 
-#[derive(Debug)]
+/// A step of the build that is timed separately
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildPhase {
+    /// Writing the start file and creating directories
+    Prepare,
+    /// A run of the TeX engine
+    Engine { pass: usize },
+    /// A run of the bibliography processor
+    Bibliography,
+    /// Post-processing of the outputs, such as pruning intermediate files
+    PostProcess,
+}
+
+impl std::fmt::Display for BuildPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildPhase::Prepare => write!(f, "prepare"),
+            BuildPhase::Engine { pass } => write!(f, "engine (pass {})", pass),
+            BuildPhase::Bibliography => write!(f, "bibliography"),
+            BuildPhase::PostProcess => write!(f, "post-process"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PhaseTiming {
+    pub phase: BuildPhase,
+    pub duration: std::time::Duration,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
 pub enum BuildInfo<'c> {
     LargoInfo(LargoInfo<'c>),
     EngineInfo(crate::engines::EngineInfo),
@@ -244,21 +774,71 @@ impl<'c> From<crate::engines::EngineInfo> for BuildInfo<'c> {
 pub struct BuildRunner<'c> {
     ctx: BuildCtx<'c>,
     engine: engines::Engine,
+    /// The bibliography processor, unless the project has none or skips it
+    bib_engine: Option<engines::Engine>,
+    /// Generates the project's package from its `.dtx` source, if it's
+    /// written in docstrip's format
+    docstrip: Option<crate::Command>,
 }
 
 enum BuildState {
     Init,
     StartEngine,
-    EngineRunning(crate::engines::EngineOutput),
+    EngineRunning(Box<crate::engines::EngineOutput>),
+    StartBib,
+    BibRunning(Box<crate::engines::EngineOutput>),
+    Fresh,
+    PostProcess,
     Finished,
+    Summary,
     Exit,
 }
 
 pub struct BuildOutput<'b> {
     ctx: &'b BuildCtx<'b>,
     engine: &'b mut engines::Engine,
+    bib_engine: Option<&'b mut engines::Engine>,
     state: BuildState,
     start: std::time::Instant,
+    /// Whether the engine has reported any errors
+    failed: bool,
+    /// Whether the outputs were restored from the build cache
+    fresh: bool,
+    /// How many warnings the current engine pass has reported
+    warnings: usize,
+    /// The references the current engine pass has found undefined, each once
+    undefined_references: Vec<String>,
+    /// The citations the current engine pass has found undefined, each once
+    undefined_citations: Vec<String>,
+    /// How many times the engine has been started
+    pass: usize,
+    /// When the current phase started
+    phase_start: std::time::Instant,
+    timings: Vec<PhaseTiming>,
+}
+
+impl<'b> BuildOutput<'b> {
+    /// What to do after an engine pass has finished
+    fn after_engine_pass(&self) -> BuildState {
+        if self.failed || self.bib_engine.is_none() {
+            BuildState::PostProcess
+        } else if self.pass == 1 {
+            BuildState::StartBib
+        } else if self.pass <= BIB_RERUNS {
+            BuildState::StartEngine
+        } else {
+            BuildState::PostProcess
+        }
+    }
+
+    fn end_phase(&mut self, phase: BuildPhase) {
+        let now = std::time::Instant::now();
+        self.timings.push(PhaseTiming {
+            phase,
+            duration: now - self.phase_start,
+        });
+        self.phase_start = now;
+    }
 }
 
 impl<'b> stream::Stream for BuildOutput<'b> {
@@ -273,76 +853,240 @@ impl<'b> stream::Stream for BuildOutput<'b> {
             BuildState::Init => {
                 let info = LargoInfo::Compiling {
                     project: &self.ctx.project_name,
-                    version: None,
+                    version: self.ctx.vars.metadata.version.as_deref(),
                     root: &self.ctx.root_dir,
                 }
                 .into();
-                self.state = BuildState::StartEngine;
+                self.state = if self.fresh {
+                    BuildState::Fresh
+                } else {
+                    BuildState::StartEngine
+                };
                 Poll::Ready(Some(Ok(info)))
             }
+            BuildState::Fresh => {
+                self.state = BuildState::PostProcess;
+                let fingerprint = self
+                    .ctx
+                    .cache
+                    .as_ref()
+                    .map(|cache| cache.fingerprint())
+                    .unwrap_or_default();
+                Poll::Ready(Some(Ok(LargoInfo::Fresh { fingerprint }.into())))
+            }
             BuildState::StartEngine => match self.engine.run() {
                 Result::Ok(engine_output) => {
-                    self.state = BuildState::EngineRunning(engine_output);
+                    self.pass += 1;
+                    // Like warnings, undefined references are counted afresh
+                    // in each pass, since a rerun may resolve them
+                    self.warnings = 0;
+                    self.undefined_references.clear();
+                    self.undefined_citations.clear();
+                    self.phase_start = std::time::Instant::now();
+                    self.state = BuildState::EngineRunning(Box::new(engine_output));
                     let info = LargoInfo::Running {
                         exec: "(TODO) tex engine",
                     }
                     .into();
                     Poll::Ready(Some(Ok(info)))
                 }
-                Result::Err(err) => Poll::Ready(Some(Err(err.into()))),
+                Result::Err(err) => Poll::Ready(Some(Err(err))),
             },
             BuildState::EngineRunning(ref mut engine_output) => {
                 match std::pin::Pin::new(engine_output).poll_next(cx) {
-                    Poll::Ready(Some(engine_info)) => Poll::Ready(Some(Ok(engine_info.into()))),
+                    Poll::Ready(Some(engine_info)) => {
+                        self.failed |= engine_info.is_error();
+                        if let engines::EngineInfo::Warning { msg, .. } = &engine_info {
+                            self.warnings += 1;
+                            if let Some((key, is_citation)) = crate::diagnostics::undefined_key(msg)
+                            {
+                                let undefined = if is_citation {
+                                    &mut self.undefined_citations
+                                } else {
+                                    &mut self.undefined_references
+                                };
+                                if !undefined.iter().any(|known| known == key) {
+                                    undefined.push(key.to_string());
+                                }
+                            }
+                        }
+                        if self.ctx.verbosity.forwards(&engine_info) {
+                            Poll::Ready(Some(Ok(engine_info.into())))
+                        } else {
+                            self.poll_next(cx)
+                        }
+                    }
                     Poll::Ready(None) => {
-                        self.state = BuildState::Finished;
+                        let pass = self.pass;
+                        self.end_phase(BuildPhase::Engine { pass });
+                        self.state = self.after_engine_pass();
                         self.poll_next(cx)
                     }
-                    Poll::Pending => {
-                        cx.waker().wake_by_ref();
-                        Poll::Pending
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+            BuildState::StartBib => {
+                let bib_engine = self
+                    .bib_engine
+                    .as_mut()
+                    .expect("bibliography pass without a bibliography engine");
+                match bib_engine.run() {
+                    Result::Ok(bib_output) => {
+                        self.phase_start = std::time::Instant::now();
+                        self.state = BuildState::BibRunning(Box::new(bib_output));
+                        let info = LargoInfo::Running { exec: "biber" }.into();
+                        Poll::Ready(Some(Ok(info)))
                     }
+                    Result::Err(err) => Poll::Ready(Some(Err(err))),
+                }
+            }
+            BuildState::BibRunning(ref mut bib_output) => {
+                match std::pin::Pin::new(bib_output).poll_next(cx) {
+                    Poll::Ready(Some(bib_info)) => {
+                        self.failed |= bib_info.is_error();
+                        if self.ctx.verbosity.forwards(&bib_info) {
+                            Poll::Ready(Some(Ok(bib_info.into())))
+                        } else {
+                            self.poll_next(cx)
+                        }
+                    }
+                    Poll::Ready(None) => {
+                        self.end_phase(BuildPhase::Bibliography);
+                        self.state = if self.failed {
+                            BuildState::PostProcess
+                        } else {
+                            BuildState::StartEngine
+                        };
+                        self.poll_next(cx)
+                    }
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+            BuildState::PostProcess => {
+                self.state = BuildState::Finished;
+                if !self.failed && !self.fresh {
+                    let res = self.ctx.finish();
+                    self.end_phase(BuildPhase::PostProcess);
+                    if let Err(err) = res {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+                // Outputs restored from the cache are exported all the same
+                if !self.failed {
+                    if let Err(err) = self.ctx.export() {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+                if self.ctx.timings {
+                    let timings = std::mem::take(&mut self.timings);
+                    Poll::Ready(Some(Ok(LargoInfo::PhaseTimings { timings }.into())))
+                } else {
+                    self.poll_next(cx)
                 }
             }
             BuildState::Finished => {
-                self.state = BuildState::Exit;
+                self.state = BuildState::Summary;
                 let duration = std::time::Instant::now() - self.start;
                 Poll::Ready(Some(Ok(BuildInfo::LargoInfo(LargoInfo::Finished {
-                    profile_name: self.ctx.profile_name,
+                    profile_name: self.ctx.profile_name.clone(),
                     duration,
                 }))))
             }
+            BuildState::Summary => {
+                self.state = BuildState::Exit;
+                if self.undefined_references.is_empty() && self.undefined_citations.is_empty() {
+                    return Poll::Ready(None);
+                }
+                let info = LargoInfo::Undefined {
+                    references: std::mem::take(&mut self.undefined_references),
+                    citations: std::mem::take(&mut self.undefined_citations),
+                };
+                Poll::Ready(Some(Ok(info.into())))
+            }
             BuildState::Exit => Poll::Ready(None),
         }
     }
 }
 
-impl<'c> BuildRunner<'c> {
-    // FIXME: Just do this with macros.
-    fn write_largo_vars<W: std::io::Write>(&self, w: &mut W) -> Result<()> {
-        let vars = &self.ctx.vars;
-        write!(w, r#"\def\LargoProfile{{{}}}"#, vars.profile)?;
-        write!(
-            w,
-            r#"\def\LargoOutputDirectory{{{}}}"#,
-            vars.output_directory.display()
-        )?;
-        if let Some(bib) = &vars.bibliography {
-            write!(w, r#"\def\LargoBibliography{{{}}}"#, bib)?;
+impl<'b> BuildOutput<'b> {
+    /// Whether the engine has reported any errors so far
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// How many warnings the last engine pass reported. Earlier passes are
+    /// disregarded, since reruns resolve many of their warnings.
+    pub fn warnings(&self) -> usize {
+        self.warnings
+    }
+
+    /// Stop the build, killing the engine if it's running and removing any
+    /// outputs it may have left half-written.
+    pub async fn cancel(&mut self) -> Result<()> {
+        let state = std::mem::replace(&mut self.state, BuildState::Exit);
+        match state {
+            BuildState::EngineRunning(mut output) | BuildState::BibRunning(mut output) => {
+                output.cancel().await?;
+                dirs::BuildDir::try_prune(
+                    &self.ctx.build_dir,
+                    &self.ctx.jobname,
+                    PARTIAL_OUTPUT_EXTENSIONS,
+                )?;
+            }
+            _ => (),
         }
         Ok(())
     }
+}
+
+impl<'c> BuildRunner<'c> {
+    /// Whether there's a document to build. A package project may have none,
+    /// only the packages in its source directory.
+    pub fn has_main_file(&self) -> bool {
+        let main_file: P<dirs::SrcFile> =
+            self.ctx.src_dir.clone().extend(self.ctx.main_file.as_str());
+        main_file.exists()
+    }
+
+    /// The directory the build's outputs are written to
+    pub fn build_dir(&self) -> &P<dirs::BuildDir> {
+        &self.ctx.build_dir
+    }
+
+    /// The PDF produced by the build
+    pub fn artifact(&self) -> P<dirs::ArtifactFile> {
+        let file_name = self.ctx.artifact_name();
+        self.ctx.build_dir.clone().extend(file_name.as_str())
+    }
 
+    // FIXME: Just do this with macros.
+    /// The start file loads the variables' package before anything else, so
+    /// that even the document class can use them
     fn write_start_file<W: std::io::Write>(&self, w: &mut W) -> Result<()> {
-        self.write_largo_vars(w)?;
-        write!(w, r"\input{{{}}}", dirs::MAIN_FILE)?;
+        write!(w, r"\RequirePackage{{{}}}", dirs::LARGO_VARS_PACKAGE)?;
+        write!(w, r"\input{{{}}}", self.ctx.main_file)?;
         Ok(())
     }
 
     fn prepare_build_environment(&self) -> Result<()> {
-        // FIXME: ignore error if `CACHEDIR.TAG` already exists
-        let _ = crate::dirs::try_create_target_dir(&self.ctx.target_dir);
+        crate::dirs::try_create_target_dir(&self.ctx.target_dir)?;
         std::fs::create_dir_all(&self.ctx.build_dir)?;
+        // An `\include`d file's `.aux` is written to the same place in the
+        // build directory as the file has in the source directory, which the
+        // engine doesn't create itself
+        for source in crate::scan::collect_sources(&self.ctx.src_dir, &self.ctx.main_file)? {
+            let Some(dir) = source.path.parent() else {
+                continue;
+            };
+            let is_inside = dir
+                .components()
+                .all(|part| matches!(part, std::path::Component::Normal(_)));
+            if is_inside && !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(self.ctx.build_dir.join(dir))?;
+            }
+        }
+        let vars_file: P<dirs::LargoVarsFile> = self.ctx.build_dir.clone().extend(());
+        std::fs::write(&vars_file, self.ctx.vars.to_package()?)?;
         // Create the `_start.tex` file
         let start_file: P<dirs::StartFile> = self.ctx.build_dir.clone().extend(());
         let mut f = std::fs::File::create(&start_file)?;
@@ -350,13 +1094,39 @@ impl<'c> BuildRunner<'c> {
         Ok(())
     }
 
-    pub async fn run<'a>(&'a mut self) -> Result<BuildOutput> {
-        self.prepare_build_environment()?;
+    pub async fn run<'a>(&'a mut self) -> Result<BuildOutput<'a>> {
+        let start = std::time::Instant::now();
+        let (prepared, duration) = crate::util::timed(|| {
+            self.prepare_build_environment()?;
+            self.ctx.try_restore()
+        });
+        let fresh = prepared?;
+        if let (Some(docstrip), false) = (&mut self.docstrip, fresh) {
+            let output = docstrip.output().await?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "docstrip failed to generate the package:\n{}",
+                    String::from_utf8_lossy(&output.stdout)
+                ));
+            }
+        }
         Ok(BuildOutput {
             ctx: &self.ctx,
             engine: &mut self.engine,
+            bib_engine: self.bib_engine.as_mut(),
             state: BuildState::Init,
-            start: std::time::Instant::now(),
+            start,
+            failed: false,
+            fresh,
+            warnings: 0,
+            undefined_references: Vec::new(),
+            undefined_citations: Vec::new(),
+            pass: 0,
+            phase_start: std::time::Instant::now(),
+            timings: vec![PhaseTiming {
+                phase: BuildPhase::Prepare,
+                duration,
+            }],
         })
     }
 }
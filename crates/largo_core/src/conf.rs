@@ -1,12 +1,19 @@
 //! Tool configuration
 
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 
 use merge::Merge;
 
-use crate::dirs::{self, ContentString as S};
+use crate::dirs;
 use crate::Result;
+use anyhow::anyhow;
+
+pub mod global;
+pub mod migrate;
+mod parse;
+mod version;
 
 pub const DEV_PROFILE: &str = "dev";
 pub const RELEASE_PROFILE: &str = "release";
@@ -34,7 +41,27 @@ pub enum TexEngine {
     Luatex,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, Merge)]
+impl std::fmt::Display for TexFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TexFormat::Tex => write!(f, "tex"),
+            TexFormat::Latex => write!(f, "latex"),
+        }
+    }
+}
+
+impl std::fmt::Display for TexEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TexEngine::Tex => write!(f, "tex"),
+            TexEngine::Pdftex => write!(f, "pdftex"),
+            TexEngine::Xetex => write!(f, "xetex"),
+            TexEngine::Luatex => write!(f, "luatex"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Merge)]
 #[merge(replace)]
 #[serde(rename_all = "lowercase")]
 pub enum OutputFormat {
@@ -51,18 +78,18 @@ pub enum BibEngine {
     Biber,
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize, Merge)]
-pub struct Executable<'c>(&'c str);
+#[derive(Debug, Clone, Deserialize, Serialize, Merge)]
+pub struct Executable<'c>(Cow<'c, str>);
 
 impl<'c> AsRef<str> for Executable<'c> {
     fn as_ref(&self) -> &str {
-        self.0
+        &self.0
     }
 }
 
 impl<'c> AsRef<std::ffi::OsStr> for Executable<'c> {
     fn as_ref(&self) -> &std::ffi::OsStr {
-        self.0.as_ref()
+        std::ffi::OsStr::new(&*self.0)
     }
 }
 
@@ -72,16 +99,25 @@ macro_rules! executable_config {
         #[serde(default)]
         pub struct ExecutableConfig<'c> {
             $(
-                #[serde(borrow)]
                 pub $exec: Executable<'c>,
             )*
         }
 
+        impl<'c> ExecutableConfig<'c> {
+            /// The name of each program in the configuration
+            pub const NAMES: &'static [&'static str] = &[$(stringify!($exec)),*];
+
+            /// Each program, by its name in the configuration
+            pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Executable<'c>)> {
+                [$((stringify!($exec), &self.$exec)),*].into_iter()
+            }
+        }
+
         impl<'c> Default for ExecutableConfig<'c> {
             fn default() -> Self {
                 Self {
                     $(
-                        $exec: Executable(stringify!($exec)),
+                        $exec: Executable(Cow::Borrowed(stringify!($exec))),
                     )*
                 }
             }
@@ -94,67 +130,266 @@ executable_config![tex, latex, pdftex, pdflatex, xetex, xelatex, luatex, lualate
 #[derive(Debug, Default, Deserialize, Serialize, Merge)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct BuildConfig<'c> {
-    #[serde(flatten, borrow)]
+    #[serde(flatten)]
     pub execs: ExecutableConfig<'c>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Merge)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct BibConfig<'c> {
-    #[serde(borrow)]
-    pub bibliography: Option<&'c str>,
+    #[serde(skip_serializing_if = "Bibliographies::is_empty")]
+    pub bibliography: Bibliographies<'c>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, Merge)]
+/// One or more bibliography files, written as a single path or a list of them
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Merge)]
 #[merge(replace)]
-#[serde(rename_all = "kebab-case")]
+#[serde(from = "OneOrMany<'c>")]
+pub struct Bibliographies<'c>(Vec<Cow<'c, str>>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<'c> {
+    One(Cow<'c, str>),
+    Many(Vec<Cow<'c, str>>),
+}
+
+impl<'c> From<OneOrMany<'c>> for Bibliographies<'c> {
+    fn from(paths: OneOrMany<'c>) -> Self {
+        match paths {
+            OneOrMany::One(path) => Self(vec![path]),
+            OneOrMany::Many(paths) => Self(paths),
+        }
+    }
+}
+
+impl<'c> Bibliographies<'c> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Make relative paths relative to `dir`, the directory of the file they
+    /// were written in
+    fn resolve(&mut self, dir: &std::path::Path) {
+        for path in &mut self.0 {
+            if std::path::Path::new(path.as_ref()).is_relative() {
+                *path = Cow::Owned(dir.join(path.as_ref()).to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|path| path.as_ref())
+    }
+}
+
+/// When to color terminal output. This is written either as one of `auto`,
+/// `always` or `never`, or as a bare boolean.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, Merge)]
+#[merge(replace)]
+#[serde(try_from = "TermColorRepr", into = "TermColorRepr")]
 pub enum TermColor {
+    /// Always, or never
     Bool(bool),
+    /// Only when writing to a terminal
     #[default]
     Auto,
+    Always,
+    Never,
+}
+
+impl std::str::FromStr for TermColor {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(TermColor::Auto),
+            "always" => Ok(TermColor::Always),
+            "never" => Ok(TermColor::Never),
+            "true" => Ok(TermColor::Bool(true)),
+            "false" => Ok(TermColor::Bool(false)),
+            _ => Err(anyhow!(
+                "invalid color choice `{}`; expected `auto`, `always`, or `never`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum TermColorRepr {
+    Bool(bool),
+    Name(String),
+}
+
+impl TryFrom<TermColorRepr> for TermColor {
+    type Error = crate::Error;
+
+    fn try_from(repr: TermColorRepr) -> Result<Self> {
+        match repr {
+            TermColorRepr::Bool(b) => Ok(TermColor::Bool(b)),
+            TermColorRepr::Name(s) => s.parse(),
+        }
+    }
+}
+
+impl From<TermColor> for TermColorRepr {
+    fn from(color: TermColor) -> Self {
+        match color {
+            TermColor::Bool(b) => TermColorRepr::Bool(b),
+            TermColor::Auto => TermColorRepr::Name("auto".to_string()),
+            TermColor::Always => TermColorRepr::Name("always".to_string()),
+            TermColor::Never => TermColorRepr::Name("never".to_string()),
+        }
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Merge)]
 #[merge(replace)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct TermConfig {
-    quiet: bool,
-    verbose: bool,
-    color: TermColor,
+    /// Print only errors
+    pub quiet: bool,
+    /// Print all of the TeX engine's output
+    pub verbose: bool,
+    pub color: TermColor,
+}
+
+/// Where dependencies are fetched from
+#[derive(Debug, Default, Deserialize, Serialize, Merge)]
+#[merge(replace)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RegistryConfig<'c> {
+    /// CTAN mirrors, tried in order until one answers. If this is empty,
+    /// `https://www.ctan.org/` is used.
+    pub mirrors: Vec<Cow<'c, str>>,
+    /// How long to wait for a mirror before trying the next one, in seconds
+    pub timeout: Option<u64>,
+    /// How many times to retry a mirror that fails, before trying the next
+    pub retries: Option<u32>,
+    /// How many dependencies to download at once
+    pub jobs: Option<usize>,
+    /// How long package metadata from CTAN is kept before it's fetched
+    /// again, in seconds
+    pub metadata_ttl: Option<u64>,
+    /// The proxy to use for every request, like `http://proxy.example.com:8080`.
+    /// Without one, the `HTTP_PROXY` and `HTTPS_PROXY` variables are used.
+    pub proxy: Option<Cow<'c, str>>,
+    /// A PEM file of certificates to trust, besides the system's, for proxies
+    /// and mirrors with their own certificate authority
+    pub ca_bundle: Option<Cow<'c, std::path::Path>>,
+    /// Never use the network: dependencies must already be in the lockfile
+    pub offline: bool,
+}
+
+/// How new projects start
+#[derive(Debug, Default, Deserialize, Serialize, Merge)]
+#[merge(replace)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct InitConfig<'c> {
+    /// Packages that a new document loads in its preamble, and depends on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<DependencyName<'c>>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Merge)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct DocConfig<'c> {
-    reader: Option<&'c str>,
+    /// The program used to open built documents
+    pub reader: Option<ReaderConfig<'c>>,
+}
+
+/// A document reader: just the name of a program, or a table of its
+/// arguments, with variants for particular platforms
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ReaderConfig<'c> {
+    Program(Cow<'c, str>),
+    Table(Box<ReaderTable<'c>>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReaderTable<'c> {
+    /// The program, unless every platform has its own
+    pub command: Option<Cow<'c, str>>,
+    #[serde(default)]
+    pub args: Vec<Cow<'c, str>>,
+    #[serde(default)]
+    pub forward_args: Vec<Cow<'c, str>>,
+    pub linux: Option<ReaderCommand<'c>>,
+    pub macos: Option<ReaderCommand<'c>>,
+    pub windows: Option<ReaderCommand<'c>>,
+}
+
+/// How to run a document reader. In the arguments, `{pdf}` stands for the
+/// document, which is passed last if no argument mentions it. The
+/// `forward_args` are only passed when opening the document at a position in
+/// its source, given by `{tex}`, `{line}`, and `{column}`, for forward search.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReaderCommand<'c> {
+    pub command: Cow<'c, str>,
+    #[serde(default)]
+    pub args: Vec<Cow<'c, str>>,
+    #[serde(default)]
+    pub forward_args: Vec<Cow<'c, str>>,
+}
+
+impl<'c> ReaderConfig<'c> {
+    /// The reader for this platform, if one is configured. Unix platforms
+    /// other than macOS use the `linux` table.
+    pub fn command(&self) -> Option<ReaderCommand<'c>> {
+        match self {
+            ReaderConfig::Program(command) => Some(ReaderCommand {
+                command: command.clone(),
+                args: Vec::new(),
+                forward_args: Vec::new(),
+            }),
+            ReaderConfig::Table(table) => {
+                let platform = if cfg!(target_os = "macos") {
+                    &table.macos
+                } else if cfg!(target_family = "unix") {
+                    &table.linux
+                } else {
+                    &table.windows
+                };
+                match (platform, &table.command) {
+                    (Some(platform), _) => Some(platform.clone()),
+                    (None, Some(command)) => Some(ReaderCommand {
+                        command: command.clone(),
+                        args: table.args.clone(),
+                        forward_args: table.forward_args.clone(),
+                    }),
+                    (None, None) => None,
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Merge)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct LargoConfig<'c> {
-    #[serde(flatten, borrow)]
+    #[serde(flatten)]
     pub build: BuildConfig<'c>,
     /// The default profile selected if no other profile is chosen.
-    #[serde(borrow)]
     pub default_profile: ProfileName<'c>,
     /// The default TeX format
     pub default_tex_format: TexFormat,
     /// The default TeX engine
     pub default_tex_engine: TexEngine,
-    /// Global bibliography file
-    #[serde(borrow)]
+    /// Global bibliography files
     pub bib: BibConfig<'c>,
-    #[serde(borrow)]
     pub doc: DocConfig<'c>,
+    pub registry: RegistryConfig<'c>,
+    pub init: InitConfig<'c>,
     pub term: TermConfig,
+    pub alias: Aliases,
 }
 
 impl<'c> LargoConfig<'c> {
-    fn new(content: &'c S<dirs::LargoConfigFile>) -> Result<Self> {
-        let config = toml::from_str(content)?;
-        Ok(config)
-    }
-
     pub fn choose_program(&self, engine: TexEngine, format: TexFormat) -> &Executable<'c> {
         let execs = &self.build.execs;
         match (engine, format) {
@@ -170,86 +405,346 @@ impl<'c> LargoConfig<'c> {
     }
 }
 
-/// Get configuration in the current working directory
-pub fn with_config<T, F: FnOnce(&LargoConfig, Option<crate::conf::Project>) -> T>(
-    f: F,
-) -> Result<T> {
+/// The global configuration and, if there is one, the project's, owning their
+/// data rather than borrowing it from the files they were read from, so that
+/// they can be kept and passed between tasks for as long as they're needed.
+#[derive(Debug)]
+pub struct Config {
+    pub global: LargoConfig<'static>,
+    pub project: Option<Project<'static>>,
+    /// Problems with the configuration that aren't bad enough to stop for
+    pub warnings: Vec<String>,
+}
+
+/// Read the configuration for the project at `location`
+pub fn load(location: &dirs::ProjectLocation) -> Result<Config> {
     // Global config
-    let global_config_dir = dirs::LargoConfigDir::global_config()?;
-    let global_config_file = typedir::path!(global_config_dir => dirs::LargoConfigFile);
+    let global_config_file = dirs::LargoConfigFile::global()?;
     // TODO: shouldn't crash if you have no config file; instead, just give you
     // the default config.
-    let global_config_contents = dirs::LargoConfigFile::try_read(&global_config_file)?;
-    let global_config = LargoConfig::new(&global_config_contents)?;
+    let global_config_contents = dirs::ContentString::try_read(&global_config_file)?;
+    let parsed = parse::from_str(
+        &global_config_contents,
+        &global_config_file,
+        &parse::GLOBAL_SCHEMA,
+    )?;
+    let mut global: LargoConfig = parsed.config;
+    global
+        .bib
+        .bibliography
+        .resolve(&global_config_file.parent());
+    let mut warnings = parsed.unknown_keys;
 
     // Project configuration
-    let root = dirs::RootDir::find().ok();
-    if let Some(mut root) = root {
-        let project_config_file = typedir::pathref!(root => dirs::ProjectConfigFile);
-        let project_config_contents = dirs::ProjectConfigFile::try_read(&project_config_file)?;
-        let project_config = toml::from_str(&project_config_contents)?;
-        drop(project_config_file);
-        let project = Some(crate::conf::Project {
-            root,
-            config: project_config,
-        });
-        Ok(f(&global_config, project))
-    } else {
-        Ok(f(&global_config, None))
+    let project = match location.find_root()? {
+        Some(root) => {
+            let (project, project_warnings) = load_project(root)?;
+            warnings.extend(project_warnings);
+            Some(project)
+        }
+        None => None,
+    };
+    Ok(Config {
+        global,
+        project,
+        warnings,
+    })
+}
+
+/// Read the manifest of the project at `root`, with a warning for each key in
+/// it that wasn't understood
+pub fn load_project(
+    mut root: typedir::PathBuf<dirs::RootDir>,
+) -> Result<(Project<'static>, Vec<String>)> {
+    let project_config_file = typedir::pathref!(root => dirs::ProjectConfigFile);
+    let project_config_contents = dirs::ContentString::try_read(&project_config_file)?;
+    version::check_requirement(&project_config_contents)?;
+    let parsed: parse::Parsed<ProjectConfig> = parse::from_str(
+        &project_config_contents,
+        &project_config_file,
+        &parse::PROJECT_SCHEMA,
+    )?;
+    // Projects can insist on a manifest without typos, for CI
+    let deny = parsed.config.project.deny_unknown_keys.unwrap_or_default();
+    if deny && !parsed.unknown_keys.is_empty() {
+        return Err(anyhow!(parsed.unknown_keys.join("\n\n")));
     }
+    drop(project_config_file);
+    let project = Project {
+        root,
+        config: parsed.config,
+    };
+    Ok((project, parsed.unknown_keys))
 }
 
-#[derive(Debug)]
+/// Read the `[workspace]` table of the workspace at `root`, whose manifest
+/// needn't also be a project's
+pub fn load_workspace(
+    mut root: typedir::PathBuf<dirs::WorkspaceRootDir>,
+) -> Result<WorkspaceConfig<'static>> {
+    #[derive(Deserialize)]
+    struct WorkspaceManifest<'c> {
+        workspace: WorkspaceConfig<'c>,
+    }
+    let manifest_file = typedir::pathref!(root => dirs::ProjectConfigFile);
+    let contents = dirs::ContentString::try_read(&manifest_file)?;
+    let manifest: WorkspaceManifest = toml::from_str(&contents)
+        .map_err(|err| anyhow!("cannot read `{}`: {}", manifest_file.display(), err))?;
+    // Nothing is borrowed from the contents, which are about to go
+    Ok(WorkspaceConfig {
+        members: manifest
+            .workspace
+            .members
+            .into_iter()
+            .map(|member| Cow::Owned(member.into_owned()))
+            .collect(),
+    })
+}
+
+#[derive(Debug, Clone)]
 pub struct Project<'c> {
     pub root: typedir::PathBuf<dirs::RootDir>,
     pub config: ProjectConfig<'c>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ProjectConfig<'c> {
     pub project: ProjectConfigHead<'c>,
     pub package: Option<PackageConfig>,
     pub class: Option<ClassConfig>,
-    #[serde(rename = "profile", default, borrow)]
+    #[serde(rename = "profile", default)]
     pub profiles: Option<Profiles<'c>>,
+    #[serde(rename = "document", default, skip_serializing_if = "Vec::is_empty")]
+    pub documents: Vec<DocumentConfig<'c>>,
     #[serde(default)]
     pub dependencies: Dependencies<'c>,
+    /// Environment variables set for the engine, from the `[env]` table
+    #[serde(default, skip_serializing_if = "EnvVars::is_empty")]
+    pub env: EnvVars,
+    /// Variables defined for the document, from the `[vars]` table
+    #[serde(default, skip_serializing_if = "UserVars::is_empty")]
+    pub vars: UserVars,
+    #[serde(default, skip_serializing_if = "Aliases::is_empty")]
+    pub alias: Aliases,
+    pub workspace: Option<WorkspaceConfig<'c>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// A manifest's `[workspace]` table, which makes it the root of a workspace
+/// of projects beneath it
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkspaceConfig<'c> {
+    /// The directories of the workspace's projects, relative to its root
+    #[serde(default)]
+    pub members: Vec<Cow<'c, std::path::Path>>,
+}
+
+impl<'c> ProjectConfig<'c> {
+    /// The configuration of a new project, with nothing but a name and a
+    /// first version
+    pub fn new(name: &'c str) -> Self {
+        Self {
+            project: ProjectConfigHead {
+                name: Cow::Borrowed(name),
+                largo_version: None,
+                deny_unknown_keys: None,
+                main: None,
+                deps_layout: None,
+                metadata: ProjectMetadata {
+                    version: Some(Cow::Borrowed("0.1.0")),
+                    ..Default::default()
+                },
+                system_settings: SystemSettings::default(),
+                project_settings: ProjectSettings::default(),
+            },
+            package: None,
+            class: None,
+            profiles: None,
+            documents: Vec::new(),
+            dependencies: Dependencies::new(),
+            env: EnvVars::default(),
+            vars: UserVars::default(),
+            alias: Aliases::default(),
+            workspace: None,
+        }
+    }
+
+    /// The document with the given name or, without one, the first listed
+    /// document. `None` if the project has no `[[document]]` sections.
+    pub fn select_document(
+        &self,
+        name: Option<DocumentName<'_>>,
+    ) -> Result<Option<DocumentConfig<'c>>> {
+        match name {
+            Some(name) => self
+                .documents
+                .iter()
+                .find(|doc| doc.name.as_ref() == name.as_ref())
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| anyhow!("document `{}` not found", name)),
+            None => Ok(self.documents.first().cloned()),
+        }
+    }
+
+    /// Every profile available to the project: the standard ones, and any it
+    /// defines or overrides
+    pub fn all_profiles(&self) -> Profiles<'c> {
+        let mut profiles = self.profiles.clone().unwrap_or_default();
+        profiles.merge_left(Profiles::standard());
+        profiles
+    }
+
+    /// The settings in effect in a profile, which override the project's own
+    pub fn effective_profile(&self, profile: Profile) -> Profile {
+        let mut project_settings = self.project.project_settings.clone();
+        project_settings.merge_right(profile.project_settings);
+        let mut env = self.env.clone();
+        env.merge_right(profile.env);
+        let mut vars = self.vars.clone();
+        vars.merge_right(profile.vars);
+        Profile {
+            project_settings,
+            system_settings: profile.system_settings,
+            env,
+            vars,
+        }
+    }
+
+    /// The entry point of the selected document, relative to the source
+    /// directory
+    pub fn main_file(&self, name: Option<DocumentName<'_>>) -> Result<String> {
+        Ok(match self.select_document(name)? {
+            Some(doc) => doc.main_file(),
+            None => self.project.main_file().to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ProjectConfigHead<'c> {
-    pub name: &'c str,
+    pub name: Cow<'c, str>,
+    /// The versions of largo that can build the project, like `>=0.3`
+    pub largo_version: Option<Cow<'c, str>>,
+    /// Whether keys that largo doesn't understand are errors, rather than
+    /// warnings
+    pub deny_unknown_keys: Option<bool>,
+    /// The entry point in the source directory, for a project without
+    /// documents. Defaults to `main.tex`.
+    pub main: Option<Cow<'c, str>>,
+    /// Where CTAN dependencies are installed
+    pub deps_layout: Option<DepsLayout>,
+    #[serde(flatten)]
+    pub metadata: ProjectMetadata<'c>,
     #[serde(flatten)]
     pub project_settings: ProjectSettings,
     #[serde(flatten)]
     pub system_settings: SystemSettings,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+impl<'c> ProjectConfigHead<'c> {
+    /// The project's entry point, when it has no documents
+    pub fn main_file(&self) -> &str {
+        self.main.as_deref().unwrap_or(dirs::MAIN_FILE)
+    }
+}
+
+/// Where a project's CTAN dependencies are installed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DepsLayout {
+    /// In each profile's own `target/<profile>/deps`
+    #[default]
+    Profile,
+    /// In `target/deps`, so that switching profiles doesn't install
+    /// everything again
+    Shared,
+}
+
+/// Descriptive information about a project, which doesn't affect how it's
+/// built, though it's available to the document as macros
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProjectMetadata<'c> {
+    pub version: Option<Cow<'c, str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<Cow<'c, str>>,
+    pub description: Option<Cow<'c, str>>,
+    /// An SPDX license expression, like `CC-BY-4.0`
+    pub license: Option<Cow<'c, str>>,
+}
+
+/// One of several documents built from the same project
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DocumentConfig<'c> {
+    pub name: DocumentName<'c>,
+    /// The document's entry point in the source directory. Defaults to
+    /// `<name>.tex`.
+    pub main: Option<Cow<'c, str>>,
+    /// Bibliography files for this document, relative to the project root,
+    /// instead of the global ones
+    pub bibliography: Option<Bibliographies<'c>>,
+}
+
+impl<'c> DocumentConfig<'c> {
+    pub fn main_file(&self) -> String {
+        match &self.main {
+            Some(main) => main.to_string(),
+            None => format!("{}.tex", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct DocumentName<'c>(Cow<'c, str>);
+
+impl<'c> AsRef<str> for DocumentName<'c> {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<'c> std::fmt::Display for DocumentName<'c> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<'c> TryFrom<&'c str> for DocumentName<'c> {
+    type Error = crate::Error;
+
+    fn try_from(s: &'c str) -> std::result::Result<Self, Self::Error> {
+        Ok(Self(Cow::Borrowed(s)))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct PackageConfig {}
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ClassConfig {}
 
-#[derive(
-    Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, Deserialize, Serialize, Merge,
-)]
-#[serde(transparent)]
-pub struct ProfileName<'c>(&'c str);
+/// The name of a profile, which is also the name of its directory in
+/// `target`, so it can't be the name of one of the other directories there
+#[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Deserialize, Serialize, Merge)]
+#[serde(try_from = "Cow<'c, str>", into = "Cow<'c, str>")]
+pub struct ProfileName<'c>(Cow<'c, str>);
 
 impl<'c> Default for ProfileName<'c> {
     fn default() -> Self {
-        Self(crate::conf::DEV_PROFILE)
+        Self(Cow::Borrowed(crate::conf::DEV_PROFILE))
     }
 }
 
 impl<'c> AsRef<str> for ProfileName<'c> {
     fn as_ref(&self) -> &str {
-        self.0
+        &self.0
     }
 }
 
@@ -263,12 +758,47 @@ impl<'c> TryFrom<&'c str> for ProfileName<'c> {
     type Error = crate::Error;
 
     fn try_from(s: &'c str) -> std::result::Result<Self, Self::Error> {
+        Self::try_from(Cow::Borrowed(s))
+    }
+}
+
+impl<'c> TryFrom<Cow<'c, str>> for ProfileName<'c> {
+    type Error = crate::Error;
+
+    fn try_from(s: Cow<'c, str>) -> std::result::Result<Self, Self::Error> {
+        let reserved = match s.as_ref() {
+            dirs::DEPS_DIR => Some("shared dependencies"),
+            dirs::CHECK_DIR => Some("check builds"),
+            _ => None,
+        };
+        if let Some(used_for) = reserved {
+            return Err(anyhow!(
+                "`{}` can't be the name of a profile: `target/{}` is for {}",
+                s,
+                s,
+                used_for
+            ));
+        }
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if s.is_empty() || !s.chars().all(valid) {
+            return Err(anyhow!(
+                "invalid profile name `{}`: profile names may only contain letters, numbers, \
+                 `-` and `_`",
+                s
+            ));
+        }
         Ok(Self(s))
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, Merge)]
-pub struct Profiles<'c>(#[serde(borrow)] BTreeMap<ProfileName<'c>, Profile>);
+impl<'c> From<ProfileName<'c>> for Cow<'c, str> {
+    fn from(name: ProfileName<'c>) -> Self {
+        name.0
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Merge)]
+pub struct Profiles<'c>(BTreeMap<ProfileName<'c>, Profile>);
 
 impl<'c> Profiles<'c> {
     pub fn new() -> Profiles<'c> {
@@ -278,6 +808,10 @@ impl<'c> Profiles<'c> {
     pub fn select_profile(mut self, name: &ProfileName<'c>) -> Option<Profile> {
         self.0.remove(name)
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ProfileName<'c>, &Profile)> {
+        self.0.iter()
+    }
 }
 
 impl Profiles<'static> {
@@ -287,24 +821,49 @@ impl Profiles<'static> {
         let mut profiles = Profiles::new();
         let dev_profile = Profile::default();
         let release_profile = Profile::default();
-        profiles.0.insert(ProfileName(DEV_PROFILE), dev_profile);
         profiles
             .0
-            .insert(ProfileName(RELEASE_PROFILE), release_profile);
+            .insert(ProfileName(Cow::Borrowed(DEV_PROFILE)), dev_profile);
+        profiles
+            .0
+            .insert(ProfileName(Cow::Borrowed(RELEASE_PROFILE)), release_profile);
         profiles
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, Merge)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Merge)]
 #[serde(rename_all = "kebab-case")]
 pub struct Profile {
     #[serde(flatten)]
     pub project_settings: ProjectSettings,
+    #[serde(flatten)]
+    pub system_settings: ProfileSystemSettings,
+    /// Environment variables that override those of the project's `[env]`
+    /// table in this profile
+    #[serde(default, skip_serializing_if = "EnvVars::is_empty")]
+    pub env: EnvVars,
+    /// Variables that override those of the project's `[vars]` table in this
+    /// profile
+    #[serde(default, skip_serializing_if = "UserVars::is_empty")]
+    pub vars: UserVars,
+}
+
+impl Profile {
+    /// Each setting that has a value, as a dotted key and a TOML value
+    pub fn settings(&self) -> Result<Vec<(String, String)>> {
+        let mut settings = Vec::new();
+        if let toml::Value::Table(table) = toml::Value::try_from(self)? {
+            for (key, value) in table {
+                global::flatten(key, value, &mut settings);
+            }
+        }
+        Ok(settings)
+    }
 }
 
 /// Which TeX system components to use: the TeX format, TeX engine, bibliography
 /// engine, and so on.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct SystemSettings {
     pub tex_format: TexFormat,
@@ -312,8 +871,33 @@ pub struct SystemSettings {
     pub bib_engine: Option<BibEngine>,
 }
 
+/// Overrides of the project's `SystemSettings` in a profile, so that, for
+/// example, a profile can build with a different engine.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Merge)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfileSystemSettings {
+    pub tex_format: Option<TexFormat>,
+    pub tex_engine: Option<TexEngine>,
+    pub bib_engine: Option<BibEngine>,
+}
+
+impl ProfileSystemSettings {
+    /// Apply these overrides to the project's settings
+    pub fn apply(self, settings: &mut SystemSettings) {
+        if let Some(tex_format) = self.tex_format {
+            settings.tex_format = tex_format;
+        }
+        if let Some(tex_engine) = self.tex_engine {
+            settings.tex_engine = tex_engine;
+        }
+        if let Some(bib_engine) = self.bib_engine {
+            settings.bib_engine = Some(bib_engine);
+        }
+    }
+}
+
 /// Project-specific configuration such as shell-escape and synctex.
-#[derive(Debug, Default, Deserialize, Serialize, Merge)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Merge)]
 #[serde(rename_all = "kebab-case")]
 pub struct ProjectSettings {
     pub output_format: Option<OutputFormat>,
@@ -325,15 +909,84 @@ pub struct ProjectSettings {
     pub synctex: Option<bool>,
     /// Whether to compile in draft mode (omit images, etc.)
     pub draft_mode: Option<bool>,
+    /// Whether to keep intermediate files (`.aux`, `.toc`, etc.) in the build
+    /// directory after a successful build. Defaults to `true`.
+    pub keep_intermediates: Option<bool>,
+    /// A directory beneath the project root, relative to it, to copy the
+    /// finished document into. It's still built in `target/<profile>/build`.
+    pub output_dir: Option<std::path::PathBuf>,
+    /// Whether to skip the bibliography pass and the engine reruns that
+    /// follow it, for faster builds.
+    pub skip_bib: Option<bool>,
+    /// Whether to reuse the outputs of an identical earlier build, from any
+    /// working copy, kept in the shared cache at `~/.largo/cache`.
+    pub cache: Option<bool>,
+    /// The date to give as `\LargoBuildDate`, in place of the day of the
+    /// build, so that a release can be rebuilt exactly
+    pub build_date: Option<BuildDate>,
+}
+
+/// The day a document says it was built on, like `2024-05-01`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BuildDate(chrono::NaiveDate);
+
+impl BuildDate {
+    const FORMAT: &'static str = "%Y-%m-%d";
+
+    /// The date of a build: the one it was given, or else the one in
+    /// `SOURCE_DATE_EPOCH`, from `[env]` or the environment, or else today
+    pub fn resolve(given: Option<Self>, env: &EnvVars) -> crate::Result<Self> {
+        if let Some(date) = given {
+            return Ok(date);
+        }
+        let epoch = match env.get("SOURCE_DATE_EPOCH") {
+            Some(epoch) => Some(epoch.to_string()),
+            None => std::env::var("SOURCE_DATE_EPOCH").ok(),
+        };
+        match epoch {
+            // The epoch is in UTC, so that it means the same everywhere
+            Some(epoch) => epoch
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .and_then(|secs| chrono::NaiveDateTime::from_timestamp_opt(secs, 0))
+                .map(|time| Self(time.date()))
+                .ok_or_else(|| anyhow::anyhow!("invalid `SOURCE_DATE_EPOCH`: `{}`", epoch)),
+            None => Ok(Self(chrono::Local::now().date_naive())),
+        }
+    }
+}
+
+impl std::fmt::Display for BuildDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.format(Self::FORMAT))
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, Deserialize, Serialize)]
+impl TryFrom<String> for BuildDate {
+    type Error = crate::Error;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        chrono::NaiveDate::parse_from_str(&s, Self::FORMAT)
+            .map(Self)
+            .map_err(|_| anyhow::anyhow!("invalid build date `{}`; write it like `2024-05-01`", s))
+    }
+}
+
+impl From<BuildDate> for String {
+    fn from(date: BuildDate) -> Self {
+        date.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(transparent)]
-pub struct DependencyName<'c>(&'c str);
+pub struct DependencyName<'c>(Cow<'c, str>);
 
 impl<'c> AsRef<str> for DependencyName<'c> {
     fn as_ref(&self) -> &str {
-        self.0
+        &self.0
     }
 }
 
@@ -347,17 +1000,113 @@ impl<'c> TryFrom<&'c str> for DependencyName<'c> {
     type Error = crate::Error;
 
     fn try_from(s: &'c str) -> std::result::Result<Self, Self::Error> {
-        Ok(Self(s))
+        Ok(Self(Cow::Borrowed(s)))
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-pub struct Dependencies<'c>(#[serde(borrow)] BTreeMap<DependencyName<'c>, Dependency<'c>>);
+/// Environment variables to set when running the engine, such as `TZ` or
+/// `openout_any`
+#[derive(Debug, Default, Clone, Deserialize, Serialize, Merge)]
+#[serde(transparent)]
+pub struct EnvVars(BTreeMap<String, String>);
+
+impl EnvVars {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+}
+
+impl FromIterator<(String, String)> for EnvVars {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// Variables made available to the document, each as a `\LargoVar<Name>`
+/// macro: `institution` becomes `\LargoVarInstitution`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, Merge)]
+#[serde(transparent)]
+pub struct UserVars(BTreeMap<String, VarValue>);
+
+impl UserVars {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &VarValue)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&VarValue> {
+        self.0.get(key)
+    }
+}
+
+/// The value of a user variable. A string is escaped, so that it's typeset
+/// as it's written; `{ raw = "\\textbf{DRAFT}" }` is passed to TeX as it is.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Merge)]
+#[merge(replace)]
+#[serde(untagged)]
+pub enum VarValue {
+    Text(String),
+    Raw { raw: String },
+}
+
+/// Named sequences of commands, from the `[alias]` table. Each step is a largo
+/// subcommand with its arguments, like `"build -p release"`, or else an
+/// external command.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Aliases(BTreeMap<String, Vec<String>>);
+
+impl Aliases {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.0.get(name).map(Vec::as_slice)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+// An alias defined in two places is replaced, not concatenated
+impl Merge for Aliases {
+    fn merge_left(&mut self, other: Self) -> &mut Self {
+        for (name, steps) in other.0 {
+            self.0.entry(name).or_insert(steps);
+        }
+        self
+    }
+
+    fn merge_right(&mut self, other: Self) -> &mut Self {
+        self.0.extend(other.0);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Dependencies<'c>(BTreeMap<DependencyName<'c>, Dependency<'c>>);
 
 impl<'c> Dependencies<'c> {
     pub fn new() -> Self {
         Self(BTreeMap::new())
     }
+
+    pub fn insert(&mut self, name: DependencyName<'c>, dep: Dependency<'c>) {
+        self.0.insert(name, dep);
+    }
 }
 
 impl<'a> IntoIterator for &'a Dependencies<'a> {
@@ -370,21 +1119,27 @@ impl<'a> IntoIterator for &'a Dependencies<'a> {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case", untagged)]
 pub enum Dependency<'c> {
     Version(DependencyVersion<'c>),
     Path {
-        path: &'c std::path::Path,
+        path: Cow<'c, std::path::Path>,
         #[serde(default)]
         largo: bool,
+        /// Directories beneath `path` to put on the input path instead of
+        /// `path` itself, like `tex/latex/foo`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        texinputs: Vec<Cow<'c, std::path::Path>>,
+    },
+    /// Another project in the same workspace, by name
+    Member {
+        member: Cow<'c, str>,
     },
     Ctan {
-        #[serde(borrow)]
         version: DependencyVersion<'c>,
     },
     Git {
-        #[serde(borrow)]
         url: Url<'c>,
         #[serde(default)]
         largo: bool,
@@ -392,14 +1147,14 @@ pub enum Dependency<'c> {
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(from = "&'c str", into = "&'c str")]
+#[serde(from = "Cow<'c, str>", into = "Cow<'c, str>")]
 pub enum DependencyVersion<'c> {
     Any,
-    Version(&'c str),
+    Version(Cow<'c, str>),
 }
 
-impl<'c> From<&'c str> for DependencyVersion<'c> {
-    fn from(version: &'c str) -> Self {
+impl<'c> From<Cow<'c, str>> for DependencyVersion<'c> {
+    fn from(version: Cow<'c, str>) -> Self {
         if version == "*" {
             Self::Any
         } else {
@@ -408,13 +1163,97 @@ impl<'c> From<&'c str> for DependencyVersion<'c> {
     }
 }
 
-impl<'c> Into<&'c str> for DependencyVersion<'c> {
-    fn into(self) -> &'c str {
+impl<'c> From<&'c str> for DependencyVersion<'c> {
+    fn from(version: &'c str) -> Self {
+        Cow::Borrowed(version).into()
+    }
+}
+
+impl<'c> DependencyVersion<'c> {
+    /// The TeX Live revision required, for a requirement written like
+    /// `r67890`
+    pub fn revision(&self) -> Option<&str> {
         match self {
-            DependencyVersion::Any => "*",
+            DependencyVersion::Version(req) => req
+                .strip_prefix('r')
+                .filter(|rev| !rev.is_empty() && rev.bytes().all(|b| b.is_ascii_digit())),
+            DependencyVersion::Any => None,
+        }
+    }
+
+    /// Whether a package satisfies this requirement. CTAN only serves the
+    /// latest version of each package, so there are no ranges: a requirement
+    /// is either a TeX Live revision or a CTAN version string, like `1.2a` or
+    /// `2023-05-01`, and must match exactly.
+    pub fn matches(&self, version: Option<&str>, revision: Option<&str>) -> bool {
+        match (self, self.revision()) {
+            (DependencyVersion::Any, _) => true,
+            (_, Some(req)) => revision == Some(req),
+            (DependencyVersion::Version(req), None) => version == Some(req.as_ref()),
+        }
+    }
+}
+
+impl<'c> std::fmt::Display for DependencyVersion<'c> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyVersion::Any => write!(f, "*"),
+            DependencyVersion::Version(req) => write!(f, "{}", req),
+        }
+    }
+}
+
+impl<'c> From<DependencyVersion<'c>> for Cow<'c, str> {
+    fn from(val: DependencyVersion<'c>) -> Self {
+        match val {
+            DependencyVersion::Any => Cow::Borrowed("*"),
             DependencyVersion::Version(s) => s,
         }
     }
 }
 
-pub type Url<'c> = &'c str;
+pub type Url<'c> = Cow<'c, str>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_dates_can_be_fixed() {
+        let settings: ProjectSettings = toml::from_str("build-date = \"2024-05-01\"").unwrap();
+        let env = EnvVars::default();
+        let date = BuildDate::resolve(settings.build_date, &env).unwrap();
+        assert_eq!(date.to_string(), "2024-05-01");
+        let env = EnvVars(BTreeMap::from([(
+            "SOURCE_DATE_EPOCH".to_string(),
+            "1700000000".to_string(),
+        )]));
+        let date = BuildDate::resolve(None, &env).unwrap();
+        assert_eq!(date.to_string(), "2023-11-14");
+        assert!(toml::from_str::<ProjectSettings>("build-date = \"May 1\"").is_err());
+    }
+
+    #[test]
+    fn profiles_cannot_take_the_shared_directories() {
+        assert!(ProfileName::try_from("paper-draft").is_ok());
+        assert!(ProfileName::try_from(dirs::DEPS_DIR).is_err());
+        assert!(ProfileName::try_from(dirs::CHECK_DIR).is_err());
+        assert!(ProfileName::try_from("../dev").is_err());
+        assert!(toml::from_str::<Profiles>("[check]").is_err());
+    }
+
+    #[test]
+    fn colors_can_be_booleans() {
+        let term: TermConfig = toml::from_str("color = true").unwrap();
+        assert!(matches!(term.color, TermColor::Bool(true)));
+        let term: TermConfig = toml::from_str("color = \"never\"").unwrap();
+        assert!(matches!(term.color, TermColor::Never));
+        assert!(toml::from_str::<TermConfig>("color = \"sometimes\"").is_err());
+        let value = toml::Value::try_from(TermConfig {
+            color: TermColor::Bool(false),
+            ..TermConfig::default()
+        })
+        .unwrap();
+        assert_eq!(value.get("color"), Some(&toml::Value::Boolean(false)));
+    }
+}
@@ -0,0 +1,121 @@
+//! Reading and editing the global configuration, `~/.largo/config.toml`, by
+//! dotted keys like `doc.reader`.
+
+use anyhow::anyhow;
+use typedir::PathBuf as P;
+
+use super::{BuildConfig, LargoConfig};
+use crate::{dirs, Result};
+
+fn config_file() -> Result<P<dirs::LargoConfigFile>> {
+    dirs::LargoConfigFile::global()
+}
+
+/// The programs in the build configuration are flattened into the top level of
+/// the file, but are named `build.<program>` here to keep them apart from the
+/// other options.
+fn key_path(key: &str) -> Vec<&str> {
+    match key.strip_prefix("build.") {
+        Some(exec) => vec![exec],
+        None => key.split('.').collect(),
+    }
+}
+
+/// The effective configuration, with defaults filled in
+fn effective_config(doc: &toml_edit::Document) -> Result<toml::Value> {
+    let content = doc.to_string();
+    let config: LargoConfig = toml::from_str(&content)?;
+    Ok(toml::Value::try_from(&config)?)
+}
+
+fn lookup<'v>(value: &'v toml::Value, path: &[&str]) -> Option<&'v toml::Value> {
+    path.iter().try_fold(value, |value, seg| value.get(seg))
+}
+
+/// Get the value of an option, or `None` if it isn't set and has no default.
+/// Strings are returned bare, so that they can be used in scripts; anything
+/// else is written as TOML.
+pub fn get(key: &str) -> Result<Option<String>> {
+    let doc = dirs::LargoConfigFile::try_read_document(&config_file()?)?;
+    let config = effective_config(&doc)?;
+    let value = lookup(&config, &key_path(key)).map(|value| match value {
+        toml::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    });
+    Ok(value)
+}
+
+/// Set an option, keeping the rest of the file as it was. The value is read as
+/// TOML if it can be, and as a string otherwise.
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let path = key_path(key);
+    let config_file = config_file()?;
+    let mut doc = dirs::LargoConfigFile::try_read_document(&config_file)?;
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into());
+
+    let Some((last, tables)) = path.split_last() else {
+        return Err(anyhow!("empty configuration key"));
+    };
+    let mut table = doc.as_table_mut();
+    for seg in tables {
+        let item = table
+            .entry(seg)
+            .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()));
+        table = item
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("`{}` is not a table in the configuration", seg))?;
+    }
+    match table.get_mut(last).and_then(toml_edit::Item::as_value_mut) {
+        // Keep any comment trailing the old value
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = value;
+            *old.decor_mut() = decor;
+        }
+        None => {
+            table.insert(last, toml_edit::value(value.decorated(" ", "")));
+        }
+    }
+
+    // Unknown keys are ignored when the configuration is read, so we catch
+    // them by checking that the new value survives a round trip.
+    let config =
+        effective_config(&doc).map_err(|err| anyhow!("invalid value for `{}`: {}", key, err))?;
+    if lookup(&config, &path).is_none() {
+        return Err(anyhow!("unknown configuration key `{}`", key));
+    }
+    dirs::LargoConfigFile::try_write_document(&config_file, &doc)
+}
+
+/// Every option that has a value, written as TOML, in the order they appear in
+/// the configuration
+pub fn list() -> Result<Vec<(String, String)>> {
+    let doc = dirs::LargoConfigFile::try_read_document(&config_file()?)?;
+    let config = effective_config(&doc)?;
+    let build = toml::Value::try_from(BuildConfig::default())?;
+    let mut options = Vec::new();
+    if let toml::Value::Table(table) = config {
+        for (key, value) in table {
+            let key = if build.get(&key).is_some() {
+                format!("build.{}", key)
+            } else {
+                key
+            };
+            flatten(key, value, &mut options);
+        }
+    }
+    Ok(options)
+}
+
+pub(super) fn flatten(key: String, value: toml::Value, options: &mut Vec<(String, String)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (sub_key, value) in table {
+                flatten(format!("{}.{}", key, sub_key), value, options);
+            }
+        }
+        value => options.push((key, value.to_string())),
+    }
+}
@@ -0,0 +1,49 @@
+//! Rewriting manifests written for older versions of largo, whose keys have
+//! since been renamed or moved. This works on the document itself rather than
+//! on a parsed configuration, so that comments and formatting survive, and so
+//! that a manifest largo can no longer read can still be fixed.
+
+use toml_edit::Table;
+use typedir::{Extend, PathBuf as P};
+
+use crate::{dirs, Result};
+
+/// Rewrite the deprecated keys in a manifest, returning a description of each
+/// change. Nothing is changed if there's nothing to fix.
+pub fn fix_manifest(root: &P<dirs::RootDir>) -> Result<Vec<String>> {
+    let manifest: P<dirs::ProjectConfigFile> = root.clone().extend(());
+    let mut doc = dirs::ProjectConfigFile::try_read_document(&manifest)?;
+    let changes = migrate(doc.as_table_mut())?;
+    if !changes.is_empty() {
+        dirs::ProjectConfigFile::try_write_document(&manifest, &doc)?;
+    }
+    Ok(changes)
+}
+
+/// Rewrites the manifest, and describes what it changed
+type Migration = fn(&mut Table) -> Result<Vec<String>>;
+
+/// No key has been renamed or moved since manifests were first read, so there
+/// are no migrations yet
+const MIGRATIONS: &[Migration] = &[];
+
+fn migrate(root: &mut Table) -> Result<Vec<String>> {
+    let mut changes = Vec::new();
+    for migration in MIGRATIONS {
+        changes.extend(migration(root)?);
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_manifests_are_left_alone() {
+        let src = "[project]\nname = \"p\"\n\n[profile.draft]\ndraft-mode = true\n";
+        let mut doc: toml_edit::Document = src.parse().unwrap();
+        assert!(migrate(doc.as_table_mut()).unwrap().is_empty());
+        assert_eq!(doc.to_string(), src);
+    }
+}
@@ -0,0 +1,623 @@
+//! Parsing configuration files, with errors that point into the file. Keys
+//! that don't mean anything to largo aren't silently ignored: they're
+//! reported, with a suggestion of what was probably meant.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use toml::Spanned;
+
+use crate::Result;
+
+/// The keys each table may contain, for suggesting corrections to unknown
+/// keys. A `*` stands for any name, as in `profile.*`. Tables whose keys are
+/// chosen by the user, like `[env]`, aren't listed.
+pub struct Schema {
+    pub tables: &'static [(&'static str, &'static [&'static str])],
+    /// Tables whose keys are written at the top level of the file, but which
+    /// are named in `[target.<os>]` tables, like `[target.macos.build]`
+    pub flattened: &'static [&'static str],
+}
+
+/// The table of platform-specific configuration
+const TARGET_TABLE: &str = "target";
+
+/// The platforms that can be named in `[target.<os>]`, spelled as in
+/// `std::env::consts::OS`
+const TARGET_OSES: &[&str] = &["linux", "macos", "windows"];
+
+const PROJECT_SETTINGS_KEYS: &[&str] = &[
+    "output-format",
+    "shell-escape",
+    "synctex",
+    "draft-mode",
+    "keep-intermediates",
+    "output-dir",
+    "skip-bib",
+    "cache",
+    "build-date",
+];
+
+const SYSTEM_SETTINGS_KEYS: &[&str] = &["tex-format", "tex-engine", "bib-engine"];
+
+pub const PROJECT_SCHEMA: Schema = Schema {
+    tables: &[
+        (
+            "",
+            &[
+                "project",
+                "package",
+                "class",
+                "profile",
+                "document",
+                "dependencies",
+                "env",
+                "vars",
+                "alias",
+                "target",
+                "workspace",
+            ],
+        ),
+        (
+            "project",
+            &[
+                "name",
+                "largo-version",
+                "deny-unknown-keys",
+                "main",
+                "deps-layout",
+                "version",
+                "authors",
+                "description",
+                "license",
+            ],
+        ),
+        ("project", PROJECT_SETTINGS_KEYS),
+        ("project", SYSTEM_SETTINGS_KEYS),
+        ("profile.*", &["env", "vars"]),
+        ("profile.*.vars.*", &["raw"]),
+        ("profile.*", PROJECT_SETTINGS_KEYS),
+        ("profile.*", SYSTEM_SETTINGS_KEYS),
+        ("document", &["name", "main", "bibliography"]),
+        (
+            "dependencies.*",
+            &["version", "path", "url", "largo", "texinputs", "member"],
+        ),
+        ("vars.*", &["raw"]),
+        ("workspace", &["members"]),
+    ],
+    flattened: &[],
+};
+
+pub const GLOBAL_SCHEMA: Schema = Schema {
+    tables: &[
+        (
+            "",
+            &[
+                "default-profile",
+                "default-tex-format",
+                "default-tex-engine",
+                "bib",
+                "doc",
+                "registry",
+                "init",
+                "term",
+                "alias",
+                "target",
+            ],
+        ),
+        ("", super::ExecutableConfig::NAMES),
+        ("bib", &["bibliography"]),
+        ("doc", &["reader"]),
+        (
+            "doc.reader",
+            &[
+                "command",
+                "args",
+                "forward-args",
+                "linux",
+                "macos",
+                "windows",
+            ],
+        ),
+        ("doc.reader.*", &["command", "args", "forward-args"]),
+        (
+            "registry",
+            &[
+                "mirrors",
+                "timeout",
+                "retries",
+                "jobs",
+                "metadata-ttl",
+                "proxy",
+                "ca-bundle",
+                "offline",
+            ],
+        ),
+        ("init", &["packages"]),
+        ("term", &["quiet", "verbose", "color"]),
+    ],
+    // The programs are flattened into the top level
+    flattened: &["build"],
+};
+
+/// The shape of a TOML document, with the location of each value
+enum Node {
+    Table(Vec<(String, Spanned<Node>)>),
+    Array(Vec<Spanned<Node>>),
+    Leaf,
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NodeVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for NodeVisitor {
+            type Value = Node;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a TOML value")
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<Node, E> {
+                Ok(Node::Leaf)
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<Node, E> {
+                Ok(Node::Leaf)
+            }
+
+            fn visit_u64<E>(self, _: u64) -> Result<Node, E> {
+                Ok(Node::Leaf)
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<Node, E> {
+                Ok(Node::Leaf)
+            }
+
+            fn visit_str<E>(self, _: &str) -> Result<Node, E> {
+                Ok(Node::Leaf)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+                let mut elems = Vec::new();
+                while let Some(elem) = seq.next_element()? {
+                    elems.push(elem);
+                }
+                Ok(Node::Array(elems))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Node::Table(entries))
+            }
+        }
+
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+impl Node {
+    fn get(&self, key: &str) -> Option<&Node> {
+        match self {
+            Node::Table(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.get_ref()),
+            _ => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Node::Table(entries) => entries.is_empty(),
+            Node::Array(elems) => elems.is_empty(),
+            Node::Leaf => false,
+        }
+    }
+}
+
+/// A key in the file that wasn't understood
+struct UnknownKey {
+    /// The table it's in, like `profile.dev`
+    table: Vec<String>,
+    key: String,
+    /// Where in the file its value begins, if known
+    offset: Option<usize>,
+}
+
+/// A parsed configuration file
+pub struct Parsed<T> {
+    pub config: T,
+    /// A report of each key that wasn't understood, to be shown as a warning
+    /// or an error
+    pub unknown_keys: Vec<String>,
+}
+
+/// Parse a configuration file, with `schema` describing the keys it may
+/// contain.
+pub fn from_str<T>(src: &str, path: &Path, schema: &Schema) -> Result<Parsed<T>>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let mut config: T = toml::from_str(src).map_err(|err| {
+        let mut msg = err.to_string();
+        // The location is shown below instead
+        if let Some(idx) = msg.rfind(" at line ") {
+            msg.truncate(idx);
+        }
+        // A missing field is reported at the end of its table, which is less
+        // helpful than its header
+        let table = msg
+            .strip_prefix("missing field ")
+            .and_then(|msg| msg.split_once(" for key `"))
+            .map(|(_, key)| key.trim_end_matches('`').split('.').map(String::from));
+        let offset = match table {
+            Some(mut table) => {
+                let table: Vec<String> = table.by_ref().collect();
+                let (key, table) = table.split_last().expect("split yields a key");
+                header_offset(src, table, key)
+            }
+            None => err.line_col().map(|(line, col)| {
+                // Point into a table's header rather than at its bracket
+                let offset = line_offset(src, line) + col;
+                offset + src[offset..].len() - src[offset..].trim_start_matches('[').len()
+            }),
+        };
+        anyhow!(render(src, path, offset, &msg, None))
+    })?;
+    let source: Node = toml::from_str(src)?;
+    let parsed = toml::Value::try_from(&config)?;
+    let mut unknown = Vec::new();
+    find_unknown_keys(&source, &parsed, &mut Vec::new(), &mut unknown);
+    // The platform tables are checked below, against the configuration as it
+    // would be on each platform
+    unknown.retain(|unknown| !(unknown.table.is_empty() && unknown.key == TARGET_TABLE));
+    if let Some(Node::Table(targets)) = source.get(TARGET_TABLE) {
+        let base: toml::Value = toml::from_str(src)?;
+        for (os, os_node) in targets {
+            let table = vec![TARGET_TABLE.to_string()];
+            if !TARGET_OSES.contains(&os.as_str()) {
+                unknown.push(UnknownKey {
+                    table,
+                    key: os.clone(),
+                    offset: Some(os_node.start()).filter(|_| os_node.end() > 0),
+                });
+                continue;
+            }
+            let overlay = base
+                .get(TARGET_TABLE)
+                .and_then(|targets| targets.get(os))
+                .cloned()
+                .unwrap_or(toml::Value::Table(Default::default()));
+            let merged: T = with_target(&base, overlay, schema)
+                .try_into()
+                .map_err(|err| {
+                    let mut msg = err.to_string();
+                    if let Some(idx) = msg.rfind(" at line ") {
+                        msg.truncate(idx);
+                    }
+                    let msg = format!("{} (with `[{}.{}]`)", msg, TARGET_TABLE, os);
+                    anyhow!(render(
+                        src,
+                        path,
+                        header_offset(src, &table, os),
+                        &msg,
+                        None
+                    ))
+                })?;
+            let parsed = toml::Value::try_from(&merged)?;
+            find_unknown_target_keys(os_node.get_ref(), &parsed, os, schema, &mut unknown);
+            if os == std::env::consts::OS {
+                config = merged;
+            }
+        }
+    }
+    let unknown_keys = unknown
+        .into_iter()
+        .map(|unknown| {
+            let msg = if unknown.table.is_empty() {
+                format!("unknown key `{}`", unknown.key)
+            } else {
+                format!(
+                    "unknown key `{}` in `[{}]`",
+                    unknown.key,
+                    unknown.table.join(".")
+                )
+            };
+            let offset = match unknown.offset {
+                Some(offset) => key_offset(src, offset),
+                None => header_offset(src, &unknown.table, &unknown.key),
+            };
+            let help = suggest(schema, &unknown.table, &unknown.key)
+                .map(|key| format!("did you mean `{}`?", key));
+            render(src, path, offset, &msg, help.as_deref())
+        })
+        .collect();
+    Ok(Parsed {
+        config,
+        unknown_keys,
+    })
+}
+
+/// The file as it would be with a `[target.<os>]` table merged into it
+fn with_target(base: &toml::Value, overlay: toml::Value, schema: &Schema) -> toml::Value {
+    let mut merged = base.clone();
+    let toml::Value::Table(root) = &mut merged else {
+        unreachable!("a TOML document is a table");
+    };
+    root.remove(TARGET_TABLE);
+    if let toml::Value::Table(overlay) = overlay {
+        for (key, value) in overlay {
+            match value {
+                toml::Value::Table(flattened) if schema.flattened.contains(&key.as_str()) => {
+                    for (key, value) in flattened {
+                        merge_value(root.entry(key), value);
+                    }
+                }
+                value => merge_value(root.entry(key), value),
+            }
+        }
+    }
+    merged
+}
+
+/// Merge tables key by key; anything else replaces what was there
+fn merge_value(entry: toml::map::Entry, value: toml::Value) {
+    match (entry, value) {
+        (toml::map::Entry::Occupied(mut entry), toml::Value::Table(table)) => {
+            match entry.get_mut() {
+                toml::Value::Table(existing) => {
+                    for (key, value) in table {
+                        merge_value(existing.entry(key), value);
+                    }
+                }
+                existing => *existing = toml::Value::Table(table),
+            }
+        }
+        (toml::map::Entry::Occupied(mut entry), value) => *entry.get_mut() = value,
+        (toml::map::Entry::Vacant(entry), value) => {
+            entry.insert(value);
+        }
+    }
+}
+
+/// Compare the keys in a file with those that survived being parsed: any
+/// that didn't weren't understood. Empty tables, like an `[env]` with nothing
+/// in it, may not survive either, but there's nothing in them to lose.
+fn find_unknown_keys(
+    source: &Node,
+    parsed: &toml::Value,
+    table: &mut Vec<String>,
+    unknown: &mut Vec<UnknownKey>,
+) {
+    match (source, parsed) {
+        (Node::Table(entries), toml::Value::Table(parsed)) => {
+            find_unknown_entries(entries.iter(), parsed, table, unknown);
+        }
+        (Node::Array(elems), toml::Value::Array(parsed)) => {
+            for (elem, parsed) in elems.iter().zip(parsed) {
+                find_unknown_keys(elem.get_ref(), parsed, table, unknown);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn find_unknown_entries<'a>(
+    entries: impl Iterator<Item = &'a (String, Spanned<Node>)>,
+    parsed: &toml::map::Map<String, toml::Value>,
+    table: &mut Vec<String>,
+    unknown: &mut Vec<UnknownKey>,
+) {
+    for (key, value) in entries {
+        match parsed.get(key) {
+            Some(parsed) => {
+                table.push(key.clone());
+                find_unknown_keys(value.get_ref(), parsed, table, unknown);
+                table.pop();
+            }
+            None if value.get_ref().is_empty() => (),
+            None => unknown.push(UnknownKey {
+                table: table.clone(),
+                key: key.clone(),
+                // Tables defined by a header have no span
+                offset: Some(value.start()).filter(|_| value.end() > 0),
+            }),
+        }
+    }
+}
+
+/// Find the unknown keys of a `[target.<os>]` table, in the configuration
+/// parsed with it merged in
+fn find_unknown_target_keys(
+    source: &Node,
+    parsed: &toml::Value,
+    os: &str,
+    schema: &Schema,
+    unknown: &mut Vec<UnknownKey>,
+) {
+    let (Node::Table(entries), toml::Value::Table(parsed)) = (source, parsed) else {
+        return;
+    };
+    let mut table = vec![TARGET_TABLE.to_string(), os.to_string()];
+    let (flattened, entries): (Vec<_>, Vec<_>) = entries
+        .iter()
+        .partition(|(key, _)| schema.flattened.contains(&key.as_str()));
+    find_unknown_entries(entries.into_iter(), parsed, &mut table, unknown);
+    for (key, value) in flattened {
+        if let Node::Table(entries) = value.get_ref() {
+            table.push(key.clone());
+            find_unknown_entries(entries.iter(), parsed, &mut table, unknown);
+            table.pop();
+        }
+    }
+}
+
+/// The known key most like an unknown one, if any is close enough to be a
+/// likely typo
+fn suggest(schema: &Schema, table: &[String], key: &str) -> Option<&'static str> {
+    // The keys of a `[target.<os>]` table are those of the top level
+    let table = match table {
+        [target] if target == TARGET_TABLE => return closest(TARGET_OSES.iter().copied(), key),
+        [target, _, flattened, rest @ ..]
+            if target == TARGET_TABLE && schema.flattened.contains(&flattened.as_str()) =>
+        {
+            rest
+        }
+        [target, _, rest @ ..] if target == TARGET_TABLE => rest,
+        table => table,
+    };
+    let known = schema
+        .tables
+        .iter()
+        .filter(|(pattern, _)| table_matches(pattern, table))
+        .flat_map(|(_, keys)| keys.iter().copied());
+    closest(known, key)
+}
+
+fn closest(known: impl Iterator<Item = &'static str>, key: &str) -> Option<&'static str> {
+    known
+        .map(|known| (strsim::levenshtein(known, key), known))
+        .filter(|(distance, _)| *distance <= std::cmp::max(1, key.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+fn table_matches(pattern: &str, table: &[String]) -> bool {
+    if pattern.is_empty() {
+        return table.is_empty();
+    }
+    let parts: Vec<&str> = pattern.split('.').collect();
+    parts.len() == table.len()
+        && parts
+            .iter()
+            .zip(table)
+            .all(|(part, name)| *part == "*" || part == name)
+}
+
+/// The offset of the start of a (zero-indexed) line
+fn line_offset(src: &str, line: usize) -> usize {
+    src.split_inclusive('\n').take(line).map(str::len).sum()
+}
+
+/// The offset of the key whose value begins at `value_offset`, found by
+/// stepping back over the `=`
+fn key_offset(src: &str, value_offset: usize) -> Option<usize> {
+    let before = src[..value_offset].trim_end_matches([' ', '\t']);
+    let before = before.strip_suffix('=')?.trim_end_matches([' ', '\t']);
+    let key_len = before
+        .chars()
+        .rev()
+        .take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '"' | '\''))
+        .map(char::len_utf8)
+        .sum::<usize>();
+    Some(before.len() - key_len)
+}
+
+/// The offset of the key within the header of a table like `[table.key]`
+fn header_offset(src: &str, table: &[String], key: &str) -> Option<usize> {
+    let mut path = table.to_vec();
+    path.push(key.to_string());
+    let header = path.join(".");
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let trimmed = line.trim_start_matches('[').trim_start();
+        if line.starts_with('[') && trimmed.starts_with(&header) {
+            let key_start = line.len() - trimmed.len() + header.len() - key.len();
+            return Some(offset + key_start);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Format an error message with the line of the file it refers to, in the
+/// style of rustc
+fn render(src: &str, path: &Path, offset: Option<usize>, msg: &str, help: Option<&str>) -> String {
+    let Some(offset) = offset.filter(|offset| *offset <= src.len()) else {
+        let mut rendered = format!("{}\n  --> {}", msg, path.display());
+        if let Some(help) = help {
+            rendered.push_str(&format!("\n   = help: {}", help));
+        }
+        return rendered;
+    };
+    let line_start = src[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_num = src[..line_start].matches('\n').count() + 1;
+    let line = src[line_start..].lines().next().unwrap_or_default();
+    let col = src[line_start..offset].chars().count();
+    let width = line[offset - line_start..]
+        .chars()
+        .take_while(|c| !c.is_whitespace() && !matches!(c, '=' | ']' | ',' | '.'))
+        .count()
+        .max(1);
+    let gutter = " ".repeat(line_num.to_string().len());
+    let mut rendered = format!(
+        "{msg}\n{gutter}--> {}:{}:{}\n{gutter} |\n{line_num} | {line}\n{gutter} | {}{}",
+        path.display(),
+        line_num,
+        col + 1,
+        " ".repeat(col),
+        "^".repeat(width),
+    );
+    if let Some(help) = help {
+        rendered.push_str(&format!("\n{gutter} = help: {}", help));
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_keys_are_located() {
+        let src = "[project]\nname = \"p\"\nshell-scape = true\ntex-format = \"latex\"\n\
+                   tex-engine = \"pdftex\"\n\n[profile.dev]\nsyntex = true\n";
+        let parsed =
+            from_str::<crate::conf::ProjectConfig>(src, Path::new("largo.toml"), &PROJECT_SCHEMA)
+                .unwrap();
+        let err = parsed.unknown_keys.join("\n");
+        assert!(err.contains("unknown key `shell-scape` in `[project]`"));
+        assert!(err.contains("largo.toml:3:1"));
+        assert!(err.contains("did you mean `shell-escape`?"));
+        assert!(err.contains("largo.toml:8:1"));
+        assert!(err.contains("did you mean `synctex`?"));
+    }
+
+    #[test]
+    fn target_tables_apply_on_their_platform() {
+        let other = if std::env::consts::OS == "windows" {
+            "linux"
+        } else {
+            "windows"
+        };
+        let src = format!(
+            "[project]\nname = \"p\"\ntex-format = \"latex\"\ntex-engine = \"pdftex\"\n\n\
+             [target.{}.project]\ntex-engine = \"xetex\"\n\n\
+             [target.{}.project]\ntex-engine = \"luatex\"\nshel-escape = true\n\n\
+             [target.linx.project]\n",
+            std::env::consts::OS,
+            other
+        );
+        let parsed =
+            from_str::<crate::conf::ProjectConfig>(&src, Path::new("largo.toml"), &PROJECT_SCHEMA)
+                .unwrap();
+        assert!(matches!(
+            parsed.config.project.system_settings.tex_engine,
+            crate::conf::TexEngine::Xetex
+        ));
+        let err = parsed.unknown_keys.join("\n");
+        assert!(err.contains(&format!(
+            "unknown key `shel-escape` in `[target.{}.project]`",
+            other
+        )));
+        assert!(err.contains("did you mean `shell-escape`?"));
+        assert!(err.contains("unknown key `linx` in `[target]`"));
+        assert!(err.contains("did you mean `linux`?"));
+    }
+}
@@ -0,0 +1,158 @@
+//! The `largo-version` requirement of a project, which keeps a version of
+//! largo too old to understand a manifest from building it. Requirements are
+//! written as in Cargo, like `>=0.3` or `^0.2.1, <0.2.5`, though there's no
+//! support for pre-release versions.
+
+use anyhow::anyhow;
+
+use crate::Result;
+
+/// This version of largo
+pub const LARGO_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Tilde,
+    Caret,
+}
+
+/// A single comparison, like `>=0.3`. Components left out of the version
+/// match anything, so `=0.3` matches `0.3.4`.
+#[derive(Debug)]
+struct Comparator {
+    op: Op,
+    parts: Vec<u64>,
+}
+
+impl Comparator {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (op, version) = [
+            (">=", Op::GreaterEq),
+            ("<=", Op::LessEq),
+            (">", Op::Greater),
+            ("<", Op::Less),
+            ("=", Op::Exact),
+            ("~", Op::Tilde),
+            ("^", Op::Caret),
+        ]
+        .into_iter()
+        .find_map(|(prefix, op)| s.strip_prefix(prefix).map(|rest| (op, rest)))
+        .unwrap_or((Op::Caret, s));
+        let parts = parse_version(version.trim())?;
+        Some(Self { op, parts })
+    }
+
+    fn matches(&self, version: &[u64; 3]) -> bool {
+        let given = &self.parts[..];
+        let truncated = &version[..given.len()];
+        let at_least = version.as_slice() >= padded(given).as_slice();
+        match self.op {
+            Op::Exact => truncated == given,
+            Op::Greater => truncated > given,
+            Op::GreaterEq => truncated >= given,
+            Op::Less => truncated < given,
+            Op::LessEq => truncated <= given,
+            // `~1.2.3` allows changes to the patch version, and `~1` to the
+            // minor version
+            Op::Tilde => {
+                let fixed = std::cmp::min(given.len(), 2);
+                at_least && version[..fixed] == given[..fixed]
+            }
+            // `^1.2.3` allows any change that leaves the leftmost nonzero
+            // component alone
+            Op::Caret => {
+                let fixed = given
+                    .iter()
+                    .position(|part| *part != 0)
+                    .map_or(given.len(), |idx| idx + 1);
+                at_least && version[..fixed] == given[..fixed]
+            }
+        }
+    }
+}
+
+fn parse_version(s: &str) -> Option<Vec<u64>> {
+    let parts = s
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    (1..=3).contains(&parts.len()).then_some(parts)
+}
+
+fn padded(parts: &[u64]) -> [u64; 3] {
+    let mut version = [0; 3];
+    version[..parts.len()].copy_from_slice(parts);
+    version
+}
+
+/// Whether `version` satisfies every comparator of `req`
+fn req_matches(req: &str, version: &str) -> Result<bool> {
+    let comparators = req
+        .split(',')
+        .map(Comparator::parse)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| anyhow!("invalid `largo-version` requirement `{}`", req))?;
+    let version = parse_version(version)
+        .filter(|parts| parts.len() == 3)
+        .ok_or_else(|| anyhow!("invalid version `{}`", version))?;
+    let version = padded(&version);
+    Ok(comparators.iter().all(|comp| comp.matches(&version)))
+}
+
+/// Check a manifest's `largo-version` requirement, if it has one. This is done
+/// before the rest of the manifest is parsed, so that an old largo says it's
+/// too old, rather than failing to understand a newer key.
+pub(super) fn check_requirement(manifest: &str) -> Result<()> {
+    let Ok(manifest) = toml::from_str::<toml::Value>(manifest) else {
+        return Ok(());
+    };
+    let req = manifest
+        .get("project")
+        .and_then(|project| project.get("largo-version"))
+        .and_then(toml::Value::as_str);
+    match req {
+        Some(req) if !req_matches(req, LARGO_VERSION)? => Err(anyhow!(
+            "this project requires largo `{}`, but this is largo {}; please update largo",
+            req,
+            LARGO_VERSION
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requirements_match() {
+        let cases = [
+            (">=0.3", "0.3.0", true),
+            (">=0.3", "0.2.9", false),
+            (">0.3", "0.3.5", false),
+            (">0.3", "0.4.0", true),
+            ("<=0.3", "0.3.5", true),
+            ("=0.3", "0.3.4", true),
+            ("0.3.1", "0.3.9", true),
+            ("0.3.1", "0.4.0", false),
+            ("^1.2", "1.9.0", true),
+            ("~1.2", "1.3.0", false),
+            (">=0.2, <0.4", "0.3.1", true),
+            (">=0.2, <0.4", "0.4.0", false),
+        ];
+        for (req, version, expected) in cases {
+            assert_eq!(
+                req_matches(req, version).unwrap(),
+                expected,
+                "{req} {version}"
+            );
+        }
+        assert!(req_matches(">=x", "0.1.0").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Checking a project's dependencies against the packages its sources load.
+//! Like the rest of the scanner, this only sees packages loaded by name in
+//! the source directory: a dependency that's only loaded by another package
+//! looks unused.
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+
+use typedir::{Extend, PathBuf as P};
+
+use crate::conf::{self, Dependency, DependencyName};
+use crate::{dirs, scan, Result};
+
+/// Extensions of the files that can load packages
+const SOURCE_EXTENSIONS: &[&str] = &["tex", "sty", "cls"];
+
+#[derive(Debug)]
+pub struct Audit<'d> {
+    /// Dependencies that provide nothing the sources load
+    pub unused: Vec<&'d DependencyName<'d>>,
+    /// Packages and classes the sources load that aren't in the source
+    /// directory, provided by a dependency, or installed
+    pub undeclared: BTreeSet<String>,
+}
+
+impl Audit<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.unused.is_empty() && self.undeclared.is_empty()
+    }
+}
+
+/// Cross-reference the packages loaded in a project's sources with its
+/// dependencies, as installed in the profile's deps directory. A dependency
+/// provides a package with its own name, and every style and class file
+/// that it contains.
+pub fn audit<'d>(
+    root: &P<dirs::RootDir>,
+    profile: &conf::ProfileName<'_>,
+    layout: conf::DepsLayout,
+    deps: &'d conf::Dependencies<'d>,
+) -> Result<Audit<'d>> {
+    let src_dir: P<dirs::SrcDir> = root.clone().extend(());
+    let mut loaded = BTreeSet::new();
+    let mut local = HashSet::new();
+    for path in files_with_extensions(&src_dir, SOURCE_EXTENSIONS)? {
+        let contents = scan::strip_comments(&std::fs::read_to_string(&path)?);
+        loaded.extend(scan::loaded_packages(&contents));
+        local.extend(file_name(&path));
+    }
+
+    let target_dir: P<dirs::TargetDir> = root.clone().extend(());
+    let deps_dir = dirs::DepsDir::for_profile(&target_dir, profile, layout);
+    let mut provided = HashSet::new();
+    let mut unused = Vec::new();
+    for (name, dep) in deps {
+        let search_dirs = match dep {
+            Dependency::Version(_) | Dependency::Ctan { .. } => {
+                let dep_dir: P<dirs::DepDir> = deps_dir.clone().extend(name);
+                vec![dep_dir.to_path_buf()]
+            }
+            Dependency::Path { .. } | Dependency::Member { .. } => {
+                super::dependency_paths(root, &deps_dir, name, dep)?
+            }
+            Dependency::Git { .. } => vec![],
+        };
+        let mut files = HashSet::new();
+        for dir in search_dirs {
+            for path in files_with_extensions(&dir, &["sty", "cls"])? {
+                files.extend(file_name(&path));
+            }
+        }
+        let is_used = loaded
+            .iter()
+            .any(|file| files.contains(file) || stem(file) == name.as_ref());
+        if !is_used {
+            unused.push(name);
+        }
+        provided.insert(name.as_ref().to_string());
+        provided.extend(files);
+    }
+
+    let undeclared = loaded
+        .into_iter()
+        .filter(|file| {
+            !local.contains(file) && !provided.contains(file) && !provided.contains(stem(file))
+        })
+        .filter(|file| crate::probe::kpsewhich(file).is_none())
+        .map(|file| stem(&file).to_string())
+        .collect();
+    Ok(Audit { unused, undeclared })
+}
+
+/// A file name without its extension
+fn stem(file: &str) -> &str {
+    file.rsplit_once('.').map_or(file, |(stem, _)| stem)
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    Some(path.file_name()?.to_str()?.to_string())
+}
+
+/// Every file under `dir` with one of these extensions. A directory that
+/// doesn't exist has none.
+fn files_with_extensions(dir: &Path, extensions: &[&str]) -> Result<Vec<std::path::PathBuf>> {
+    let mut files = Vec::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(files_with_extensions(&path, extensions)?);
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| extensions.contains(&ext))
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,131 @@
+//! Downloads from CTAN, kept in `~/.largo/cache/ctan/<package>/<version>` and
+//! shared by every project on this machine, so that each version of a package
+//! is only ever downloaded once. Projects unpack the archive into their deps
+//! directory. Package metadata is kept alongside for a while, too.
+
+use typedir::{Extend, PathBuf as P};
+
+use super::{DependencyPayload, DownloadFormat};
+use crate::conf::DependencyName;
+use crate::{dirs, Result};
+
+/// A version, made safe to use as a directory name
+fn version_dir_name(version: &str) -> String {
+    version
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn archive_name(name: &DependencyName<'_>, format: DownloadFormat) -> String {
+    match format {
+        DownloadFormat::Zip => format!("{}.zip", name),
+        DownloadFormat::TdsZip => format!("{}.tds.zip", name),
+    }
+}
+
+/// Where archives of this version of a package are kept
+fn version_dir(name: &DependencyName<'_>, version: &str) -> Result<P<dirs::CachedVersionDir>> {
+    Ok(dirs::CtanCacheDir::global()?
+        .extend(name)
+        .extend(version_dir_name(version).as_str()))
+}
+
+/// Where an archive of this version of a package is kept
+fn archive_path(
+    name: &DependencyName<'_>,
+    version: &str,
+    format: DownloadFormat,
+) -> Result<P<dirs::CachedArchiveFile>> {
+    Ok(version_dir(name, version)?.extend(archive_name(name, format).as_str()))
+}
+
+/// The cached archive of this version of a package, if there is one. An
+/// archive that doesn't match the checksum it's expected to have is ignored.
+pub fn try_read(
+    name: &DependencyName<'_>,
+    version: &str,
+    checksum: Option<&str>,
+) -> Result<Option<DependencyPayload>> {
+    // CTAN only offers one kind of archive for a package, but it could
+    // have started offering a TDS archive since this one was cached
+    for format in [DownloadFormat::TdsZip, DownloadFormat::Zip] {
+        let archive = archive_path(name, version, format)?;
+        let bytes = match std::fs::read(&archive) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let payload = DependencyPayload { bytes, format };
+        if checksum.is_none_or(|checksum| payload.checksum() == checksum) {
+            return Ok(Some(payload));
+        }
+    }
+    Ok(None)
+}
+
+/// Keep a downloaded archive. It's written off to the side and moved into
+/// place, so that a concurrent build never sees half of it.
+pub fn try_store(
+    name: &DependencyName<'_>,
+    version: &str,
+    payload: &DependencyPayload,
+) -> Result<()> {
+    let version_dir = version_dir(name, version)?;
+    std::fs::create_dir_all(&version_dir)?;
+    let archive: P<dirs::CachedArchiveFile> = version_dir
+        .clone()
+        .extend(archive_name(name, payload.format).as_str());
+    let staging = version_dir.join(format!(
+        ".{}.{}",
+        archive_name(name, payload.format),
+        std::process::id()
+    ));
+    std::fs::write(&staging, &payload.bytes)?;
+    std::fs::rename(&staging, &archive)?;
+    Ok(())
+}
+
+fn metadata_path(name: &DependencyName<'_>) -> Result<P<dirs::CachedMetadataFile>> {
+    let package_dir: P<dirs::CachedPackageDir> = dirs::CtanCacheDir::global()?.extend(name);
+    Ok(package_dir.extend(()))
+}
+
+/// The cached metadata of a package, if it's younger than `ttl`, or of any
+/// age without one
+pub fn try_read_metadata(
+    name: &DependencyName<'_>,
+    ttl: Option<std::time::Duration>,
+) -> Result<Option<String>> {
+    let path = metadata_path(name)?;
+    let modified = match std::fs::metadata(&path).and_then(|meta| meta.modified()) {
+        Ok(modified) => modified,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // A clock that's gone backwards makes the metadata stale
+    let fresh = ttl.is_none_or(|ttl| modified.elapsed().is_ok_and(|age| age < ttl));
+    if !fresh {
+        return Ok(None);
+    }
+    Ok(Some(std::fs::read_to_string(&path)?))
+}
+
+/// Keep the metadata of a package, written off to the side and moved into
+/// place like an archive
+pub fn try_store_metadata(name: &DependencyName<'_>, json: &str) -> Result<()> {
+    let path = metadata_path(name)?;
+    let package_dir = path.parent();
+    std::fs::create_dir_all(&package_dir)?;
+    let staging = package_dir.join(format!(
+        ".{}.{}",
+        dirs::CTAN_METADATA_FILE,
+        std::process::id()
+    ));
+    std::fs::write(&staging, json)?;
+    std::fs::rename(&staging, &path)?;
+    Ok(())
+}
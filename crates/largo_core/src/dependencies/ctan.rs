@@ -220,6 +220,13 @@ pub struct Version {
     pub date: Option<String>,
 }
 
+impl Version {
+    /// The version number, or the date for packages that don't have one
+    pub fn latest(&self) -> Option<&str> {
+        self.number.as_deref().or(self.date.as_deref())
+    }
+}
+
 /// The value can be either a string or a list of strings with keys of licenses.
 #[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
@@ -320,19 +327,15 @@ impl From<String> for Texlive {
 
 #[cfg(test)]
 mod tests {
-    use crate::conf::{DependencyName, DependencyVersion};
+    use crate::conf::DependencyName;
 
     use super::super::WebClient;
 
     #[tokio::test]
     async fn get_pkg_metadata_works() {
-        let client = WebClient::new().unwrap();
-        // FIXME: This is a pretty awkward way to construct one of these things!
-        let name: crate::conf::DependencyName<'static> = unsafe { std::mem::transmute("tex") };
-        let pkg = client
-            .get_ctan_pkg_metadata(&name, &DependencyVersion::Any)
-            .await
-            .unwrap();
+        let client = WebClient::new(&Default::default(), &|_| ()).unwrap();
+        let name = DependencyName::try_from("tex").unwrap();
+        let pkg = client.get_ctan_pkg_metadata(&name).await.unwrap();
         assert_eq!(&pkg.authors[0].id, "knuth");
     }
 }
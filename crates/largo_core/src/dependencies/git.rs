@@ -0,0 +1,28 @@
+//! Fetching from git repositories, by way of the `git` executable, which
+//! already knows about the user's credentials and proxies.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::Result;
+
+/// Clone the latest commit of a repository into `dest`, which mustn't exist
+/// yet. Git is never allowed to prompt for credentials, which would hang a
+/// build.
+pub fn clone(url: &str, dest: &Path) -> Result<()> {
+    let output = std::process::Command::new("git")
+        .args(["clone", "--quiet", "--depth", "1", "--", url])
+        .arg(dest)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|err| anyhow!("failed to run git: {}", err))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to clone `{}`: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
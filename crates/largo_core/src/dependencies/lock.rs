@@ -0,0 +1,459 @@
+//! The lockfile, `largo.lock`, which records exactly what each dependency
+//! resolved to, so that later builds use the same thing.
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use typedir::{Extend, PathBuf as P};
+
+use super::{Progress, WebClient};
+use crate::build::LargoInfo;
+use crate::conf::{Dependencies, Dependency, DependencyName, DependencyVersion, RegistryConfig};
+use crate::{dirs, Result};
+
+const LOCKFILE_VERSION: u32 = 1;
+
+const LOCKFILE_HEADER: &str =
+    "# This file is generated by largo. It is not intended for manual editing.\n";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Lockfile {
+    version: u32,
+    #[serde(rename = "package", default, skip_serializing_if = "Vec::is_empty")]
+    packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LockedPackage {
+    pub name: String,
+    /// Where the dependency comes from: `ctan`, `path+<path>`, or `git+<url>`
+    pub source: String,
+    /// The resolved version, for CTAN dependencies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The installed TeX Live revision, for dependencies that require one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// SHA-256 digest of the downloaded archive, for CTAN dependencies. It's
+    /// recorded when the archive is first downloaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+fn source_id(dep: &Dependency) -> String {
+    match dep {
+        Dependency::Version(_) | Dependency::Ctan { .. } => "ctan".to_string(),
+        Dependency::Path { path, .. } => format!("path+{}", path.display()),
+        Dependency::Member { member } => format!("member+{}", member),
+        Dependency::Git { url, .. } => format!("git+{}", url),
+    }
+}
+
+/// Whether a dependency is locked at all. Git dependencies are cloned as they
+/// are, so there's nothing to lock them to yet.
+fn is_lockable(dep: &Dependency) -> bool {
+    !matches!(dep, Dependency::Git { .. })
+}
+
+impl LockedPackage {
+    /// Whether this entry is still a valid resolution of the dependency
+    fn locks(&self, dep: &Dependency) -> bool {
+        if self.source != source_id(dep) {
+            return false;
+        }
+        match dep {
+            Dependency::Version(req) | Dependency::Ctan { version: req } => {
+                req.matches(self.version.as_deref(), self.revision.as_deref())
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Lockfile {
+    pub fn try_read(root: &P<dirs::RootDir>) -> Result<Option<Self>> {
+        let lock_file: P<dirs::LockFile> = root.clone().extend(());
+        match dirs::LockFile::try_read(&lock_file)? {
+            Some(content) => Ok(Some(toml::from_str(&content)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write the lockfile, unless it already says the same thing
+    pub fn try_write(&self, root: &P<dirs::RootDir>) -> Result<()> {
+        let lock_file: P<dirs::LockFile> = root.clone().extend(());
+        let content = format!("{}{}", LOCKFILE_HEADER, toml::to_string(self)?);
+        if dirs::LockFile::try_read(&lock_file)?.as_deref() == Some(content.as_str()) {
+            return Ok(());
+        }
+        use typedir::WriteFile;
+        Ok(dirs::LockFile::replace(&lock_file, content)?)
+    }
+
+    pub fn get(&self, name: &DependencyName) -> Option<&LockedPackage> {
+        self.packages.iter().find(|pkg| pkg.name == name.as_ref())
+    }
+
+    /// Record the checksum of a dependency's archive, once it's been
+    /// downloaded
+    pub fn set_checksum(&mut self, name: &DependencyName, checksum: String) {
+        if let Some(pkg) = self
+            .packages
+            .iter_mut()
+            .find(|pkg| pkg.name == name.as_ref())
+        {
+            pkg.checksum = Some(checksum);
+        }
+    }
+
+    /// Drop a dependency's entry, returning whether there was one
+    pub fn remove(&mut self, name: &DependencyName) -> bool {
+        let len = self.packages.len();
+        self.packages.retain(|pkg| pkg.name != name.as_ref());
+        self.packages.len() != len
+    }
+
+    /// Whether the lockfile locks exactly the dependencies in the manifest
+    pub fn is_up_to_date(&self, deps: &Dependencies) -> bool {
+        let mut locked = 0;
+        for (name, dep) in deps.into_iter().filter(|(_, dep)| is_lockable(dep)) {
+            match self.get(name) {
+                Some(pkg) if pkg.locks(dep) => locked += 1,
+                _ => return false,
+            }
+        }
+        locked == self.packages.len()
+    }
+}
+
+/// Which lockfile entries to resolve afresh, even if they're still valid
+#[derive(Debug, Clone, Copy)]
+pub enum Update<'a> {
+    Nothing,
+    All,
+    Only(&'a DependencyName<'a>),
+}
+
+impl<'a> Update<'a> {
+    fn includes(&self, name: &DependencyName) -> bool {
+        match self {
+            Update::Nothing => false,
+            Update::All => true,
+            Update::Only(only) => *only == name,
+        }
+    }
+}
+
+/// Resolve the manifest's dependencies, keeping every entry of the previous
+/// lockfile that is still valid unless it is to be updated.
+pub async fn resolve(
+    deps: &Dependencies<'_>,
+    previous: Option<&Lockfile>,
+    update: Update<'_>,
+    force_vendor: bool,
+    registry: &RegistryConfig<'_>,
+    progress: Progress<'_>,
+) -> Result<Lockfile> {
+    // The entries that are kept, in manifest order, with gaps for the
+    // dependencies that have to be resolved
+    let mut kept = Vec::new();
+    let mut unresolved = Vec::new();
+    for (name, dep) in deps.into_iter().filter(|(_, dep)| is_lockable(dep)) {
+        let pkg = previous
+            .and_then(|lock| lock.get(name))
+            .filter(|pkg| pkg.locks(dep) && !update.includes(name));
+        match pkg {
+            Some(pkg) => kept.push(Some(pkg.clone())),
+            // Local dependencies don't need the network to resolve, and
+            // others can be resolved from the cache if they've been before
+            None if registry.offline
+                && !matches!(dep, Dependency::Path { .. } | Dependency::Member { .. })
+                && super::cache::try_read_metadata(name, None)?.is_none() =>
+            {
+                return Err(anyhow!(
+                    "dependency `{}` isn't locked in `{}`, so it can't be resolved in offline \
+                     mode; run once without `--offline` to resolve it",
+                    name,
+                    dirs::LOCK_FILE
+                ));
+            }
+            None => {
+                kept.push(None);
+                unresolved.push((name, dep));
+            }
+        }
+    }
+    let mut resolved = Vec::new().into_iter();
+    if !unresolved.is_empty() {
+        let client = WebClient::new(registry, progress)?;
+        let client = &client;
+        let resolutions = unresolved.into_iter().map(|(name, dep)| {
+            let resolution = resolve_dependency(client, name, dep, force_vendor);
+            (name, resolution)
+        });
+        resolved = super::try_join_bounded(client.jobs, resolutions)
+            .await?
+            .into_iter();
+    }
+    let packages = kept
+        .into_iter()
+        .map(|pkg| pkg.unwrap_or_else(|| resolved.next().expect("every gap was resolved")))
+        .collect();
+    Ok(Lockfile {
+        version: LOCKFILE_VERSION,
+        packages,
+    })
+}
+
+async fn resolve_dependency(
+    client: &WebClient<'_>,
+    name: &DependencyName<'_>,
+    dep: &Dependency<'_>,
+    force_vendor: bool,
+) -> Result<LockedPackage> {
+    let (version, revision, checksum) = match dep {
+        Dependency::Version(req) | Dependency::Ctan { version: req } => {
+            let meta = client.get_ctan_pkg_metadata(name).await?;
+            let version = meta.version.latest().map(str::to_string);
+            let revision = match req.revision() {
+                Some(rev) => Some(installed_revision(name, rev)?),
+                None => None,
+            };
+            if !req.matches(version.as_deref(), revision.as_deref()) {
+                return Err(anyhow!(
+                    "cannot resolve dependency `{}`: version `{}` is required, but CTAN has {}\n\
+                     CTAN only serves the latest version of each package; \
+                     require that version, or `*`, instead",
+                    name,
+                    req,
+                    version.map_or("no version".to_string(), |v| format!("`{}`", v)),
+                ));
+            }
+            // There's no archive to take a checksum of if the engine can
+            // already find the package
+            if !force_vendor && super::find_in_distribution(name).is_some() {
+                return Ok(LockedPackage {
+                    name: name.to_string(),
+                    source: source_id(dep),
+                    version,
+                    revision,
+                    checksum: None,
+                });
+            }
+            // CTAN's metadata has no checksums, so one is only taken here
+            // from an archive that's already been downloaded; otherwise it's
+            // recorded when the archive is installed
+            let checksum = match &version {
+                Some(version) => {
+                    super::cache::try_read(name, version, None)?.map(|payload| payload.checksum())
+                }
+                None => None,
+            };
+            (version, revision, checksum)
+        }
+        Dependency::Path { .. } | Dependency::Member { .. } => (None, None, None),
+        Dependency::Git { .. } => unreachable!("git dependencies aren't locked"),
+    };
+    Ok(LockedPackage {
+        name: name.to_string(),
+        source: source_id(dep),
+        version,
+        revision,
+        checksum,
+    })
+}
+
+/// The installed revision of a dependency that requires TeX Live revision
+/// `rev`, which has to match
+fn installed_revision(name: &DependencyName<'_>, rev: &str) -> Result<String> {
+    let installed = crate::probe::installed_package(name.as_ref())
+        .and_then(|pkg| pkg.revision)
+        .ok_or_else(|| {
+            anyhow!(
+                "cannot resolve dependency `{}`: TeX Live revision `r{}` is required, \
+                 but `tlmgr` doesn't know of an installed copy",
+                name,
+                rev
+            )
+        })?;
+    Ok(installed)
+}
+
+/// Check the copies of the dependencies installed in the TeX distribution
+/// against their requirements. Whatever `tlmgr` can't tell us is assumed to
+/// be fine, and so are dependencies with a copy in `deps_dir`, which the
+/// engine finds first.
+pub fn check_installed(deps: &Dependencies<'_>, deps_dir: &P<dirs::DepsDir>) -> Result<()> {
+    for (name, dep) in deps {
+        let (Dependency::Version(req) | Dependency::Ctan { version: req }) = dep else {
+            continue;
+        };
+        if let DependencyVersion::Any = req {
+            continue;
+        }
+        let stamp_file: P<dirs::DepStampFile> = deps_dir.clone().extend(name).extend(());
+        if stamp_file.exists() {
+            continue;
+        }
+        let Some(installed) = crate::probe::installed_package(name.as_ref()) else {
+            continue;
+        };
+        let known = match req.revision() {
+            Some(_) => installed.revision.is_some(),
+            None => installed.version.is_some(),
+        };
+        if known && !req.matches(installed.version.as_deref(), installed.revision.as_deref()) {
+            let found = match (req.revision(), &installed.version, &installed.revision) {
+                (Some(_), _, Some(rev)) => format!("revision `r{}`", rev),
+                (_, Some(version), _) => format!("version `{}`", version),
+                _ => unreachable!("the installed copy has a known version"),
+            };
+            return Err(anyhow!(
+                "dependency `{}` requires version `{}`, but the installed copy is {}",
+                name,
+                req,
+                found
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Bring the lockfile up to date with the manifest before a build. With
+/// `locked`, it is an error for the lockfile to need any change.
+pub async fn ensure(
+    root: &P<dirs::RootDir>,
+    deps: &Dependencies<'_>,
+    deps_dir: &P<dirs::DepsDir>,
+    locked: bool,
+    force_vendor: bool,
+    registry: &RegistryConfig<'_>,
+    progress: Progress<'_>,
+) -> Result<()> {
+    let previous = Lockfile::try_read(root)?;
+    if let Some(lock) = &previous {
+        if lock.is_up_to_date(deps) {
+            return Ok(());
+        }
+    }
+    if locked {
+        return Err(anyhow!(
+            "`{}` needs to be updated but `--locked` was passed",
+            dirs::LOCK_FILE
+        ));
+    }
+    // Asking `tlmgr` is slow, so the installed copies are only checked when
+    // the requirements change
+    if !force_vendor {
+        check_installed(deps, deps_dir)?;
+    }
+    let lock = resolve(
+        deps,
+        previous.as_ref(),
+        Update::Nothing,
+        force_vendor,
+        registry,
+        progress,
+    )
+    .await?;
+    lock.try_write(root)
+}
+
+/// Resolve dependencies afresh and rewrite the lockfile: all of them, or only
+/// the named one.
+pub async fn update(
+    root: &P<dirs::RootDir>,
+    deps: &Dependencies<'_>,
+    only: Option<&DependencyName<'_>>,
+    registry: &RegistryConfig<'_>,
+    progress: Progress<'_>,
+) -> Result<()> {
+    if registry.offline {
+        return Err(anyhow!(
+            "cannot update dependencies in offline mode; run without `--offline`"
+        ));
+    }
+    let update = match only {
+        Some(name) => {
+            if !deps.into_iter().any(|(dep_name, _)| dep_name == name) {
+                return Err(anyhow!("dependency `{}` not found", name));
+            }
+            Update::Only(name)
+        }
+        None => Update::All,
+    };
+    let previous = Lockfile::try_read(root)?;
+    let lock = resolve(deps, previous.as_ref(), update, false, registry, progress).await?;
+    lock.try_write(root)
+}
+
+/// A locked CTAN dependency that CTAN has a newer version of
+#[derive(Debug)]
+pub struct Outdated {
+    pub name: String,
+    pub locked: Option<String>,
+    pub latest: Option<String>,
+    /// Whether the newer version meets the manifest's requirement, so that
+    /// `largo update` would lock it
+    pub compatible: bool,
+}
+
+/// The locked CTAN dependencies, or only the named one, that CTAN has newer
+/// versions of. The lockfile isn't changed.
+pub async fn outdated(
+    root: &P<dirs::RootDir>,
+    deps: &Dependencies<'_>,
+    only: Option<&DependencyName<'_>>,
+    registry: &RegistryConfig<'_>,
+) -> Result<Vec<Outdated>> {
+    if registry.offline {
+        return Err(anyhow!(
+            "cannot check for newer versions in offline mode; run without `--offline`"
+        ));
+    }
+    if let Some(name) = only {
+        if !deps.into_iter().any(|(dep_name, _)| dep_name == name) {
+            return Err(anyhow!("dependency `{}` not found", name));
+        }
+    }
+    let Some(lock) = Lockfile::try_read(root)? else {
+        return Err(anyhow!(
+            "there is no `{}` yet; run `largo update` to create it",
+            dirs::LOCK_FILE
+        ));
+    };
+    let mut locked = Vec::new();
+    for (name, dep) in deps {
+        let (Dependency::Version(req) | Dependency::Ctan { version: req }) = dep else {
+            continue;
+        };
+        if only.is_some_and(|only| only != name) {
+            continue;
+        }
+        if let Some(pkg) = lock.get(name) {
+            locked.push((name, req, pkg));
+        }
+    }
+    if locked.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Only metadata is fetched, so there's no progress to show
+    let no_progress = |_: LargoInfo<'_>| ();
+    let client = WebClient::new(registry, &no_progress)?;
+    let client = &client;
+    let checks = locked.into_iter().map(|(name, req, pkg)| {
+        let check = async move {
+            let meta = client.get_ctan_pkg_metadata(name).await?;
+            let latest = meta.version.latest().map(str::to_string);
+            let outdated = (latest != pkg.version).then(|| Outdated {
+                name: name.to_string(),
+                locked: pkg.version.clone(),
+                compatible: req.matches(latest.as_deref(), pkg.revision.as_deref()),
+                latest,
+            });
+            Ok(outdated)
+        };
+        (name, check)
+    });
+    let outdated = super::try_join_bounded(client.jobs, checks).await?;
+    Ok(outdated.into_iter().flatten().collect())
+}
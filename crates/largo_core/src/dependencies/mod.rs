@@ -1,132 +1,800 @@
 //! Tools for finding, downloading, installing, etc., project dependencies.
 
 use crate::{
+    build::LargoInfo,
     conf::{self, Dependency, DependencyName},
-    Result,
+    dirs, Result,
 };
 
-use futures::stream::futures_unordered::FuturesUnordered;
+use anyhow::anyhow;
+use typedir::{Extend, FindRoot, PathBuf as P};
+
+use futures::StreamExt;
 
 use self::ctan::CtanLocation;
 
 pub type DependencyPath = std::path::PathBuf;
 
-pub mod ctan;
+/// Where the progress of each download is reported, as it happens
+pub type Progress<'p> = &'p (dyn Fn(LargoInfo<'_>) + Sync);
 
-#[allow(dead_code)]
-pub struct DependencyDownload<'a> {
-    name: &'a DependencyName<'a>,
-    payload: DependencyPayload,
-}
+/// How often to report the progress of a download
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+pub mod audit;
+pub mod cache;
+pub mod ctan;
+pub mod git;
+pub mod lock;
+mod unpack;
 
-#[allow(dead_code)]
 #[derive(Debug)]
 pub struct DependencyPayload {
     bytes: Vec<u8>,
     format: DownloadFormat,
 }
 
-#[derive(Debug)]
+impl DependencyPayload {
+    /// The SHA-256 digest of the archive, as recorded in the lockfile
+    fn checksum(&self) -> String {
+        use sha2::Digest;
+        crate::util::to_hex(&sha2::Sha256::digest(&self.bytes))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DownloadFormat {
+    /// The package's directory on CTAN, zipped up as it is
     Zip,
+    /// A TDS-compliant archive from CTAN's `install` tree, which unpacks into
+    /// a texmf tree
+    TdsZip,
 }
 
-pub fn get_dependency_paths(deps: &conf::Dependencies) -> Vec<DependencyPath> {
-    deps.into_iter()
-        .filter_map(|(_, dep)| match dep {
-            Dependency::Version(_) => unimplemented!(),
-            Dependency::Path { path, largo } => {
-                if *largo {
-                    unimplemented!("We don't yet support Largo dependencies");
-                }
-                let path: std::path::PathBuf = path.to_path_buf();
-                Some(path)
+/// The directories to search for a project's dependencies. Relative paths in
+/// the manifest are relative to the project's `root`; CTAN packages are in the
+/// profile's `deps_dir`, if they've been installed there.
+pub fn get_dependency_paths(
+    root: &std::path::Path,
+    deps_dir: &P<dirs::DepsDir>,
+    deps: &conf::Dependencies,
+) -> Result<Vec<DependencyPath>> {
+    let mut paths = Vec::new();
+    for (name, dep) in deps {
+        paths.extend(dependency_paths(root, deps_dir, name, dep)?);
+    }
+    Ok(paths)
+}
+
+/// The directories to search for a single dependency
+fn dependency_paths(
+    root: &std::path::Path,
+    deps_dir: &P<dirs::DepsDir>,
+    name: &DependencyName<'_>,
+    dep: &Dependency<'_>,
+) -> Result<Vec<DependencyPath>> {
+    Ok(match dep {
+        Dependency::Version(_) | Dependency::Ctan { .. } => {
+            let dep_dir: P<dirs::DepDir> = deps_dir.clone().extend(name);
+            let texmf: P<dirs::DepTexmfDir> = dep_dir.clone().extend(());
+            // A package with a texmf tree is found through `TEXMFHOME`,
+            // and one that isn't installed comes from the distribution
+            if texmf.exists() || !dep_dir.exists() {
+                vec![]
+            } else {
+                // Packages are unpacked as they are on CTAN, which may
+                // be in subdirectories
+                vec![format!("{}//", dep_dir.display()).into()]
+            }
+        }
+        Dependency::Path {
+            path,
+            largo,
+            texinputs,
+        } => {
+            let path = root.join(path);
+            if !texinputs.is_empty() {
+                texinputs.iter().map(|subdir| path.join(subdir)).collect()
+            } else if *largo {
+                // A largo project's packages are in its source directory;
+                // anything it builds is added by the build itself.
+                vec![path.join(dirs::SRC_DIR)]
+            } else {
+                vec![path.to_path_buf()]
+            }
+        }
+        Dependency::Member { member } => {
+            vec![workspace_member(root, member)?.join(dirs::SRC_DIR)]
+        }
+        Dependency::Git { largo, .. } => {
+            let dep_dir: P<dirs::DepDir> = deps_dir.clone().extend(name);
+            if *largo {
+                vec![dep_dir.join(dirs::SRC_DIR)]
+            } else {
+                vec![dep_dir.to_path_buf()]
             }
-            Dependency::Ctan { .. } => unimplemented!(),
-            Dependency::Git { .. } => unimplemented!(),
+        }
+    })
+}
+
+/// The root of the workspace member with this name: one of the `members` of
+/// the workspace `root` is in
+fn workspace_member(root: &std::path::Path, member: &str) -> Result<P<dirs::RootDir>> {
+    let workspace_root = dirs::WorkspaceRootDir::find_from(root).ok_or_else(|| {
+        anyhow!(
+            "cannot use workspace member `{}`: `{}` isn't in a workspace",
+            member,
+            root.display()
+        )
+    })?;
+    let workspace = conf::load_workspace(workspace_root.clone())?;
+    for path in &workspace.members {
+        let manifest = workspace_root.join(path).join(dirs::PROJECT_CONFIG_FILE);
+        // Members that can't be read are passed over
+        let Ok(member_root) = dirs::RootDir::from_manifest(&manifest) else {
+            continue;
+        };
+        let Ok((project, _warnings)) = conf::load_project(member_root) else {
+            continue;
+        };
+        if project.config.project.name == member {
+            return Ok(project.root);
+        }
+    }
+    Err(anyhow!(
+        "the workspace at `{}` has no member named `{}`",
+        workspace_root.display(),
+        member
+    ))
+}
+
+/// The texmf trees of the CTAN packages installed in the profile's
+/// `deps_dir` from TDS archives
+pub fn get_texmf_trees(
+    deps_dir: &P<dirs::DepsDir>,
+    deps: &conf::Dependencies,
+) -> Vec<DependencyPath> {
+    deps.into_iter()
+        .filter(|(_, dep)| matches!(dep, Dependency::Version(_) | Dependency::Ctan { .. }))
+        .map(|(name, _)| {
+            let texmf: P<dirs::DepTexmfDir> = deps_dir.clone().extend(name).extend(());
+            texmf.to_path_buf()
         })
+        .filter(|texmf| texmf.exists())
         .collect()
 }
 
-#[allow(unused)]
+/// The largo projects that a project depends on, directly or through each
+/// other, in an order in which they can be built: each after its own
+/// dependencies. Those in git repositories are cloned for the `profile` if
+/// they haven't been, so that their own dependencies can be found. The
+/// project's dependencies are built in the release profile, whichever profile
+/// it's built in, so that's where theirs are cloned.
+pub fn largo_dependencies(
+    project: &conf::Project,
+    profile: &conf::ProfileName<'_>,
+    registry: &conf::RegistryConfig<'_>,
+) -> Result<Vec<conf::Project<'static>>> {
+    let mut order = Vec::new();
+    let mut visiting = vec![project.root.to_path_buf()];
+    collect_largo_dependencies(project, profile, registry, &mut visiting, &mut order)?;
+    Ok(order)
+}
+
+fn collect_largo_dependencies(
+    project: &conf::Project,
+    profile: &conf::ProfileName<'_>,
+    registry: &conf::RegistryConfig<'_>,
+    visiting: &mut Vec<std::path::PathBuf>,
+    order: &mut Vec<conf::Project<'static>>,
+) -> Result<()> {
+    for (name, dep) in &project.config.dependencies {
+        let dir = match dep {
+            Dependency::Path {
+                path, largo: true, ..
+            } => project.root.join(path),
+            Dependency::Git { url, largo: true } => {
+                let target_dir: P<dirs::TargetDir> = project.root.clone().extend(());
+                let layout = project.config.project.deps_layout.unwrap_or_default();
+                let deps_dir = dirs::DepsDir::for_profile(&target_dir, profile, layout);
+                clone_git_dependency(&deps_dir, name, url, registry)?;
+                let dep_dir: P<dirs::DepDir> = deps_dir.extend(name);
+                dep_dir.to_path_buf()
+            }
+            Dependency::Member { member } => workspace_member(&project.root, member)?.to_path_buf(),
+            _ => continue,
+        };
+        let root = {
+            let manifest = dir.join(dirs::PROJECT_CONFIG_FILE);
+            dirs::RootDir::from_manifest(&manifest)
+                .map_err(|err| anyhow!("cannot use largo dependency `{}`: {}", name, err))?
+        };
+        let root_path = root.to_path_buf();
+        if visiting.contains(&root_path) {
+            return Err(anyhow!(
+                "largo dependency `{}` at `{}` depends on itself",
+                name,
+                root.display()
+            ));
+        }
+        if order
+            .iter()
+            .any(|built| built.root.to_path_buf() == root_path)
+        {
+            continue;
+        }
+        // Unknown keys are for the dependency's own authors to worry about
+        let (dep_project, _warnings) = conf::load_project(root)?;
+        visiting.push(root_path);
+        let release = conf::ProfileName::try_from(conf::RELEASE_PROFILE)?;
+        collect_largo_dependencies(&dep_project, &release, registry, visiting, order)?;
+        visiting.pop();
+        order.push(dep_project);
+    }
+    Ok(())
+}
+
+/// Where the engine can already find a package, if it can, going by its
+/// style or class file
+pub fn find_in_distribution(name: &conf::DependencyName<'_>) -> Option<std::path::PathBuf> {
+    ["sty", "cls"]
+        .into_iter()
+        .find_map(|ext| crate::probe::kpsewhich(&format!("{}.{}", name, ext)))
+}
+
+/// Unpack the locked version of each CTAN dependency into the profile's deps
+/// directory, from the download cache. Anything missing from the cache is
+/// downloaded into it first, except for packages the engine can already
+/// find, unless `force_vendor` is set. Returns a note about each package
+/// that was skipped that way.
+pub async fn install(
+    root: &P<dirs::RootDir>,
+    profile: &conf::ProfileName<'_>,
+    layout: conf::DepsLayout,
+    deps: &conf::Dependencies<'_>,
+    force_vendor: bool,
+    registry: &conf::RegistryConfig<'_>,
+    progress: Progress<'_>,
+) -> Result<Vec<String>> {
+    let mut notes = Vec::new();
+    let target_dir: P<dirs::TargetDir> = root.clone().extend(());
+    let deps_dir = dirs::DepsDir::for_profile(&target_dir, profile, layout);
+    for (name, dep) in deps {
+        if let Dependency::Git { url, .. } = dep {
+            clone_git_dependency(&deps_dir, name, url, registry)?;
+        }
+    }
+    let Some(lock) = lock::Lockfile::try_read(root)? else {
+        return Ok(notes);
+    };
+    // Work out what's missing before downloading anything
+    let mut missing = Vec::new();
+    let mut installs = Vec::new();
+    for (name, dep) in deps {
+        if !matches!(dep, Dependency::Version(_) | Dependency::Ctan { .. }) {
+            continue;
+        }
+        // Packages without a version can't be told apart in the cache
+        let Some(pkg) = lock.get(name) else {
+            continue;
+        };
+        let Some(version) = &pkg.version else {
+            continue;
+        };
+        let checksum = pkg.checksum.as_deref();
+        if !force_vendor {
+            if let Some(path) = find_in_distribution(name) {
+                notes.push(format!(
+                    "`{}` is already installed, at `{}`; pass `--force-vendor` to \
+                     download it anyway",
+                    name,
+                    path.display()
+                ));
+                continue;
+            }
+        }
+        if cache::try_read(name, version, checksum)?.is_none() {
+            if registry.offline {
+                return Err(anyhow!(
+                    "dependency `{}` version `{}` isn't in the download cache, so it can't be \
+                     installed in offline mode; run once without `--offline` to download it",
+                    name,
+                    version
+                ));
+            }
+            missing.push((name, version.as_str()));
+        }
+        installs.push((name, version.as_str(), checksum));
+    }
+    if !missing.is_empty() {
+        let client = WebClient::new(registry, progress)?;
+        let client = &client;
+        let fetches = missing
+            .into_iter()
+            .map(|(name, version)| (name, client.fetch_into_cache(name, version)));
+        try_join_bounded(client.jobs, fetches).await?;
+    }
+    // Archives downloaded for the first time have their checksums recorded
+    let mut checksums = Vec::new();
+    for (name, version, checksum) in installs {
+        let payload = cache::try_read(name, version, checksum)?.ok_or_else(|| {
+            anyhow!(
+                "the download of dependency `{}` doesn't match its checksum in `{}`",
+                name,
+                dirs::LOCK_FILE
+            )
+        })?;
+        if checksum.is_none() {
+            checksums.push((name, payload.checksum()));
+        }
+        unpack::unpack(&payload, &deps_dir.clone().extend(name))?;
+    }
+    if !checksums.is_empty() {
+        let mut lock = lock;
+        for (name, checksum) in checksums {
+            lock.set_checksum(name, checksum);
+        }
+        lock.try_write(root)?;
+    }
+    Ok(notes)
+}
+
+/// Clone a git dependency into the profile's deps directory, unless it's
+/// there already. Git dependencies aren't locked, so the clone is kept as it
+/// is until the dependency is purged.
+fn clone_git_dependency(
+    deps_dir: &P<dirs::DepsDir>,
+    name: &DependencyName<'_>,
+    url: &str,
+    registry: &conf::RegistryConfig<'_>,
+) -> Result<()> {
+    let dep_dir: P<dirs::DepDir> = deps_dir.clone().extend(name);
+    if dep_dir.exists() {
+        return Ok(());
+    }
+    if registry.offline {
+        return Err(anyhow!(
+            "git dependency `{}` hasn't been cloned, so it can't be installed in offline \
+             mode; run once without `--offline` to clone it",
+            name
+        ));
+    }
+    if let Some(parent) = dep_dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    git::clone(url, &dep_dir)
+        .map_err(|err| anyhow!("cannot install git dependency `{}`: {}", name, err))
+}
+
+/// Run a job for each dependency, at most `jobs` at a time, returning their
+/// results in order. If more than one fails, every failure is reported.
+async fn try_join_bounded<'a, T, F>(
+    jobs: usize,
+    tasks: impl IntoIterator<Item = (&'a DependencyName<'a>, F)>,
+) -> Result<Vec<T>>
+where
+    F: std::future::Future<Output = Result<T>>,
+{
+    let results: Vec<_> = futures::stream::iter(tasks)
+        .map(|(name, task)| async move { (name, task.await) })
+        .buffered(jobs)
+        .collect()
+        .await;
+    let mut values = Vec::new();
+    let mut failures = Vec::new();
+    for (name, result) in results {
+        match result {
+            Ok(value) => values.push(value),
+            Err(err) => failures.push((name, err)),
+        }
+    }
+    match failures.len() {
+        0 => Ok(values),
+        1 => Err(failures.remove(0).1),
+        n => Err(anyhow!(
+            "{} dependencies failed:\n  {}",
+            n,
+            failures
+                .iter()
+                .map(|(name, err)| format!("`{}`: {:#}", name, err))
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        )),
+    }
+}
+
+/// Remove a dependency from the project manifest. If `purge` is set, also
+/// delete whatever was fetched for it into each profile's deps directory.
+pub fn remove_dependency(
+    root: &P<dirs::RootDir>,
+    name: &DependencyName<'_>,
+    purge: bool,
+) -> Result<()> {
+    let manifest: P<dirs::ProjectConfigFile> = root.clone().extend(());
+    let mut doc = dirs::ProjectConfigFile::try_read_document(&manifest)?;
+    let removed = doc
+        .get_mut("dependencies")
+        .and_then(|deps| deps.as_table_like_mut())
+        .and_then(|deps| deps.remove(name.as_ref()));
+    if removed.is_none() {
+        return Err(anyhow!(
+            "dependency `{}` not found in `{}`",
+            name,
+            dirs::PROJECT_CONFIG_FILE
+        ));
+    }
+    dirs::ProjectConfigFile::try_write_document(&manifest, &doc)?;
+    if let Some(mut lock) = lock::Lockfile::try_read(root)? {
+        if lock.remove(name) {
+            lock.try_write(root)?;
+        }
+    }
+    if purge {
+        purge_dependency(root, name)?;
+    }
+    Ok(())
+}
+
+/// Add a CTAN dependency on any version of a package to the project manifest,
+/// unless there's already a dependency with its name. Returns whether it was
+/// added.
+pub fn add_dependency(root: &P<dirs::RootDir>, name: &DependencyName<'_>) -> Result<bool> {
+    let manifest: P<dirs::ProjectConfigFile> = root.clone().extend(());
+    let mut doc = dirs::ProjectConfigFile::try_read_document(&manifest)?;
+    let deps = doc
+        .as_table_mut()
+        .entry("dependencies")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("`dependencies` is not a table in the manifest"))?;
+    if deps.contains_key(name.as_ref()) {
+        return Ok(false);
+    }
+    deps.insert(name.as_ref(), toml_edit::value("*"));
+    dirs::ProjectConfigFile::try_write_document(&manifest, &doc)?;
+    Ok(true)
+}
+
+/// A CTAN package that might provide a file the engine couldn't find
+#[derive(Debug)]
+pub struct Suggestion {
+    pub name: String,
+    pub caption: String,
+}
+
+/// Look for a CTAN package named after a style or class file the engine
+/// couldn't find, as most packages are. CTAN can't be asked which package a
+/// file belongs to, so one that's named differently isn't found.
+pub async fn suggest_dependency(
+    file: &str,
+    registry: &conf::RegistryConfig<'_>,
+) -> Result<Option<Suggestion>> {
+    let Some(stem) = file
+        .strip_suffix(".sty")
+        .or_else(|| file.strip_suffix(".cls"))
+    else {
+        return Ok(None);
+    };
+    let Ok(name) = DependencyName::try_from(stem) else {
+        return Ok(None);
+    };
+    // Only metadata is fetched, so there's no progress to show
+    let no_progress = |_: LargoInfo<'_>| ();
+    let client = WebClient::new(registry, &no_progress)?;
+    match client.get_ctan_pkg_metadata(&name).await {
+        Ok(meta) => Ok(Some(Suggestion {
+            name: meta.id,
+            caption: meta.caption,
+        })),
+        Err(err)
+            if err
+                .downcast_ref::<reqwest::Error>()
+                .and_then(reqwest::Error::status)
+                == Some(reqwest::StatusCode::NOT_FOUND) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn purge_dependency(root: &P<dirs::RootDir>, name: &DependencyName<'_>) -> Result<()> {
+    let target_dir: P<dirs::TargetDir> = root.clone().extend(());
+    let shared_deps_dir: P<dirs::DepsDir> = target_dir.clone().extend(());
+    let shared_dep_dir: P<dirs::DepDir> = shared_deps_dir.extend(name);
+    dirs::remove_dir_all(&shared_dep_dir)?;
+    // Nothing has been fetched for a profile that's never been built
+    for profile in dirs::TargetDir::profile_names(&target_dir)? {
+        let profile_dir: P<dirs::ProfileTargetDir> = target_dir.clone().extend(&profile);
+        let deps_dir: P<dirs::DepsDir> = profile_dir.extend(());
+        let dep_dir: P<dirs::DepDir> = deps_dir.extend(name);
+        dirs::remove_dir_all(&dep_dir)?;
+    }
+    Ok(())
+}
+
+/// The certificates in a PEM bundle, each of which has to be added by itself
+fn pem_certificates(bundle: &str) -> impl Iterator<Item = &str> {
+    const END: &str = "-----END CERTIFICATE-----";
+    bundle
+        .split_inclusive(END)
+        .filter(|pem| pem.ends_with(END))
+        .map(str::trim)
+}
+
+/// The CTAN mirror used if none are configured
+const DEFAULT_CTAN_MIRROR: &str = "https://www.ctan.org/";
+
+/// How long to wait for a mirror to connect, or to send more of a response,
+/// if the configuration doesn't say, in seconds
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// How many times to retry a failing mirror if the configuration doesn't say
+const DEFAULT_RETRIES: u32 = 2;
+
+/// How many downloads to run at once if the configuration doesn't say
+const DEFAULT_JOBS: usize = 4;
+
+/// How long to keep package metadata if the configuration doesn't say, in
+/// seconds
+const DEFAULT_METADATA_TTL: u64 = 60 * 60;
+
+/// How long to wait before the first retry of a mirror. Each retry after
+/// that waits twice as long as the last.
+const RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Why an attempt at fetching from a mirror failed
+enum Failure {
+    /// The mirror may do better next time, or another one may
+    Retry(String),
+    /// No mirror will do better
+    Final(anyhow::Error),
+}
+
 pub struct WebClient<'w> {
     inner: reqwest::Client,
-    ctan_root_url: &'w str,
+    /// How long to wait for a mirror to send more of a response. A large
+    /// download may take much longer than this, as long as it keeps coming.
+    timeout: std::time::Duration,
+    /// CTAN mirrors, in the order they're tried
+    mirrors: Vec<String>,
+    /// How many times to retry a mirror before trying the next
+    retries: u32,
+    /// How many downloads to run at once
+    jobs: usize,
+    /// How long to keep package metadata
+    metadata_ttl: std::time::Duration,
+    /// Whether any use of the network is an error
+    offline: bool,
+    progress: Progress<'w>,
 }
 
 impl<'w> WebClient<'w> {
-    #[allow(dead_code)]
-    fn new() -> Result<Self> {
-        let inner = reqwest::Client::builder().build()?;
+    fn new(registry: &conf::RegistryConfig<'w>, progress: Progress<'w>) -> Result<Self> {
+        let timeout = std::time::Duration::from_secs(registry.timeout.unwrap_or(DEFAULT_TIMEOUT));
+        let mut builder = reqwest::Client::builder().connect_timeout(timeout);
+        // Without a proxy of our own, reqwest uses the one in the environment
+        if let Some(proxy) = &registry.proxy {
+            let proxy = reqwest::Proxy::all(proxy.as_ref())
+                .map_err(|err| anyhow!("invalid proxy `{}`: {}", proxy, err))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(ca_bundle) = &registry.ca_bundle {
+            let bundle = std::fs::read_to_string(ca_bundle.as_ref()).map_err(|err| {
+                anyhow!("cannot read CA bundle `{}`: {}", ca_bundle.display(), err)
+            })?;
+            for pem in pem_certificates(&bundle) {
+                let cert = reqwest::Certificate::from_pem(pem.as_bytes()).map_err(|err| {
+                    anyhow!("invalid certificate in `{}`: {}", ca_bundle.display(), err)
+                })?;
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        let inner = builder.build()?;
+        let mirrors = if registry.mirrors.is_empty() {
+            vec![DEFAULT_CTAN_MIRROR.to_string()]
+        } else {
+            registry
+                .mirrors
+                .iter()
+                .map(|mirror| mirror.to_string())
+                .collect()
+        };
         Ok(Self {
             inner,
-            ctan_root_url: "https://www.ctan.org/",
+            timeout,
+            mirrors,
+            retries: registry.retries.unwrap_or(DEFAULT_RETRIES),
+            jobs: registry.jobs.unwrap_or(DEFAULT_JOBS).max(1),
+            metadata_ttl: std::time::Duration::from_secs(
+                registry.metadata_ttl.unwrap_or(DEFAULT_METADATA_TTL),
+            ),
+            offline: registry.offline,
+            progress,
         })
     }
 
-    pub fn download_dependencies<'a>(
-        &'a self,
-        deps: &'a conf::Dependencies<'a>,
-    ) -> impl futures::stream::Stream<Item = Result<DependencyDownload<'a>>> {
-        let downloads: FuturesUnordered<_> = deps
-            .into_iter()
-            .map(|(name, spec)| self.download_dependency(name, spec))
-            .collect();
-        downloads
-    }
-
-    pub async fn download_dependency<'a>(
-        &'a self,
-        name: &'a DependencyName<'a>,
-        spec: &Dependency<'a>,
-    ) -> Result<DependencyDownload<'a>> {
-        match spec {
-            Dependency::Version(version) => self.download_ctan_dependency(name, version),
-            Dependency::Path { .. } => todo!(),
-            Dependency::Ctan { version } => self.download_ctan_dependency(name, version),
-            Dependency::Git { .. } => todo!(),
-        }
-        .await
-    }
-
-    pub async fn download_ctan_dependency<'a>(
-        &'a self,
-        name: &'a DependencyName<'a>,
-        version: &conf::DependencyVersion<'a>,
-    ) -> Result<DependencyDownload<'a>> {
-        let meta = self.get_ctan_pkg_metadata(name, version).await?;
-        let payload = match meta.ctan {
-            Some(ctan) => self.download_from_ctan_location(ctan).await,
-            None => Err(anyhow::anyhow!(
-                "package metadata contained no CTAN location"
-            )),
-        }?;
-        Ok(DependencyDownload { name, payload })
-    }
-
-    async fn get_ctan_pkg_metadata(
+    /// Fetch a path from the first mirror that answers. A mirror that times
+    /// out, can't be reached, has a server error, or breaks off its response
+    /// is retried a few times, then passed over for the next; any other error
+    /// is final. The download of a package's archive, named by `name`,
+    /// reports its progress.
+    async fn get(&self, path: &str, name: Option<&str>) -> Result<Vec<u8>> {
+        if self.offline {
+            return Err(anyhow!(
+                "cannot fetch `{}` from CTAN in offline mode; \
+                 run without `--offline` to allow network access",
+                path
+            ));
+        }
+        let mut failures = Vec::new();
+        for mirror in &self.mirrors {
+            let url = format!("{}/{}", mirror.trim_end_matches('/'), path);
+            let mut failure = None;
+            for attempt in 0..=self.retries {
+                if attempt > 0 {
+                    tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempt - 1)).await;
+                }
+                match self.try_get(&url, name).await {
+                    Ok(bytes) => return Ok(bytes),
+                    Err(Failure::Retry(msg)) => failure = Some(msg),
+                    Err(Failure::Final(err)) => return Err(err),
+                }
+            }
+            failures.extend(failure);
+        }
+        Err(anyhow!(
+            "no CTAN mirror could be reached:\n  {}",
+            failures.join("\n  ")
+        ))
+    }
+
+    /// Fetch a URL once, giving up if the mirror goes quiet for longer than
+    /// the timeout
+    async fn try_get(
         &self,
-        name: &DependencyName<'_>,
-        version: &conf::DependencyVersion<'_>,
-    ) -> Result<ctan::Package> {
-        let url = format!("{}/json/2.0/pkg/{}", &self.ctan_root_url, name);
-        let req = self.inner.get(url);
-        let req = match version {
-            conf::DependencyVersion::Any => req,
-            conf::DependencyVersion::Version(_version) => {
-                unimplemented!("It appears that CTAN doesn't actually provide past versions");
+        url: &str,
+        name: Option<&str>,
+    ) -> std::result::Result<Vec<u8>, Failure> {
+        let timed_out = || Failure::Retry(format!("{}: timed out", url));
+        let sent = tokio::time::timeout(self.timeout, self.inner.get(url).send()).await;
+        let mut response = match sent.map_err(|_| timed_out())? {
+            Ok(response) if response.status().is_server_error() => {
+                return Err(Failure::Retry(format!("{}: {}", url, response.status())));
             }
+            Ok(response) => response
+                .error_for_status()
+                .map_err(|err| Failure::Final(err.into()))?,
+            // The error names the URL itself
+            Err(err) if err.is_timeout() || err.is_connect() => {
+                return Err(Failure::Retry(err.to_string()));
+            }
+            Err(err) => return Err(Failure::Final(err.into())),
         };
-        let package = req.send().await?.json().await?;
+        let total = response.content_length();
+        let mut bytes = Vec::with_capacity(total.unwrap_or(0) as usize);
+        if let Some(name) = name {
+            (self.progress)(LargoInfo::Downloading {
+                name,
+                bytes: 0,
+                total,
+            });
+        }
+        let mut reported = std::time::Instant::now();
+        loop {
+            let chunk = tokio::time::timeout(self.timeout, response.chunk()).await;
+            let chunk = match chunk.map_err(|_| timed_out())? {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                // A response broken off partway may come through whole next time
+                Err(err) => return Err(Failure::Retry(err.to_string())),
+            };
+            bytes.extend_from_slice(&chunk);
+            if let Some(name) = name {
+                if reported.elapsed() >= PROGRESS_INTERVAL {
+                    reported = std::time::Instant::now();
+                    (self.progress)(LargoInfo::Downloading {
+                        name,
+                        bytes: bytes.len() as u64,
+                        total,
+                    });
+                }
+            }
+        }
+        if let Some(name) = name {
+            (self.progress)(LargoInfo::Downloaded {
+                name,
+                bytes: bytes.len() as u64,
+            });
+        }
+        Ok(bytes)
+    }
+
+    /// Download a locked version of a package into the download cache, which
+    /// can only be done while it's still the latest version
+    async fn fetch_into_cache(&self, name: &DependencyName<'_>, version: &str) -> Result<()> {
+        let meta = self.get_ctan_pkg_metadata(name).await?;
+        if meta.version.latest() != Some(version) {
+            return Err(anyhow!(
+                "version `{}` of dependency `{}` is no longer on CTAN; \
+                 run `largo update {}` to lock the latest version",
+                version,
+                name,
+                name
+            ));
+        }
+        let payload = self.download_package(name, meta).await?;
+        cache::try_store(name, version, &payload)
+    }
+
+    /// The metadata of the latest version of a package, which is the only
+    /// one CTAN provides. It's cached for a while, and offline, whatever is
+    /// in the cache will do.
+    async fn get_ctan_pkg_metadata(&self, name: &DependencyName<'_>) -> Result<ctan::Package> {
+        let ttl = (!self.offline).then_some(self.metadata_ttl);
+        if let Some(json) = cache::try_read_metadata(name, ttl)? {
+            // Metadata that can't be read is fetched again
+            if let Ok(package) = serde_json::from_str(&json) {
+                return Ok(package);
+            }
+        }
+        let path = format!("json/2.0/pkg/{}", name);
+        let json = String::from_utf8(self.get(&path, None).await?)?;
+        let package = serde_json::from_str(&json)?;
+        cache::try_store_metadata(name, &json)?;
         Ok(package)
     }
 
-    async fn download_from_ctan_location(&self, ctan: CtanLocation) -> Result<DependencyPayload> {
-        let url = format!("{}/tex-archive/{}.zip", self.ctan_root_url, ctan.path);
-        let bytes = self.inner.get(url).send().await?.bytes().await?.into();
-        Ok(DependencyPayload {
-            bytes,
-            format: DownloadFormat::Zip,
-        })
+    /// Download a package's archive: a TDS archive, if CTAN has one, or else
+    /// the package's directory as it is on CTAN
+    async fn download_package(
+        &self,
+        name: &DependencyName<'_>,
+        meta: ctan::Package,
+    ) -> Result<DependencyPayload> {
+        match (meta.install, meta.ctan) {
+            (Some(install), _) => {
+                let path = format!("install/{}", install.path.trim_start_matches('/'));
+                self.download(name, &path, DownloadFormat::TdsZip).await
+            }
+            (None, Some(ctan)) => self.download_from_ctan_location(name, ctan).await,
+            (None, None) => Err(anyhow!("package metadata contained no CTAN location")),
+        }
+    }
+
+    async fn download_from_ctan_location(
+        &self,
+        name: &DependencyName<'_>,
+        ctan: CtanLocation,
+    ) -> Result<DependencyPayload> {
+        let path = format!("tex-archive/{}.zip", ctan.path.trim_start_matches('/'));
+        self.download(name, &path, DownloadFormat::Zip).await
+    }
+
+    /// Download an archive, reporting its progress as it goes
+    async fn download(
+        &self,
+        name: &DependencyName<'_>,
+        path: &str,
+        format: DownloadFormat,
+    ) -> Result<DependencyPayload> {
+        let bytes = self.get(path, Some(name.as_ref())).await?;
+        Ok(DependencyPayload { bytes, format })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pem_bundles_are_split() {
+        let bundle = "# first\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+                      -----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        let certs: Vec<_> = pem_certificates(bundle).collect();
+        assert_eq!(certs.len(), 2);
+        assert!(certs[0].starts_with("# first"));
+        assert!(certs[1].starts_with("-----BEGIN CERTIFICATE-----\nBBBB"));
+        assert_eq!(pem_certificates("no certificates here").count(), 0);
     }
 }
@@ -0,0 +1,37 @@
+//! Unpacking downloaded archives into a profile's deps directory. A package
+//! with a TDS archive gets a texmf tree of its own, so that its fonts and
+//! other non-TeX files can be found; any other package is unpacked as it is
+//! and searched for on `TEXINPUTS`.
+
+use typedir::{Extend, PathBuf as P};
+
+use super::{DependencyPayload, DownloadFormat};
+use crate::{dirs, Result};
+
+/// Unpack an archive into a dependency's directory, replacing whatever was
+/// there, unless it's already been unpacked there
+pub fn unpack(payload: &DependencyPayload, dep_dir: &P<dirs::DepDir>) -> Result<()> {
+    let checksum = payload.checksum();
+    let stamp: P<dirs::DepStampFile> = dep_dir.clone().extend(());
+    if std::fs::read_to_string(&stamp).is_ok_and(|stamped| stamped == checksum) {
+        return Ok(());
+    }
+    if dep_dir.exists() {
+        dirs::remove_dir_all(dep_dir)?;
+    }
+    let dest = match payload.format {
+        DownloadFormat::Zip => dep_dir.to_path_buf(),
+        DownloadFormat::TdsZip => {
+            let texmf: P<dirs::DepTexmfDir> = dep_dir.clone().extend(());
+            texmf.to_path_buf()
+        }
+    };
+    // Packages can be deeply nested, and deps directories already are
+    let dest = crate::platform::extended_length(&dest);
+    std::fs::create_dir_all(&dest)?;
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(&payload.bytes))?;
+    archive.extract(&dest)?;
+    // Written last, so that an interrupted unpacking is done again
+    std::fs::write(&stamp, checksum)?;
+    Ok(())
+}
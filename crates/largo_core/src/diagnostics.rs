@@ -0,0 +1,152 @@
+//! Categories of the errors and warnings that engines report, each with a code
+//! that `largo explain` describes at length, in the manner of `rustc
+//! --explain`.
+
+/// A category of errors or warnings
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    /// A one-line description
+    pub summary: &'static str,
+    /// A longer description, with common causes and fixes
+    pub explanation: &'static str,
+    /// Whether an engine message belongs to this category
+    matches: fn(&str) -> bool,
+}
+
+macro_rules! explanation {
+    ($code:literal) => {
+        include_str!(concat!("diagnostics/", $code, ".md"))
+    };
+}
+
+pub const DIAGNOSTICS: &[Diagnostic] = &[
+    Diagnostic {
+        code: "E0001",
+        summary: "undefined control sequence",
+        explanation: explanation!("E0001"),
+        matches: |msg| msg.starts_with("Undefined control sequence"),
+    },
+    Diagnostic {
+        code: "E0002",
+        summary: "file or package not found",
+        explanation: explanation!("E0002"),
+        matches: |msg| missing_file(msg).is_some(),
+    },
+    Diagnostic {
+        code: "E0003",
+        summary: "missing `$` inserted",
+        explanation: explanation!("E0003"),
+        matches: |msg| msg.starts_with("Missing $ inserted"),
+    },
+    Diagnostic {
+        code: "E0004",
+        summary: "mismatched environment",
+        explanation: explanation!("E0004"),
+        matches: |msg| msg.contains(r"Error: \begin{") && msg.contains(r"ended by \end{"),
+    },
+    Diagnostic {
+        code: "E0005",
+        summary: r"missing `\begin{document}`",
+        explanation: explanation!("E0005"),
+        matches: |msg| msg.contains(r"Error: Missing \begin{document}"),
+    },
+    Diagnostic {
+        code: "E0006",
+        summary: "paragraph ended before argument was complete",
+        explanation: explanation!("E0006"),
+        matches: |msg| msg.starts_with("Paragraph ended before"),
+    },
+    Diagnostic {
+        code: "E0007",
+        summary: "emergency stop",
+        explanation: explanation!("E0007"),
+        matches: |msg| msg.starts_with("Emergency stop"),
+    },
+    Diagnostic {
+        code: "W0001",
+        summary: "undefined reference",
+        explanation: explanation!("W0001"),
+        matches: |msg| msg.starts_with("Reference `") && msg.contains("undefined"),
+    },
+    Diagnostic {
+        code: "W0002",
+        summary: "undefined citation",
+        explanation: explanation!("W0002"),
+        matches: |msg| msg.starts_with("Citation `") && msg.contains("undefined"),
+    },
+    Diagnostic {
+        code: "W0003",
+        summary: "bad box",
+        explanation: explanation!("W0003"),
+        matches: |msg| msg.starts_with("Overfull \\") || msg.starts_with("Underfull \\"),
+    },
+];
+
+/// The category of an engine message, if it has one
+pub fn classify(msg: &str) -> Option<&'static Diagnostic> {
+    DIAGNOSTICS.iter().find(|diag| (diag.matches)(msg))
+}
+
+/// The file named by an error like ``LaTeX Error: File `foo.sty' not found``
+pub fn missing_file(msg: &str) -> Option<&str> {
+    let (_, rest) = msg.split_once("Error: File `")?;
+    let (file, rest) = rest.split_once('\'')?;
+    rest.trim_start().starts_with("not found").then_some(file)
+}
+
+/// The label or citation key named by a warning like ``Reference `fig:plot'
+/// on page 2 undefined``, and whether it's a citation
+pub fn undefined_key(msg: &str) -> Option<(&str, bool)> {
+    let (is_citation, rest) = match msg.strip_prefix("Reference `") {
+        Some(rest) => (false, rest),
+        None => (true, msg.strip_prefix("Citation `")?),
+    };
+    let (key, rest) = rest.split_once('\'')?;
+    rest.contains("undefined").then_some((key, is_citation))
+}
+
+/// The category with this code, which is case-insensitive
+pub fn lookup(code: &str) -> Option<&'static Diagnostic> {
+    DIAGNOSTICS
+        .iter()
+        .find(|diag| diag.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_unique() {
+        for (i, diag) in DIAGNOSTICS.iter().enumerate() {
+            assert!(DIAGNOSTICS[i + 1..].iter().all(|d| d.code != diag.code));
+        }
+    }
+
+    #[test]
+    fn messages_are_classified() {
+        let code = |msg| classify(msg).map(|diag| diag.code);
+        assert_eq!(
+            code("LaTeX Error: File `foo.sty' not found."),
+            Some("E0002")
+        );
+        assert_eq!(
+            code(r"Overfull \hbox (1.5pt too wide) in paragraph"),
+            Some("W0003")
+        );
+        assert_eq!(code("Something else entirely"), None);
+        assert_eq!(
+            missing_file("LaTeX Error: File `foo.sty' not found."),
+            Some("foo.sty")
+        );
+        assert_eq!(
+            undefined_key("Citation `knuth84' on page 1 undefined on input line 3."),
+            Some(("knuth84", true))
+        );
+        assert_eq!(
+            undefined_key("Reference `fig:plot' on page 2 undefined"),
+            Some(("fig:plot", false))
+        );
+    }
+}
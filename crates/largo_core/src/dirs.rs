@@ -1,23 +1,62 @@
 use crate::conf;
+use crate::files::placeholders::Placeholders;
+use crate::files::templates::DocumentTemplate;
 use anyhow::{anyhow, Result};
-use typedir::{path, pathref, AsPath, Extend, PathBuf as P, PathRef as R};
+use typedir::{
+    path, pathref, AsPath, CreateDir, Extend, FindRoot, PathBuf as P, PathRef as R, ReadFile,
+    WriteFile,
+};
 
 // Project
 pub const SRC_DIR: &str = "src";
 pub const MAIN_FILE: &str = "main.tex";
+/// The main file of a package written in docstrip's `.dtx` format, which is
+/// built into its documentation
+pub const DTX_MAIN_FILE: &str = "main.dtx";
+pub const CHAPTERS_DIR: &str = "chapters";
+pub const THEMES_DIR: &str = "themes";
 pub const TARGET_DIR: &str = "target";
 pub const BUILD_DIR: &str = "build";
+pub const CHECK_DIR: &str = "check";
 pub const START_FILE: &str = "_start.tex";
+/// The package that defines the Largo macros, generated for each build
+pub const LARGO_VARS_PACKAGE: &str = "largo-vars";
+pub const LARGO_VARS_FILE: &str = "largo-vars.sty";
 pub const DEPS_DIR: &str = "deps";
+pub const DEP_TEXMF_DIR: &str = "texmf";
+pub const DEP_STAMP_FILE: &str = ".largo-archive";
+pub const TESTS_DIR: &str = "tests";
+pub const FIGURES_DIR: &str = "figures";
+pub const BIB_DIR: &str = "bib";
+pub const DATA_DIR: &str = "data";
 pub const PROJECT_CONFIG_FILE: &str = "largo.toml";
 pub const LOCK_FILE: &str = "largo.lock";
 pub const GITIGNORE: &str = ".gitignore";
 pub const GIT_DIR: &str = ".git";
 pub const CACHEDIR_TAG_FILE: &str = "CACHEDIR.TAG";
+pub const MAKEFILE: &str = "Makefile";
+pub const VSCODE_DIR: &str = ".vscode";
+pub const VSCODE_SETTINGS_FILE: &str = "settings.json";
+/// The extension of a TeXstudio session, which is named for the project
+pub const TEXSTUDIO_SESSION_EXTENSION: &str = "txss";
 
 // Largo
 pub const CONFIG_DIR: &str = ".largo";
 pub const LARGO_CONFIG_FILE: &str = "config.toml";
+pub const GITIGNORE_TEMPLATE_FILE: &str = "gitignore";
+pub const TEMPLATES_DIR: &str = "templates";
+pub const TEMPLATE_MANIFEST_FILE: &str = "template.toml";
+/// Largo's directory within the platform's configuration directory
+pub const PLATFORM_CONFIG_DIR: &str = "largo";
+pub const CACHE_DIR: &str = "cache";
+pub const BUILD_CACHE_DIR: &str = "builds";
+pub const CTAN_CACHE_DIR: &str = "ctan";
+pub const CTAN_METADATA_FILE: &str = "metadata.json";
+
+// Freedesktop trash
+pub const TRASH_DIR: &str = "Trash";
+pub const TRASH_FILES_DIR: &str = "files";
+pub const TRASH_INFO_DIR: &str = "info";
 
 /// Strongly-typed file contents
 pub struct ContentString<N: typedir::Node>(String, std::marker::PhantomData<N>);
@@ -36,37 +75,176 @@ impl<N: typedir::Node> AsRef<str> for ContentString<N> {
     }
 }
 
+impl<N: ReadFile> ContentString<N> {
+    pub fn try_read<P: AsPath<N>>(path: &P) -> Result<Self> {
+        let content = N::read_to_string(path)?;
+        Ok(Self(content, std::marker::PhantomData))
+    }
+}
+
 typedir::typedir! {
     node RootDir {
-        PROJECT_CONFIG_FILE => node ProjectConfigFile;
-        LOCK_FILE => node LockFile;
-        SRC_DIR => node SrcDir {
-            forall s: &str, s => node SrcFile;
+        PROJECT_CONFIG_FILE => use ProjectConfigFile;
+        LOCK_FILE => node LockFile: ReadFile + WriteFile;
+        SRC_DIR => node SrcDir: CreateDir + Required {
+            THEMES_DIR => node ThemesDir: CreateDir {
+                forall s: &str, s => node ThemeFile;
+            };
+            /// The chapters of a book or report, each in its own file
+            CHAPTERS_DIR => node ChaptersDir: CreateDir {
+                forall s: &str, s => node ChapterFile: WriteFile;
+            };
+            forall s: &str, s => node SrcFile: WriteFile;
         };
-        TARGET_DIR => node TargetDir {
-            CACHEDIR_TAG_FILE => node CachedirTagFile;
+        TESTS_DIR => node TestsDir: CreateDir {
+            forall s: &str, s => node TestFile: WriteFile;
+        };
+        /// Images, found by `\includegraphics` through `TEXINPUTS`
+        FIGURES_DIR => node FiguresDir: CreateDir;
+        /// Bibliography databases, found by the bibliography processor through
+        /// `BIBINPUTS`
+        BIB_DIR => node BibDir: CreateDir;
+        /// Data files, such as tables and plots read by the document, also
+        /// found through `TEXINPUTS`
+        DATA_DIR => node DataDir: CreateDir;
+        TARGET_DIR => node TargetDir: CreateDir {
+            CACHEDIR_TAG_FILE => node CachedirTagFile: WriteFile;
+            /// Dependencies shared by every profile, with
+            /// `deps-layout = "shared"`
+            DEPS_DIR => use DepsDir;
             forall s: &crate::conf::ProfileName<'_>, s.as_ref() => node ProfileTargetDir {
-                DEPS_DIR => node DepsDir;
-                BUILD_DIR => node BuildDir {
-                    START_FILE => node StartFile;
+                DEPS_DIR => use DepsDir;
+                TESTS_DIR => node TestsTargetDir {
+                    forall s: &str, s => node TestBuildDir;
                 };
+                CHECK_DIR => node CheckDir;
+                BUILD_DIR => use BuildDir;
             };
         };
         GIT_DIR => node GitDir;
-        GITIGNORE => node Gitignore;
+        GITIGNORE => node Gitignore: WriteFile;
+        /// A Makefile written by `largo eject`
+        MAKEFILE => node Makefile: ReadFile + WriteFile;
+        /// Settings for VS Code's LaTeX Workshop, made by `largo new --editor`
+        VSCODE_DIR => node VscodeDir: CreateDir {
+            VSCODE_SETTINGS_FILE => node VscodeSettingsFile: WriteFile;
+        };
+        /// A TeXstudio session, made by `largo new --editor`
+        forall s: &str, s => node TexstudioSessionFile: WriteFile;
+        forall s: &std::path::Path, s => use OutputDir;
+    };
+
+    /// A directory of the user's own that finished documents are copied
+    /// into, by `output-dir`. Nothing else in it is largo's.
+    node OutputDir {
+        forall s: &str, s => node ExportedFile;
+        /// Each of a project's documents is copied into a directory of its
+        /// own, as it's built in one
+        forall s: &conf::DocumentName<'_>, s.as_ref() => use OutputDir;
+    };
+
+    /// A directory whose manifest has a `[workspace]` table, which may also be
+    /// a project's root
+    node WorkspaceRootDir {
+        PROJECT_CONFIG_FILE => use ProjectConfigFile;
+    };
+
+    /// A project's manifest, which may also be a workspace's
+    node ProjectConfigFile: ReadFile + WriteFile + Required;
+
+    /// A profile's dependencies, or with `deps-layout = "shared"`, every
+    /// profile's
+    node DepsDir {
+        forall s: &crate::conf::DependencyName<'_>, s.as_ref() => node DepDir {
+            /// Packages from TDS archives are unpacked into a texmf tree of
+            /// their own
+            DEP_TEXMF_DIR => node DepTexmfDir;
+            /// The checksum of the archive that was unpacked here
+            DEP_STAMP_FILE => node DepStampFile;
+        };
+    };
+
+    /// A profile's build directory, or one of its documents'
+    node BuildDir {
+        START_FILE => node StartFile;
+        LARGO_VARS_FILE => node LargoVarsFile;
+        forall s: &str, s => node BuildFile;
+        forall s: &str, s => node ArtifactFile;
+        /// Each of a project's documents is built in its own build directory,
+        /// nested in the profile's build directory
+        forall s: &conf::DocumentName<'_>, s.as_ref() => use BuildDir;
     };
 
     node HomeDir {
         CONFIG_DIR => node LargoConfigDir {
-            LARGO_CONFIG_FILE => node LargoConfigFile;
+            LARGO_CONFIG_FILE => node LargoConfigFile: ReadFile + WriteFile;
+            /// Rules added to the `.gitignore` of every new project
+            GITIGNORE_TEMPLATE_FILE => node GitignoreTemplateFile: ReadFile;
+            /// The user's own project templates, used by `largo new --template`
+            TEMPLATES_DIR => node TemplatesDir {
+                forall s: &str, s => use UserTemplateDir;
+            };
+            CACHE_DIR => node CacheDir {
+                BUILD_CACHE_DIR => node BuildCacheDir {
+                    forall s: &str, s => node CachedBuildDir {
+                        forall s: &str, s => node CachedBuildFile;
+                    };
+                };
+                /// Templates cloned from git repositories, while they're used
+                TEMPLATES_DIR => node TemplateCacheDir {
+                    forall s: &str, s => use UserTemplateDir;
+                };
+                CTAN_CACHE_DIR => node CtanCacheDir {
+                    forall s: &crate::conf::DependencyName<'_>, s.as_ref() => node CachedPackageDir {
+                        /// The package's metadata, as CTAN last gave it
+                        CTAN_METADATA_FILE => node CachedMetadataFile;
+                        forall s: &str, s => node CachedVersionDir {
+                            forall s: &str, s => node CachedArchiveFile;
+                        };
+                    };
+                };
+            };
+        };
+    };
+
+    /// One of the user's own project templates, or one cloned from a git
+    /// repository
+    node UserTemplateDir {
+        TEMPLATE_MANIFEST_FILE => node TemplateManifestFile: ReadFile + Required;
+        /// Everything else is copied into the new project
+        forall s: &std::path::Path, s => node TemplateFile: ReadFile;
+    };
+
+    /// `$XDG_CONFIG_HOME`, `~/.config`, or `%APPDATA%`
+    node PlatformConfigDir {
+        PLATFORM_CONFIG_DIR => node PlatformLargoConfigDir {
+            LARGO_CONFIG_FILE => node PlatformLargoConfigFile;
+        };
+    };
+
+    /// `$XDG_DATA_HOME/Trash`, or `~/.local/share/Trash`
+    node TrashDir {
+        TRASH_FILES_DIR => node TrashFilesDir: CreateDir {
+            forall s: &str, s => node TrashedFile;
+        };
+        /// Where each trashed file came from, and when, in a `.trashinfo` file
+        /// named after it
+        TRASH_INFO_DIR => node TrashInfoDir: CreateDir {
+            forall s: &str, s => node TrashInfoFile: WriteFile;
         };
     };
 }
 
 pub enum ProjectKind {
     Package,
+    /// A package written in docstrip's `.dtx` format, with its documentation
+    DtxPackage,
     Class,
-    Document,
+    /// A package providing a Beamer theme
+    BeamerTheme,
+    Document(crate::files::templates::DocumentTemplate),
+    /// A document from one of the user's own templates
+    User(crate::files::templates::user::UserTemplate),
 }
 
 pub struct NewProject<'a> {
@@ -74,60 +252,181 @@ pub struct NewProject<'a> {
     pub name: &'a str,
     /// What kind of project is this?
     pub kind: ProjectKind,
+    /// The theme of a Beamer presentation
+    pub theme: Option<&'a str>,
+    /// The document's title, which is otherwise its name
+    pub title: Option<&'a str>,
+    /// The document's author
+    pub author: Option<&'a str>,
+    /// The editor to set the project up for, if any
+    pub editor: Option<crate::files::editors::Editor>,
+    /// Packages that a new document loads and depends on, besides those of
+    /// its template
+    pub packages: &'a [conf::DependencyName<'a>],
 }
 
+/// The author of a document that wasn't given one
+const DEFAULT_AUTHOR: &str = "Author McAuthorface";
+
 impl<'a> NewProject<'a> {
-    fn project_toml(&self) -> conf::ProjectConfig {
+    fn project_toml(&self) -> conf::ProjectConfig<'_> {
         let package = match self.kind {
-            ProjectKind::Package => Some(conf::PackageConfig::default()),
+            ProjectKind::Package | ProjectKind::DtxPackage | ProjectKind::BeamerTheme => {
+                Some(conf::PackageConfig::default())
+            }
             _ => None,
         };
+        // Classes like Beamer come with every TeX distribution, so only the
+        // packages asked for are dependencies
+        let mut dependencies = conf::Dependencies::new();
+        if let ProjectKind::Document(_) = self.kind {
+            for package in self.packages {
+                dependencies.insert(
+                    package.clone(),
+                    conf::Dependency::Version(conf::DependencyVersion::Any),
+                );
+            }
+        }
         let class = match self.kind {
             ProjectKind::Class => Some(conf::ClassConfig::default()),
             _ => None,
         };
-        conf::ProjectConfig {
-            project: conf::ProjectConfigHead {
-                name: self.name,
-                system_settings: conf::SystemSettings::default(),
-                project_settings: conf::ProjectSettings::default(),
-            },
-            package,
-            class,
-            profiles: None,
-            dependencies: conf::Dependencies::new(),
+        let project_settings = match self.kind {
+            ProjectKind::Document(template) => template.project_settings(),
+            _ => conf::ProjectSettings::default(),
+        };
+        let system_settings = conf::SystemSettings::default();
+        let mut config = conf::ProjectConfig::new(self.name);
+        // The documentation is built from the package's source
+        if let ProjectKind::DtxPackage = self.kind {
+            config.project.main = Some(DTX_MAIN_FILE.into());
+        }
+        config.project.system_settings = system_settings;
+        config.project.project_settings = project_settings;
+        config.package = package;
+        config.class = class;
+        config.dependencies = dependencies;
+        config
+    }
+
+    fn beamer_template(&self) -> crate::files::templates::beamer::BeamerTemplate<'a> {
+        crate::files::templates::beamer::BeamerTemplate::new(self.theme)
+    }
+
+    /// The values of the placeholders in the project's files. Those the
+    /// project was given come first, then those of its template, and then
+    /// the defaults.
+    fn placeholders(&self) -> Placeholders {
+        let mut placeholders = Placeholders::new(self.name);
+        if let Some(title) = self.title {
+            placeholders.set("title", title);
+        }
+        if let Some(author) = self.author {
+            placeholders.set("author", author);
+        }
+        if let ProjectKind::User(template) = &self.kind {
+            template.set_defaults(&mut placeholders);
+        }
+        placeholders.set_default("title", self.name);
+        placeholders.set_default("author", DEFAULT_AUTHOR);
+        placeholders
+    }
+
+    /// Create the files that set an editor up to build the project
+    fn try_create_editor_settings(
+        &self,
+        root: &mut P<RootDir>,
+        editor: crate::files::editors::Editor,
+        placeholders: &Placeholders,
+    ) -> Result<()> {
+        use crate::files::editors::Editor;
+        let project_toml = self.project_toml();
+        let settings = editor.settings(placeholders, project_toml.project.main_file());
+        match editor {
+            Editor::Vscode => {
+                let vscode_dir: R<VscodeDir> = root.extend(());
+                VscodeDir::create_dir(&vscode_dir)?;
+                let settings_file: R<VscodeSettingsFile> = vscode_dir.extend(());
+                try_create_file(&settings_file, settings.as_bytes())
+            }
+            Editor::TeXstudio => {
+                let name = format!("{}.{}", self.name, TEXSTUDIO_SESSION_EXTENSION);
+                let session_file: R<TexstudioSessionFile> = root.extend(name.as_str());
+                try_create_file(&session_file, settings.as_bytes())
+            }
         }
     }
 
-    /// Create a `main.tex`, `abc.sty`, or `xyz.cls`
-    fn try_create_src_file(&self, src_dir: &mut R<SrcDir>) -> Result<()> {
+    /// Create a `main.tex`, `abc.sty`, `<name>.cls`, or `main.dtx`
+    fn try_create_src_file(
+        &self,
+        src_dir: &mut R<SrcDir>,
+        placeholders: &Placeholders,
+    ) -> Result<()> {
         match self.kind {
             ProjectKind::Package => {
                 let src_file: R<SrcFile> = src_dir.extend("main.sty");
                 let template = crate::files::packages::PackageTemplate::new(&self.name.into());
-                try_create(
-                    &src_file,
-                    ToCreate::File(format!("{}", template).as_bytes()),
-                )
+                try_create_file(&src_file, format!("{}", template).as_bytes())
+            }
+            ProjectKind::DtxPackage => {
+                let template = crate::files::packages::DtxTemplate::new(&self.name.into());
+                {
+                    let ins_file: R<SrcFile> = src_dir.extend("main.ins");
+                    try_create_file(&ins_file, template.ins(placeholders).as_bytes())?;
+                }
+                let dtx_file: R<SrcFile> = src_dir.extend(DTX_MAIN_FILE);
+                try_create_file(&dtx_file, template.dtx(placeholders).as_bytes())
             }
             ProjectKind::Class => {
-                let src_file: R<SrcFile> = src_dir.extend("main.cls");
+                // Named for the class, so that documents can load it
+                let src_file: R<SrcFile> = src_dir.extend(format!("{}.cls", self.name).as_str());
                 let template = crate::files::packages::ClassTemplate::new(&self.name.into());
-                try_create(
-                    &src_file,
-                    ToCreate::File(format!("{}", template).as_bytes()),
-                )
+                try_create_file(&src_file, format!("{}", template).as_bytes())
+            }
+            ProjectKind::BeamerTheme => {
+                let package_name = format!("beamertheme{}", self.name);
+                let src_file: R<SrcFile> = src_dir.extend(format!("{}.sty", package_name).as_str());
+                let template =
+                    crate::files::packages::BeamerThemeTemplate::new(&package_name.as_str().into());
+                try_create_file(&src_file, format!("{}", template).as_bytes())
             }
-            ProjectKind::Document => {
+            ProjectKind::Document(template) => {
+                if template == DocumentTemplate::Beamer {
+                    let themes_dir: R<ThemesDir> = src_dir.extend(());
+                    ThemesDir::create_dir(&themes_dir)?;
+                }
+                let chapters = template.chapters();
+                if !chapters.is_empty() {
+                    let mut chapters_dir: R<ChaptersDir> = src_dir.extend(());
+                    ChaptersDir::create_dir(&chapters_dir)?;
+                    for (name, contents) in chapters {
+                        let chapter_file: R<ChapterFile> = (&mut chapters_dir).extend(*name);
+                        try_create_file(&chapter_file, placeholders.fill(contents).as_bytes())?;
+                    }
+                }
                 let src_file: R<SrcFile> = src_dir.extend("main.tex");
-                try_create(
-                    &src_file,
-                    ToCreate::File(crate::files::MAIN_LATEX.as_bytes()),
-                )
+                let main_file = match template {
+                    DocumentTemplate::Beamer => self.beamer_template().to_string(),
+                    _ => template.main_file(),
+                };
+                let main_file = crate::files::templates::with_packages(&main_file, self.packages);
+                try_create_file(&src_file, placeholders.fill(&main_file).as_bytes())
+            }
+            // Only if the template has no sources of its own
+            ProjectKind::User(_) => {
+                let src_file: R<SrcFile> = src_dir.extend("main.tex");
+                let main_file = DocumentTemplate::default().main_file();
+                try_create_file(&src_file, placeholders.fill(&main_file).as_bytes())
             }
         }
     }
 
+    /// Whether the user's template already provided this file
+    fn template_provides(&self, path: &std::path::Path) -> bool {
+        matches!(self.kind, ProjectKind::User(_)) && path.exists()
+    }
+
     /// Initialize a largo project directory at the passed root
     pub fn init(self, root: std::path::PathBuf) -> Result<()> {
         // NOTE: This is *extremely* verbose without some kind of "pop-on-drop"
@@ -139,24 +438,53 @@ impl<'a> NewProject<'a> {
             .arg("init")
             .arg(root.as_os_str())
             .output()?;
+        let placeholders = self.placeholders();
+        // A user's template comes first, and gets the defaults for whatever
+        // it leaves out
+        if let ProjectKind::User(template) = &self.kind {
+            template.instantiate(&root, &placeholders)?;
+        }
         // Project config file
         {
             let proj_conf = pathref!(root => ProjectConfigFile);
-            ProjectConfigFile::try_create(&proj_conf, &self.project_toml())?;
+            if !self.template_provides(&proj_conf) {
+                ProjectConfigFile::try_create(&proj_conf, &self.project_toml())?;
+            }
         }
         // Gitignore
         {
             let gitignore = pathref!(root => Gitignore);
-            try_create(
-                &gitignore,
-                ToCreate::File(crate::files::GITIGNORE.as_bytes()),
-            )?;
+            if !self.template_provides(&gitignore) {
+                let user_rules = GitignoreTemplateFile::try_read()?;
+                let contents = crate::files::gitignore(user_rules.as_deref());
+                try_create_file(&gitignore, contents.as_bytes())?;
+            }
+        }
+        // Editor settings
+        if let Some(editor) = self.editor {
+            self.try_create_editor_settings(&mut root, editor, &placeholders)?;
         }
         // Source
         {
             let mut src_dir = pathref!(root => SrcDir);
-            try_create(&src_dir, ToCreate::Dir)?;
-            self.try_create_src_file(&mut src_dir)?;
+            if !self.template_provides(&src_dir) {
+                SrcDir::create_dir(&src_dir)?;
+                self.try_create_src_file(&mut src_dir, &placeholders)?;
+            }
+        }
+        // Asset directories, which only documents have
+        if let ProjectKind::Document(_) = self.kind {
+            FiguresDir::create_dir(&pathref!(root => FiguresDir))?;
+            BibDir::create_dir(&pathref!(root => BibDir))?;
+            DataDir::create_dir(&pathref!(root => DataDir))?;
+        }
+        // A class comes with a document to try it out on
+        if let ProjectKind::Class = self.kind {
+            let tests_dir = pathref!(root => TestsDir);
+            TestsDir::create_dir(&tests_dir)?;
+            let demo_file: R<TestFile> = tests_dir.extend("demo.tex");
+            let template = crate::files::packages::ClassTemplate::new(&self.name.into());
+            try_create_file(&demo_file, template.demo().as_bytes())?;
         }
         // Build directory
         let target_dir = path!(root => TargetDir);
@@ -164,74 +492,146 @@ impl<'a> NewProject<'a> {
     }
 }
 
+/// Create the target directory and its cache tag, if they don't exist yet
 pub fn try_create_target_dir(target_dir: &P<TargetDir>) -> Result<()> {
-    std::fs::create_dir_all(&target_dir)?;
+    TargetDir::create_dir_all(target_dir)?;
     let cachedir_tag_file: P<CachedirTagFile> = target_dir.clone().extend(());
-    try_create(
-        &cachedir_tag_file,
-        ToCreate::File(crate::files::CACHEDIR_TAG.as_bytes()),
-    )
+    match CachedirTagFile::create_new(&cachedir_tag_file, crate::files::CACHEDIR_TAG) {
+        Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => Err(err.into()),
+        _ => Ok(()),
+    }
 }
 
-impl RootDir {
-    pub fn find() -> Result<P<Self>> {
-        let mut path = std::env::current_dir().unwrap();
-        let path_cpy = path.clone();
-        loop {
-            path.push(PROJECT_CONFIG_FILE);
-            if path.exists() {
-                path.pop();
-                return Ok(P::new(Self(()), path));
+/// How to find the project to operate on
+#[derive(Debug, Clone, Default)]
+pub enum ProjectLocation {
+    /// Search upwards from the current directory
+    #[default]
+    CurrentDir,
+    /// Search upwards from this directory
+    Dir(std::path::PathBuf),
+    /// The project whose manifest is at this path
+    Manifest(std::path::PathBuf),
+}
+
+impl ProjectLocation {
+    /// The directory that paths given on the command line are relative to
+    pub fn base_dir(&self) -> Result<std::path::PathBuf> {
+        match self {
+            ProjectLocation::CurrentDir => Ok(std::env::current_dir()?),
+            ProjectLocation::Dir(dir) => dir
+                .canonicalize()
+                .map_err(|err| anyhow!("cannot use directory `{}`: {}", dir.display(), err)),
+            ProjectLocation::Manifest(manifest) => {
+                Ok(RootDir::from_manifest(manifest)?.to_path_buf())
             }
-            path.pop();
-            if !path.pop() {
-                break;
+        }
+    }
+
+    /// The root of the project, or `None` if a search found none. A manifest
+    /// path that was given explicitly must exist.
+    pub fn find_root(&self) -> Result<Option<P<RootDir>>> {
+        Ok(self.find_roots()?.map(|roots| roots.project))
+    }
+
+    /// The roots of the project and of its workspace, or `None` if a search
+    /// found no project
+    pub fn find_roots(&self) -> Result<Option<Roots>> {
+        match self {
+            ProjectLocation::Manifest(manifest) => {
+                let project = RootDir::from_manifest(manifest)?;
+                let workspace = WorkspaceRootDir::find_from(&project);
+                Ok(Some(Roots { project, workspace }))
             }
+            _ => Ok(RootDir::find_roots_from(self.base_dir()?).ok()),
         }
-        Err(anyhow!(
-            "failed to find project containing `{}`",
-            path_cpy.display()
-        ))
     }
 }
 
-impl ProjectConfigFile {
-    fn try_create<P: typedir::AsPath<Self>>(
-        path: &P,
-        project_config: &crate::conf::ProjectConfig,
-    ) -> Result<()> {
-        try_create(path, ToCreate::File(&toml::ser::to_vec(&project_config)?))
+/// A project, and the workspace it's in, if any
+#[derive(Debug, Clone)]
+pub struct Roots {
+    pub project: P<RootDir>,
+    /// The nearest directory at or above the project whose manifest has a
+    /// `[workspace]` table
+    pub workspace: Option<P<WorkspaceRootDir>>,
+}
+
+impl FindRoot for RootDir {
+    const MARKER: &'static str = PROJECT_CONFIG_FILE;
+
+    fn at(path: std::path::PathBuf) -> P<Self> {
+        P::new(Self(()), path)
     }
 }
 
-// What to create
-enum ToCreate<'a> {
-    Dir,
-    File(&'a [u8]),
+impl FindRoot for WorkspaceRootDir {
+    const MARKER: &'static str = PROJECT_CONFIG_FILE;
+
+    fn at(path: std::path::PathBuf) -> P<Self> {
+        P::new(Self(()), path)
+    }
+
+    fn is_root(dir: &std::path::Path) -> bool {
+        let Ok(manifest) = std::fs::read_to_string(dir.join(Self::MARKER)) else {
+            return false;
+        };
+        toml::from_str::<toml::Value>(&manifest)
+            .is_ok_and(|manifest| manifest.get("workspace").is_some())
+    }
 }
 
-fn try_create<N: typedir::Node, P: typedir::AsPath<N>>(
-    path: &P,
-    to_create: ToCreate,
-) -> Result<()> {
-    use std::io::Write;
-    match to_create {
-        ToCreate::Dir => std::fs::create_dir(path)?,
-        ToCreate::File(contents) => {
-            // FIXME race condition! TOC/TOU! Not good!
-            if path.exists() {
-                return Err(anyhow!("file already exists: `{}`", path.display()));
-            }
-            let mut f = std::fs::File::create(path)?;
-            f.write_all(contents)?;
+impl RootDir {
+    pub fn find() -> Result<Roots> {
+        Self::find_roots_from(std::env::current_dir()?)
+    }
+
+    /// Find the project containing a directory, and its workspace
+    pub fn find_roots_from(dir: std::path::PathBuf) -> Result<Roots> {
+        let project = <Self as FindRoot>::find_from(&dir)
+            .ok_or_else(|| anyhow!("failed to find project containing `{}`", dir.display()))?;
+        let workspace = WorkspaceRootDir::find_from(&project);
+        Ok(Roots { project, workspace })
+    }
+
+    /// The root of the project with this manifest
+    pub fn from_manifest(manifest: &std::path::Path) -> Result<P<Self>> {
+        if manifest.file_name() != Some(std::ffi::OsStr::new(PROJECT_CONFIG_FILE)) {
+            return Err(anyhow!(
+                "the manifest path must be a path to a `{}` file",
+                PROJECT_CONFIG_FILE
+            ));
+        }
+        let root = manifest
+            .canonicalize()
+            .map_err(|err| anyhow!("cannot read manifest `{}`: {}", manifest.display(), err))?;
+        match root.parent() {
+            Some(root) => Ok(P::new(Self(()), root.to_path_buf())),
+            None => Err(anyhow!("invalid manifest path `{}`", manifest.display())),
         }
     }
-    Ok(())
+}
+
+impl ProjectConfigFile {
+    pub(crate) fn try_create<P: typedir::AsPath<Self>>(
+        path: &P,
+        project_config: &crate::conf::ProjectConfig,
+    ) -> Result<()> {
+        try_create_file(path, &toml::ser::to_vec(&project_config)?)
+    }
+}
+
+/// Create a file that mustn't exist yet
+fn try_create_file<N: WriteFile, P: AsPath<N>>(path: &P, contents: &[u8]) -> Result<()> {
+    N::create_new(path, contents).map_err(|err| match err.kind() {
+        std::io::ErrorKind::AlreadyExists => anyhow!("file already exists: `{}`", path.display()),
+        _ => err.into(),
+    })
 }
 
 /// A thin wrapper around `std::fs::remove_dir_all` that ignores `NotFound` errors.
 pub fn remove_dir_all<N: typedir::Node, P: typedir::AsPath<N>>(dir: &P) -> crate::Result<()> {
-    let res = std::fs::remove_dir_all(dir);
+    let res = std::fs::remove_dir_all(crate::platform::extended_length(dir.as_ref()));
     if let Err(err) = res {
         match err.kind() {
             std::io::ErrorKind::NotFound => Ok(()),
@@ -242,6 +642,340 @@ pub fn remove_dir_all<N: typedir::Node, P: typedir::AsPath<N>>(dir: &P) -> crate
     }
 }
 
+impl OutputDir {
+    /// The `output-dir` of a profile, which has to be a directory beneath the
+    /// project's root, given relative to it
+    pub fn try_for_project(root: &P<RootDir>, output_dir: &std::path::Path) -> Result<P<Self>> {
+        check_output_dir(output_dir)?;
+        Ok(root.clone().extend(output_dir))
+    }
+}
+
+/// Check that an `output-dir` is a directory beneath the project's root, given
+/// relative to it. Anything else, like `.` or `../pdfs`, would have largo
+/// writing into directories that aren't the project's.
+pub fn check_output_dir(output_dir: &std::path::Path) -> Result<()> {
+    use std::path::Component;
+    let mut beneath_root = false;
+    for component in output_dir.components() {
+        match component {
+            Component::Normal(_) => beneath_root = true,
+            Component::CurDir => (),
+            _ => {
+                beneath_root = false;
+                break;
+            }
+        }
+    }
+    if !beneath_root {
+        return Err(anyhow!(
+            "invalid `output-dir` `{}`: it must be a directory beneath the project's root, \
+             relative to it",
+            output_dir.display()
+        ));
+    }
+    Ok(())
+}
+
+impl CheckDir {
+    /// Checks are built apart from real builds, so as not to disturb them.
+    pub fn into_build_dir(check_dir: P<Self>) -> P<BuildDir> {
+        P::new(BuildDir(()), check_dir)
+    }
+}
+
+impl TestBuildDir {
+    /// Each test document is built in a build directory of its own.
+    pub fn into_build_dir(test_build_dir: P<Self>) -> P<BuildDir> {
+        P::new(BuildDir(()), test_build_dir)
+    }
+}
+
+impl TestsDir {
+    /// The file names of the test documents, in order
+    pub fn try_list<P: AsPath<Self>>(tests_dir: &P) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(tests_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut tests = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(file_name) = entry.file_name().to_str() {
+                if file_name.ends_with(".tex") {
+                    tests.push(file_name.to_string());
+                }
+            }
+        }
+        tests.sort();
+        Ok(tests)
+    }
+}
+
+/// Something that `largo clean` removes
+#[derive(Debug)]
+pub enum Removal {
+    Dir(std::path::PathBuf),
+    File(std::path::PathBuf),
+}
+
+impl Removal {
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            Removal::Dir(path) | Removal::File(path) => path,
+        }
+    }
+
+    pub fn try_remove(&self) -> Result<()> {
+        let path = crate::platform::extended_length(self.path());
+        let res = match self {
+            Removal::Dir(_) => std::fs::remove_dir_all(path),
+            Removal::File(_) => std::fs::remove_file(path),
+        };
+        match res {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Move this to the trash instead of deleting it, if it exists
+    pub fn try_trash(&self) -> Result<()> {
+        if !self.path().exists() {
+            return Ok(());
+        }
+        crate::trash::move_to_trash(self.path())
+    }
+}
+
+/// Which outputs `largo clean` removes. With neither, it removes everything.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CleanOptions {
+    /// The fetched dependencies
+    pub deps: bool,
+    /// The built documents
+    pub docs: bool,
+}
+
+/// Where a project's documents are built, and what they're called, which
+/// `largo clean --doc` looks through
+#[derive(Debug, Default)]
+pub struct DocLayout<'c> {
+    /// The project's documents, each built in a directory of its own, with
+    /// their jobnames
+    documents: Vec<(conf::DocumentName<'c>, String)>,
+    /// The jobname of the project's main file, if it has no documents
+    jobname: String,
+}
+
+impl<'c> DocLayout<'c> {
+    pub fn new(config: &conf::ProjectConfig<'c>) -> Self {
+        let documents = config
+            .documents
+            .iter()
+            .map(|doc| (doc.name.clone(), jobname(&doc.main_file())))
+            .collect();
+        Self {
+            documents,
+            jobname: jobname(config.project.main_file()),
+        }
+    }
+
+    /// The documents a profile has built, each in its build directory. Copies
+    /// exported to an `output-dir` are the user's, and aren't among them.
+    fn artifacts(&self, root: &P<RootDir>, profile: &conf::ProfileName) -> Vec<P<ArtifactFile>> {
+        let target_dir: P<TargetDir> = root.clone().extend(());
+        let build_dir: P<BuildDir> = target_dir.extend(profile).extend(());
+        if self.documents.is_empty() {
+            return vec![build_dir.extend(format!("{}.pdf", self.jobname).as_str())];
+        }
+        self.documents
+            .iter()
+            .map(|(doc, jobname)| {
+                let doc_dir: P<BuildDir> = build_dir.clone().extend(doc);
+                doc_dir.extend(format!("{}.pdf", jobname).as_str())
+            })
+            .collect()
+    }
+}
+
+/// The name the engine gives the outputs of a main file, as a build names
+/// them
+fn jobname(main_file: &str) -> String {
+    std::path::Path::new(main_file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+impl TargetDir {
+    /// Check that the target directory is one of ours, by its cache tag,
+    /// before deleting anything in it.
+    pub fn try_check_tag(target_dir: &P<Self>) -> Result<()> {
+        let expected = crate::files::CACHEDIR_TAG_SIGNATURE;
+        let cache_tag_file: P<CachedirTagFile> = target_dir.clone().extend(());
+        let contents = std::fs::read_to_string(&cache_tag_file);
+        match contents.as_ref().map(|c| c.get(0..expected.len())) {
+            Ok(Some(sig)) if sig == expected => Ok(()),
+            _ => Err(anyhow!(
+                "invalid cache signature, not deleting `{}`",
+                target_dir.display()
+            )),
+        }
+    }
+
+    /// The names of every profile that has been built
+    pub(crate) fn profile_names(target_dir: &P<Self>) -> Result<Vec<conf::ProfileName<'static>>> {
+        let mut names = Vec::new();
+        let entries = match std::fs::read_dir(target_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            // The shared deps directory isn't a profile, and neither is a
+            // check build's, which is why their names are reserved
+            let Ok(name) = conf::ProfileName::try_from(std::borrow::Cow::Owned(name)) else {
+                continue;
+            };
+            names.push(name);
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// What cleaning would remove, in one profile or in all of them
+    pub fn try_plan_clean(
+        root: &P<RootDir>,
+        layout: &DocLayout,
+        profile: Option<&conf::ProfileName>,
+        opts: CleanOptions,
+    ) -> Result<Vec<Removal>> {
+        let target_dir: P<TargetDir> = root.clone().extend(());
+        let profiles: Vec<conf::ProfileName> = match profile {
+            Some(profile) => vec![profile.clone()],
+            None => Self::profile_names(&target_dir)?,
+        };
+        let profile_dirs: Vec<P<ProfileTargetDir>> = profiles
+            .iter()
+            .map(|profile| target_dir.clone().extend(profile))
+            .collect();
+        if !opts.deps && !opts.docs {
+            let removal = match profile {
+                Some(_) => Removal::Dir(profile_dirs[0].to_path_buf()),
+                None => Removal::Dir(target_dir.to_path_buf()),
+            };
+            return Ok(vec![removal]);
+        }
+        let mut removals = Vec::new();
+        let shared_deps_dir: P<DepsDir> = target_dir.clone().extend(());
+        if opts.deps && profile.is_none() && shared_deps_dir.exists() {
+            removals.push(Removal::Dir(shared_deps_dir.to_path_buf()));
+        }
+        if opts.docs {
+            for profile in &profiles {
+                let artifacts = layout.artifacts(root, profile);
+                removals.extend(
+                    artifacts
+                        .iter()
+                        .filter(|artifact| artifact.exists())
+                        .map(|artifact| Removal::File(artifact.to_path_buf())),
+                );
+            }
+        }
+        for profile_dir in profile_dirs {
+            if opts.deps {
+                let deps_dir: P<DepsDir> = profile_dir.clone().extend(());
+                if deps_dir.exists() {
+                    removals.push(Removal::Dir(deps_dir.to_path_buf()));
+                }
+            }
+            if opts.docs {
+                let tests_dir: P<TestsTargetDir> = profile_dir.extend(());
+                // Each test is built in a directory named for its jobname
+                for test in TestsTargetDir::try_list(&tests_dir)? {
+                    let test_dir: P<TestBuildDir> = tests_dir.clone().extend(test.as_str());
+                    let build_dir = TestBuildDir::into_build_dir(test_dir);
+                    let artifact: P<ArtifactFile> =
+                        build_dir.extend(format!("{}.pdf", test).as_str());
+                    if artifact.exists() {
+                        removals.push(Removal::File(artifact.to_path_buf()));
+                    }
+                }
+            }
+        }
+        Ok(removals)
+    }
+}
+
+impl DepsDir {
+    /// Where a profile's CTAN dependencies are installed
+    pub fn for_profile(
+        target_dir: &P<TargetDir>,
+        profile: &conf::ProfileName,
+        layout: conf::DepsLayout,
+    ) -> P<Self> {
+        match layout {
+            conf::DepsLayout::Profile => {
+                let profile_dir: P<ProfileTargetDir> = target_dir.clone().extend(profile);
+                profile_dir.extend(())
+            }
+            conf::DepsLayout::Shared => target_dir.clone().extend(()),
+        }
+    }
+}
+
+impl TestsTargetDir {
+    /// The names of the tests that have been built
+    fn try_list(tests_dir: &P<Self>) -> Result<Vec<String>> {
+        let mut tests = Vec::new();
+        let entries = match std::fs::read_dir(tests_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(tests),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    tests.push(name.to_string());
+                }
+            }
+        }
+        tests.sort();
+        Ok(tests)
+    }
+}
+
+impl BuildDir {
+    /// Remove a job's outputs with the given extensions, like `main.aux`,
+    /// returning how many there were. Other jobs' files, and anything else
+    /// in the directory, are left alone.
+    pub fn try_prune(build_dir: &P<Self>, jobname: &str, extensions: &[&str]) -> Result<usize> {
+        let mut pruned = 0;
+        for ext in extensions {
+            let file_name = format!("{}.{}", jobname, ext);
+            let build_file: P<BuildFile> = build_dir.clone().extend(file_name.as_str());
+            match std::fs::remove_file(&build_file) {
+                Ok(()) => pruned += 1,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(pruned)
+    }
+}
+
 impl HomeDir {
     /// NOTE: Intentionally not globally visible!
     fn try_get() -> Result<P<Self>> {
@@ -260,23 +994,162 @@ impl HomeDir {
 }
 
 impl LargoConfigDir {
-    #[allow(dead_code)]
     pub fn global_config() -> Result<P<Self>> {
         let home = HomeDir::try_get()?;
         Ok(home.extend(()))
     }
 }
 
+impl GitignoreTemplateFile {
+    /// The user's own ignore rules for new projects, if they have any
+    pub fn try_read() -> Result<Option<String>> {
+        let path: P<Self> = LargoConfigDir::global_config()?.extend(());
+        match Self::read_to_string(&path) {
+            Ok(rules) => Ok(Some(rules)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl PlatformConfigDir {
+    /// NOTE: Intentionally not globally visible!
+    fn try_get() -> Result<P<Self>> {
+        let path = if cfg!(target_family = "unix") {
+            match std::env::var_os("XDG_CONFIG_HOME") {
+                // Relative paths are invalid, according to the XDG spec
+                Some(path) if std::path::Path::new(&path).is_absolute() => {
+                    std::path::PathBuf::from(path)
+                }
+                _ => HomeDir::try_get()?.to_path_buf().join(".config"),
+            }
+        } else if cfg!(target_family = "windows") {
+            std::path::PathBuf::from(std::env::var("APPDATA")?)
+        } else {
+            unreachable!("target unsupported");
+        };
+        Ok(P::new(PlatformConfigDir(()), path))
+    }
+}
+
+impl TrashDir {
+    /// The user's Freedesktop trash
+    pub(crate) fn try_get() -> Result<P<Self>> {
+        let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+            // Relative paths are invalid, according to the XDG spec
+            Some(path) if std::path::Path::new(&path).is_absolute() => {
+                std::path::PathBuf::from(path)
+            }
+            _ => HomeDir::try_get()?
+                .to_path_buf()
+                .join(".local")
+                .join("share"),
+        };
+        Ok(P::new(TrashDir(()), data_dir.join(TRASH_DIR)))
+    }
+}
+
+impl BuildCacheDir {
+    /// The build cache shared by every project on this machine
+    pub fn global() -> Result<P<Self>> {
+        let cache_dir: P<CacheDir> = LargoConfigDir::global_config()?.extend(());
+        Ok(cache_dir.extend(()))
+    }
+}
+
+impl CtanCacheDir {
+    /// The downloads from CTAN shared by every project on this machine
+    pub fn global() -> Result<P<Self>> {
+        let cache_dir: P<CacheDir> = LargoConfigDir::global_config()?.extend(());
+        Ok(cache_dir.extend(()))
+    }
+}
+
 impl LargoConfigFile {
-    pub fn try_read<P: AsPath<Self>>(path: &P) -> Result<ContentString<Self>> {
-        let content = std::fs::read_to_string(path)?;
-        Ok(ContentString(content, std::marker::PhantomData))
+    /// The global configuration file. In order, this is the first of these
+    /// that exists:
+    ///
+    /// 1. `$XDG_CONFIG_HOME/largo/config.toml`, or `~/.config/largo/config.toml`
+    ///    if `XDG_CONFIG_HOME` isn't set. On Windows, it's
+    ///    `%APPDATA%\largo\config.toml` instead.
+    /// 2. `~/.largo/config.toml`, where it has always been.
+    ///
+    /// If neither does, it's the first, so that's where a new one is created.
+    pub fn global() -> Result<P<Self>> {
+        let platform_dir: P<PlatformLargoConfigDir> = PlatformConfigDir::try_get()?.extend(());
+        let platform_file: P<PlatformLargoConfigFile> = platform_dir.extend(());
+        if platform_file.exists() {
+            return Ok(P::new(LargoConfigFile(()), platform_file.to_path_buf()));
+        }
+        let legacy_file = path!(LargoConfigDir::global_config()? => LargoConfigFile);
+        if legacy_file.exists() {
+            return Ok(legacy_file);
+        }
+        Ok(P::new(LargoConfigFile(()), platform_file.to_path_buf()))
+    }
+
+    /// Read the configuration for editing, preserving its formatting and
+    /// comments. A missing file reads as an empty one.
+    pub fn try_read_document<P: AsPath<Self>>(path: &P) -> Result<toml_edit::Document> {
+        match Self::read_to_string(path) {
+            Ok(content) => Ok(content.parse()?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Ok(toml_edit::Document::new())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn try_write_document<P: AsPath<Self>>(path: &P, doc: &toml_edit::Document) -> Result<()> {
+        if let Some(config_dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(config_dir)?;
+        }
+        Ok(Self::replace(path, doc.to_string())?)
+    }
+}
+
+impl LockFile {
+    /// Read the lockfile, if the project has one
+    pub fn try_read<P: AsPath<Self>>(path: &P) -> Result<Option<ContentString<Self>>> {
+        match Self::read_to_string(path) {
+            Ok(content) => Ok(Some(ContentString(content, std::marker::PhantomData))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
     }
 }
 
 impl ProjectConfigFile {
-    pub fn try_read<P: AsPath<Self>>(path: &P) -> Result<ContentString<Self>> {
-        let content = std::fs::read_to_string(path)?;
-        Ok(ContentString(content, std::marker::PhantomData))
+    /// Read the manifest for editing, preserving its formatting and comments
+    pub fn try_read_document<P: AsPath<Self>>(path: &P) -> Result<toml_edit::Document> {
+        let content = ContentString::try_read(path)?;
+        Ok(content.parse()?)
+    }
+
+    pub fn try_write_document<P: AsPath<Self>>(path: &P, doc: &toml_edit::Document) -> Result<()> {
+        Ok(Self::replace(path, doc.to_string())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pruning_leaves_other_jobs_files() {
+        let dir = std::env::temp_dir().join(format!("largo-prune-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["main.aux", "main.toc", "main.pdf", "notes.aux"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let build_dir = P::new(BuildDir(()), dir.clone());
+        assert_eq!(
+            BuildDir::try_prune(&build_dir, "main", &["aux", "toc", "out"]).unwrap(),
+            2
+        );
+        assert!(!dir.join("main.aux").exists());
+        assert!(dir.join("main.pdf").exists());
+        assert!(dir.join("notes.aux").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
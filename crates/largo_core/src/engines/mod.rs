@@ -1,8 +1,9 @@
 use std::{pin::Pin, task::Poll};
 
+use crate::build::filter::{Filter, InfoItem};
 use crate::{build, dirs, Result};
 
-use tokio::{io::BufReader, process::ChildStdout};
+use tokio::{io::BufReader, process::Child};
 use tokio_stream as stream;
 
 pub mod pdflatex;
@@ -16,14 +17,149 @@ pub struct Engine {
     cmd: crate::Command,
 }
 
-#[derive(Debug)]
+/// Whether largo can run this engine with this format yet. Only pdfLaTeX is
+/// supported so far.
+pub fn is_supported(
+    tex_engine: crate::conf::TexEngine,
+    tex_format: crate::conf::TexFormat,
+) -> bool {
+    matches!(
+        (tex_engine, tex_format),
+        (
+            crate::conf::TexEngine::Pdftex,
+            crate::conf::TexFormat::Latex
+        )
+    )
+}
+
+/// The bibliography processor, run between engine passes. It shares the
+/// engine's output handling.
+pub fn bib_engine(
+    conf: &crate::conf::LargoConfig,
+    bib_engine: crate::conf::BibEngine,
+    build_dir: &typedir::PathBuf<dirs::BuildDir>,
+    jobname: &str,
+    env: &crate::conf::EnvVars,
+    bib_dirs: &[std::path::PathBuf],
+) -> Engine {
+    let mut cmd = match bib_engine {
+        crate::conf::BibEngine::Biber => crate::Command::new(&conf.build.execs.biber),
+    };
+    if !bib_dirs.is_empty() {
+        // The trailing empty entry keeps the default search path
+        let empty = std::path::PathBuf::new();
+        if let Ok(bib_inputs) = std::env::join_paths(bib_dirs.iter().chain([&empty])) {
+            cmd.env("BIBINPUTS", bib_inputs);
+        }
+    }
+    cmd.current_dir(build_dir)
+        .arg(jobname)
+        .envs(env.iter())
+        .stderr(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    Engine { cmd }
+}
+
+/// docstrip, run on a package's `.ins` file to generate the package from its
+/// `.dtx` source before the documentation is built. The package is written
+/// to the build directory, where the engine finds it.
+pub fn docstrip(
+    conf: &crate::conf::LargoConfig,
+    ins_file: &std::path::Path,
+    src_dir: &typedir::PathBuf<dirs::SrcDir>,
+    build_dir: &typedir::PathBuf<dirs::BuildDir>,
+    env: &crate::conf::EnvVars,
+) -> crate::Command {
+    let mut cmd = crate::Command::new(&conf.build.execs.tex);
+    // The trailing empty entry keeps the default search path
+    let empty = std::path::PathBuf::new();
+    if let Ok(texinputs) = std::env::join_paths([src_dir.as_ref(), empty.as_path()]) {
+        cmd.env("TEXINPUTS", texinputs);
+    }
+    cmd.current_dir(build_dir)
+        .arg("-interaction=nonstopmode")
+        .arg(ins_file)
+        .envs(env.iter())
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    cmd
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
 pub enum EngineInfo {
-    Error { line: usize, msg: String },
+    Error {
+        /// The line of the source the error was found on, if the engine said
+        #[serde(skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+        msg: String,
+        /// The diagnostic code of the error's category, if it has one
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<&'static str>,
+    },
+    Warning {
+        msg: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<&'static str>,
+    },
+    /// Any other line of output
+    Output { line: String },
 }
 
-#[derive(Debug)]
+impl EngineInfo {
+    fn error(msg: String, line: Option<usize>) -> Self {
+        let code = crate::diagnostics::classify(&msg).map(|diag| diag.code);
+        EngineInfo::Error { line, msg, code }
+    }
+
+    fn warning(msg: String) -> Self {
+        let code = crate::diagnostics::classify(&msg).map(|diag| diag.code);
+        EngineInfo::Warning { msg, code }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, EngineInfo::Error { .. })
+    }
+
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            EngineInfo::Error { code, .. } | EngineInfo::Warning { code, .. } => *code,
+            EngineInfo::Output { .. } => None,
+        }
+    }
+}
+
+impl From<InfoItem> for EngineInfo {
+    fn from(item: InfoItem) -> Self {
+        match item {
+            InfoItem::Error { msg, line } => EngineInfo::error(msg, line),
+            InfoItem::FileNotFound { msg, .. } => EngineInfo::error(msg, None),
+            InfoItem::Warning { msg } | InfoItem::BadBox { msg } => EngineInfo::warning(msg),
+            InfoItem::Output { line } => EngineInfo::Output { line },
+        }
+    }
+}
+
+/// The lines an engine writes to its standard output and error, as they come
+type Lines = Pin<Box<dyn stream::Stream<Item = std::io::Result<String>> + Send>>;
+
 pub struct EngineOutput {
-    lines: tokio_stream::wrappers::LinesStream<BufReader<ChildStdout>>,
+    lines: Lines,
+    filter: Filter,
+    /// Whether the engine's output has all been read
+    done: bool,
+    child: Child,
+}
+
+impl EngineOutput {
+    /// Kill the engine process and wait for it to exit
+    pub async fn cancel(&mut self) -> Result<()> {
+        self.child.kill().await?;
+        Ok(())
+    }
 }
 
 impl stream::Stream for EngineOutput {
@@ -33,44 +169,85 @@ impl stream::Stream for EngineOutput {
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        match Pin::new(&mut self.lines).poll_next(cx) {
-            Poll::Ready(Some(Ok(mut line))) => {
-                if line.starts_with("! ") {
-                    // First two characters are "! "
-                    let msg = line.split_off(2);
-                    let info = EngineInfo::Error { line: 0, msg };
-                    Poll::Ready(Some(info.into()))
-                } else {
-                    cx.waker().wake_by_ref();
-                    Poll::Pending
-                }
+        loop {
+            if let Some(item) = self.filter.next_item() {
+                return Poll::Ready(Some(item.into()));
+            }
+            if self.done {
+                return Poll::Ready(None);
             }
-            Poll::Ready(Some(Err(_err))) => panic!("unexpected error"),
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Pending => {
-                cx.waker().wake_by_ref();
-                Poll::Pending
+            match self.lines.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(line))) => self.filter.push(line),
+                // The build fails, rather than going on without the rest
+                Poll::Ready(Some(Err(err))) => {
+                    let msg = format!("cannot read the engine's output: {}", err);
+                    return Poll::Ready(Some(EngineInfo::error(msg, None)));
+                }
+                Poll::Ready(None) => {
+                    self.filter.finish();
+                    self.done = true;
+                }
+                // The lines have registered the waker
+                Poll::Pending => return Poll::Pending,
             }
         }
     }
 }
 
 impl Engine {
-    pub fn run(&mut self) -> Result<EngineOutput> {
-        use tokio::io::AsyncBufReadExt;
-        let stdout = self.run_inner()?;
-        let lines = tokio_stream::wrappers::LinesStream::new(stdout.lines());
-        Ok(EngineOutput { lines })
+    /// The command that runs the engine
+    pub(crate) fn command(&self) -> &crate::Command {
+        &self.cmd
     }
 
-    fn run_inner(&mut self) -> Result<BufReader<ChildStdout>> {
-        // `async_process::Child` does not require a manual call to `.wait()`.
+    pub fn run(&mut self) -> Result<EngineOutput> {
+        use stream::StreamExt;
         let mut child = self.cmd.spawn()?;
         let stdout = child.stdout.take().expect("failed to take child's stdout");
-        Ok(tokio::io::BufReader::new(stdout))
+        let stdout = lossy_lines(BufReader::new(stdout));
+        // Standard error is read alongside standard output, so that neither
+        // fills its pipe and stalls the engine while the other is read
+        let lines: Lines = match child.stderr.take() {
+            Some(stderr) => Box::pin(stdout.merge(lossy_lines(BufReader::new(stderr)))),
+            None => stdout,
+        };
+        Ok(EngineOutput {
+            lines,
+            filter: Filter::new(),
+            done: false,
+            child,
+        })
     }
 }
 
+/// The lines of a reader. Engines write file names and input in whatever
+/// encoding they were given, often Latin-1, so the lines aren't expected to
+/// be UTF-8. Reading stops at the first error.
+fn lossy_lines<R>(reader: R) -> Lines
+where
+    R: tokio::io::AsyncBufRead + Unpin + Send + 'static,
+{
+    use tokio::io::AsyncBufReadExt;
+    Box::pin(futures::stream::unfold(Some(reader), |reader| async move {
+        let mut reader = reader?;
+        let mut line = Vec::new();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with(b"\n") {
+                    line.pop();
+                }
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                let line = String::from_utf8_lossy(&line).into_owned();
+                Some((Ok(line), Some(reader)))
+            }
+            Err(err) => Some((Err(err), None)),
+        }
+    }))
+}
+
 /// This module is visible to _other_ submodules of `engine`, but not to `super`.
 mod private {
     /// A builder that wraps a command.
@@ -96,6 +273,9 @@ pub trait EngineBuilder: private::CommandBuilder + Sized {
 
     fn with_draft_mode(self, draft_mode: bool) -> Result<Self>;
 
+    /// Stop at the first error, instead of trying to recover from it
+    fn with_halt_on_error(self, halt_on_error: bool) -> Result<Self>;
+
     /// This function takes an `Option<bool>` because many TeX engines have two
     /// flags, `-shell-escape` and `-no-shell-escape`, and I'm not sure they
     /// aren't simple opposites.
@@ -103,14 +283,40 @@ pub trait EngineBuilder: private::CommandBuilder + Sized {
 
     fn with_jobname(self, jobname: String) -> Result<Self>;
 
-    fn with_dependencies(mut self, deps: &DependencyPaths) -> Self {
+    fn with_env(mut self, env: &crate::conf::EnvVars) -> Self {
+        self.inner_cmd_mut().envs(env.iter());
+        self
+    }
+
+    /// Search the dependencies' directories for inputs, ahead of any the
+    /// engine would search anyway. How this is done depends on the engine, so
+    /// there is no default.
+    fn with_dependencies(self, deps: &DependencyPaths) -> Self;
+
+    /// Search these texmf trees before the user's own, by putting them in
+    /// `TEXMFHOME`
+    fn with_texmf_trees(mut self, trees: &[std::path::PathBuf]) -> Self {
         use itertools::Itertools;
-        if !deps.is_empty() {
-            let tex_inputs = format!("{}", deps.iter().map(|p| p.display()).format(","));
-            self.inner_cmd_mut().env("TEXINPUTS", tex_inputs);
+        if trees.is_empty() {
+            return self;
         }
+        let cmd = self.inner_cmd_mut();
+        let set = cmd
+            .as_std()
+            .get_envs()
+            .find(|(key, _)| *key == "TEXMFHOME")
+            .and_then(|(_, val)| val.map(|val| val.to_string_lossy().into_owned()));
+        let home = set
+            .or_else(|| std::env::var("TEXMFHOME").ok())
+            .or_else(|| crate::probe::kpathsea_var("TEXMFHOME"));
+        let trees = trees
+            .iter()
+            .map(|tree| tree.display().to_string())
+            .chain(home);
+        // Braces are kpathsea's syntax for a list of trees
+        cmd.env("TEXMFHOME", format!("{{{}}}", trees.format(",")));
         self
     }
 
-    fn finish(self) -> Engine;
+    fn finish(self) -> Result<Engine>;
 }
@@ -5,7 +5,7 @@ use crate::{dirs, Result};
 
 pub struct PdflatexBuilder {
     cmd: crate::Command,
-    texinputs: Vec<String>,
+    texinputs: Vec<std::path::PathBuf>,
     cli_options: CommandLineOptions,
 }
 
@@ -40,14 +40,47 @@ impl PdflatexBuilder {
     fn disable_line_wrapping(&mut self) {
         // FIXME: you should be able to do this as a static converstion to a
         // &'static str, and without an allocation.
-        self.cmd.env("max_print_line", &i32::MAX.to_string());
+        self.cmd.env("max_print_line", i32::MAX.to_string());
+    }
+
+    /// The `TEXINPUTS` search path: our own directories first, then whatever
+    /// the user has set, either in `[env]` or in the inherited environment.
+    fn texinputs(&self) -> Result<std::ffi::OsString> {
+        let inherited = self
+            .cmd
+            .as_std()
+            .get_envs()
+            .find(|(key, _)| *key == "TEXINPUTS")
+            .map(|(_, val)| val.map(|val| val.to_os_string()))
+            .unwrap_or_else(|| std::env::var_os("TEXINPUTS"));
+        let mut paths = self.texinputs.clone();
+        if let Some(inherited) = inherited {
+            paths.extend(std::env::split_paths(&inherited));
+        }
+        // A trailing empty entry stands for the engine's default search path
+        if paths.last().is_none_or(|path| !path.as_os_str().is_empty()) {
+            paths.push(std::path::PathBuf::new());
+        }
+        Ok(std::env::join_paths(paths)?)
     }
 }
 
 impl EngineBuilder for PdflatexBuilder {
     fn with_src_dir<P: typedir::AsPath<dirs::SrcDir>>(mut self, path: P) -> Self {
-        // FIXME: unnecessary allocation
-        self.texinputs.push(format!("{}", path.as_ref().display()));
+        self.texinputs.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The build directory is searched, too, for the generated `largo-vars`
+    /// package
+    fn with_build_dir<P: typedir::AsPath<dirs::BuildDir>>(mut self, dir: P) -> Self {
+        self.cmd.current_dir(dir.as_ref());
+        self.texinputs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    fn with_dependencies(mut self, deps: &super::DependencyPaths) -> Self {
+        self.texinputs.extend(deps.iter().cloned());
         self
     }
 
@@ -68,6 +101,11 @@ impl EngineBuilder for PdflatexBuilder {
         Ok(self)
     }
 
+    fn with_halt_on_error(mut self, halt_on_error: bool) -> Result<Self> {
+        self.cli_options.halt_on_error = halt_on_error;
+        Ok(self)
+    }
+
     fn with_jobname(mut self, jobname: String) -> Result<Self> {
         self.cli_options.jobname = Some(jobname);
         Ok(self)
@@ -86,21 +124,22 @@ impl EngineBuilder for PdflatexBuilder {
         Ok(self)
     }
 
-    fn finish(mut self) -> Engine {
+    fn finish(mut self) -> Result<Engine> {
         // Appy environment variables
         self.disable_line_wrapping();
+        let texinputs = self.texinputs()?;
         let mut cmd = self.cmd;
-        let mut texinputs = self.texinputs.join(":");
-        texinputs += ":";
-        cmd.env("TEXINPUTS", &texinputs);
+        cmd.env("TEXINPUTS", texinputs);
         // Pipe the output
         cmd.stderr(std::process::Stdio::piped())
             .stdout(std::process::Stdio::piped());
+        // Don't leave the engine running if the build is abandoned
+        cmd.kill_on_drop(true);
         // What to do with the output
         clam::Options::apply(self.cli_options, &mut cmd);
         // The actual input to the tex program
         cmd.arg(dirs::START_FILE);
-        Engine { cmd }
+        Ok(Engine { cmd })
     }
 }
 
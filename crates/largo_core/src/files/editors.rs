@@ -0,0 +1,50 @@
+//! Settings that let an editor build a new project with largo, and find the
+//! document it builds.
+
+use super::placeholders::Placeholders;
+use crate::{conf, dirs};
+
+/// The editors a new project can be set up for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editor {
+    /// Visual Studio Code, with the LaTeX Workshop extension
+    Vscode,
+    TeXstudio,
+}
+
+impl Editor {
+    /// The editor's settings for a project whose main file is `main_file`
+    pub fn settings(&self, placeholders: &Placeholders, main_file: &str) -> String {
+        // Editors want forward slashes, even on Windows
+        let build_dir = [dirs::TARGET_DIR, conf::DEV_PROFILE, dirs::BUILD_DIR].join("/");
+        let jobname = std::path::Path::new(main_file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let mut placeholders = placeholders.clone();
+        placeholders.set("main", &format!("{}/{}", dirs::SRC_DIR, main_file));
+        placeholders.set("pdf", &format!("{}/{}.pdf", build_dir, jobname));
+        placeholders.set("build-dir", &build_dir);
+        let template = match self {
+            Editor::Vscode => include_str!("vscode_settings.json"),
+            Editor::TeXstudio => include_str!("texstudio.txss"),
+        };
+        placeholders.fill_verbatim(template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editors_find_the_document() {
+        let placeholders = Placeholders::new("paper");
+        let settings = Editor::TeXstudio.settings(&placeholders, "main.dtx");
+        assert!(settings.contains("MasterFile=src/main.dtx\n"));
+        assert!(settings.contains("PDFFile=target/dev/build/main.pdf\n"));
+        assert!(!Editor::Vscode
+            .settings(&placeholders, "main.tex")
+            .contains("{{"));
+    }
+}
@@ -1,9 +1,38 @@
 //! Files (and code for building them) that go in the Largo repository.
 
+pub mod editors;
 pub mod packages;
+pub mod placeholders;
+pub mod templates;
 
 pub const GITIGNORE: &str = include_str!("gitignore.txt");
-pub const MAIN_LATEX: &str = include_str!("main_latex.tex");
+
+/// A new project's `.gitignore`: largo's outputs, the editor files that
+/// commonly turn up next to TeX sources, any rules of the user's own, and
+/// finally the usual TeX auxiliary files
+pub fn gitignore(user_rules: Option<&str>) -> String {
+    let mut gitignore = String::from(
+        "# Your .gitignore here
+
+# largo-specific files and directories
+/target/
+
+## Editor swap and backup files
+*.swp
+*.swo
+*~
+.#*
+",
+    );
+    if let Some(user_rules) = user_rules {
+        gitignore.push_str("\n## Your own rules, from ~/.largo/gitignore\n");
+        gitignore.push_str(user_rules.trim_end());
+        gitignore.push('\n');
+    }
+    gitignore.push('\n');
+    gitignore.push_str(GITIGNORE);
+    gitignore
+}
 
 macro_rules! cachedir_tag_signature {
     () => {
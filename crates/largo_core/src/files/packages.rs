@@ -3,6 +3,8 @@
 
 use std::fmt;
 
+use super::placeholders::Placeholders;
+
 #[derive(Debug, Clone)]
 pub enum PackageTexFormat {
     Latex2e,
@@ -42,7 +44,8 @@ pub struct PackageDate {
 
 impl fmt::Display for PackageDate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}/{}/{}", self.year, self.month, self.day)
+        // LaTeX compares dates as numbers, so they must be padded
+        write!(f, "{:04}/{:02}/{:02}", self.year, self.month, self.day)
     }
 }
 
@@ -139,7 +142,7 @@ struct TemplateData<'a, K: TemplateKind> {
 impl<'a, K: TemplateKind> fmt::Display for TemplateData<'a, K> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Declare required TeX format
-        writeln!(f, r#"\NeedsTexFormat{{{}}}"#, self.needs_format)?;
+        writeln!(f, r#"\NeedsTeXFormat{{{}}}"#, self.needs_format)?;
         // Declare provided package, with options
         write!(f, r#"\{}{{{}}}"#, K::PROVIDES_MACRO, self.name.as_ref())?;
         if let Some(opts) = &self.provides_options {
@@ -179,10 +182,12 @@ impl<'a> fmt::Display for PackageTemplate<'a> {
     }
 }
 
+/// A class, which is built on `article`, and passes it any options it doesn't
+/// declare itself
 pub struct ClassTemplate<'a>(TemplateData<'a, Self>);
 
 impl<'a> TemplateKind for ClassTemplate<'a> {
-    const PROVIDES_MACRO: &'static str = "ProvidesPackage";
+    const PROVIDES_MACRO: &'static str = "ProvidesClass";
 }
 
 impl<'a> ClassTemplate<'a> {
@@ -191,8 +196,123 @@ impl<'a> ClassTemplate<'a> {
     }
 }
 
+impl<'a> ClassTemplate<'a> {
+    /// The class this one loads and extends
+    pub const BASE_CLASS: &'static str = "article";
+
+    /// A document that uses the class, for its tests
+    pub fn demo(&self) -> String {
+        format!(
+            concat!(
+                "\\documentclass{{{}}}\n",
+                "\n",
+                "\\begin{{document}}\n",
+                "This document uses the \\texttt{{{}}} class.\n",
+                "\\end{{document}}\n",
+            ),
+            self.0.name.as_ref(),
+            self.0.name.as_ref(),
+        )
+    }
+}
+
 impl<'a> fmt::Display for ClassTemplate<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+        self.0.fmt(f)?;
+        writeln!(f)?;
+        writeln!(f, "% Declare the class's own options here, like")?;
+        writeln!(f, r#"%   \DeclareOption{{draft}}{{...}}"#)?;
+        writeln!(f, "% and pass any others on to the base class")?;
+        writeln!(
+            f,
+            r#"\DeclareOption*{{\PassOptionsToClass{{\CurrentOption}}{{{}}}}}"#,
+            Self::BASE_CLASS
+        )?;
+        writeln!(f, r#"\ProcessOptions\relax"#)?;
+        writeln!(f)?;
+        writeln!(f, r#"\LoadClass{{{}}}"#, Self::BASE_CLASS)
+    }
+}
+
+/// A Beamer theme, which is a package named `beamertheme<theme>`
+pub struct BeamerThemeTemplate<'a>(TemplateData<'a, Self>);
+
+impl<'a> TemplateKind for BeamerThemeTemplate<'a> {
+    const PROVIDES_MACRO: &'static str = "ProvidesPackage";
+}
+
+impl<'a> BeamerThemeTemplate<'a> {
+    /// `name` is the package name, including the `beamertheme` prefix
+    pub fn new(name: &PackageName<'a>) -> Self {
+        Self(TemplateData::new(name))
+    }
+}
+
+impl<'a> fmt::Display for BeamerThemeTemplate<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)?;
+        writeln!(f)?;
+        writeln!(f, r#"\mode<presentation>"#)?;
+        writeln!(f)?;
+        writeln!(f, r#"\mode<all>"#)
+    }
+}
+
+/// A package written in docstrip's `.dtx` format, with its documentation, and
+/// the `.ins` file that generates the package from it
+pub struct DtxTemplate<'a> {
+    name: PackageName<'a>,
+}
+
+impl<'a> DtxTemplate<'a> {
+    pub fn new(name: &PackageName<'a>) -> Self {
+        Self { name: name.clone() }
+    }
+
+    /// The project's placeholders, and the package's own
+    fn placeholders(&self, placeholders: &Placeholders) -> Placeholders {
+        let mut placeholders = placeholders.clone();
+        placeholders.set("package", self.name.as_ref());
+        placeholders.set("package-date", &PackageDate::current().to_string());
+        placeholders
+    }
+
+    pub fn dtx(&self, placeholders: &Placeholders) -> String {
+        self.placeholders(placeholders)
+            .fill(include_str!("package.dtx"))
+    }
+
+    pub fn ins(&self, placeholders: &Placeholders) -> String {
+        self.placeholders(placeholders)
+            .fill(include_str!("package.ins"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_provide_classes() {
+        let name = "myclass".into();
+        let template = ClassTemplate::new(&name);
+        let class = template.to_string();
+        assert!(class.starts_with("\\NeedsTeXFormat{LaTeX2e}\n\\ProvidesClass{myclass}["));
+        assert!(class.contains("\\ProcessOptions\\relax"));
+        assert!(class.ends_with("\\LoadClass{article}\n"));
+        assert!(template.demo().starts_with("\\documentclass{myclass}"));
+    }
+
+    #[test]
+    fn dtx_generates_its_package() {
+        let name = "mypkg".into();
+        let template = DtxTemplate::new(&name);
+        let placeholders = Placeholders::new("mypkg");
+        assert!(template
+            .ins(&placeholders)
+            .contains("\\file{mypkg.sty}{\\from{main.dtx}{package}}"));
+        assert!(template
+            .dtx(&placeholders)
+            .contains("%<package>\\ProvidesPackage{mypkg}"));
     }
 }
@@ -0,0 +1,105 @@
+//! Filling in the `{{placeholders}}` in the files of a new project, like its
+//! title and author.
+
+/// The values of a new project's placeholders, by name
+#[derive(Debug, Clone, Default)]
+pub struct Placeholders(Vec<(String, String)>);
+
+impl Placeholders {
+    /// The placeholders every project has, besides those it's given: `name`,
+    /// and the `year` and `date` it was created
+    pub fn new(name: &str) -> Self {
+        let now = chrono::Local::now();
+        let mut placeholders = Self::default();
+        placeholders.set("name", name);
+        placeholders.set("year", &now.format("%Y").to_string());
+        placeholders.set("date", &now.format("%Y-%m-%d").to_string());
+        placeholders
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Give a placeholder a value, replacing any it had
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.0.iter_mut().find(|(k, _)| k == key) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.0.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Give a placeholder a value, unless it already has one
+    pub fn set_default(&mut self, key: &str, value: &str) {
+        if self.get(key).is_none() {
+            self.set(key, value);
+        }
+    }
+
+    /// Replace each `{{placeholder}}` with its value, escaped so that it's
+    /// typeset as it's written. Anything between braces that isn't a known
+    /// placeholder is left alone, since TeX uses plenty of braces of its own.
+    pub fn fill(&self, text: &str) -> String {
+        self.fill_with(text, crate::vars::escape)
+    }
+
+    /// Replace each `{{placeholder}}` with its value as it is, in a file that
+    /// isn't read by TeX, like an editor's settings
+    pub fn fill_verbatim(&self, text: &str) -> String {
+        self.fill_with(text, str::to_string)
+    }
+
+    fn fill_with(&self, text: &str, render: impl Fn(&str) -> String) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest[2..].find("}}").and_then(|end| {
+                let value = self.get(rest[2..2 + end].trim())?;
+                Some((value, end + 4))
+            });
+            match value {
+                Some((value, len)) => {
+                    out.push_str(&render(value));
+                    rest = &rest[len..];
+                }
+                // The first brace might open a group around a placeholder
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_filled() {
+        let mut placeholders = Placeholders::new("paper");
+        placeholders.set("venue", "ICALP");
+        placeholders.set_default("venue", "STOC");
+        assert_eq!(
+            placeholders.fill("\\title{{{name}}} at {{ venue }}"),
+            "\\title{paper} at ICALP"
+        );
+        assert_eq!(
+            placeholders.fill("{{\\em x}} {{other}}"),
+            "{{\\em x}} {{other}}"
+        );
+        assert_eq!(placeholders.fill("unclosed {{name"), "unclosed {{name");
+        assert_eq!(placeholders.fill("{{year}}").len(), 4);
+        placeholders.set("author", "R&D");
+        assert_eq!(placeholders.fill("{{author}}"), "R\\&D");
+        assert_eq!(placeholders.fill_verbatim("{{author}}"), "R&D");
+    }
+}
@@ -0,0 +1,127 @@
+//! Scaffolds for new document projects: their main files, any other sources
+//! they start with, and their settings
+
+use crate::conf;
+
+pub mod beamer;
+pub mod user;
+
+/// The kinds of document a project can be created from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocumentTemplate {
+    #[default]
+    Article,
+    Beamer,
+    /// A book, with its chapters in files of their own
+    Book,
+    /// A report, with its chapters in files of their own, like a book
+    Report,
+    /// A report whose chapters are `\include`d, so that some of them can be
+    /// built on their own with `\includeonly`
+    Chapters,
+    Letter,
+    /// A conference paper with the `IEEEtran` class
+    Ieee,
+    /// A conference paper with the `acmart` class
+    Acm,
+}
+
+impl DocumentTemplate {
+    /// The contents of the template's `main.tex`
+    pub fn main_file(&self) -> String {
+        use DocumentTemplate::*;
+        match self {
+            Article => include_str!("main_latex.tex").to_string(),
+            Beamer => beamer::BeamerTemplate::new(None).to_string(),
+            Book => include_str!("main_latex_book.tex").to_string(),
+            Report => include_str!("main_latex_report.tex").to_string(),
+            Chapters => include_str!("main_latex_chapters.tex").to_string(),
+            Letter => include_str!("main_latex_letter.tex").to_string(),
+            Ieee => include_str!("main_latex_ieee.tex").to_string(),
+            Acm => include_str!("main_latex_acm.tex").to_string(),
+        }
+    }
+
+    /// The chapters the template starts with, by file name, in the source
+    /// directory's `chapters`
+    pub fn chapters(&self) -> &'static [(&'static str, &'static str)] {
+        use DocumentTemplate::*;
+        match self {
+            Book | Report => &[("introduction.tex", include_str!("chapter_introduction.tex"))],
+            Chapters => &[
+                ("introduction.tex", include_str!("chapter_introduction.tex")),
+                ("background.tex", include_str!("chapter_background.tex")),
+                ("conclusion.tex", include_str!("chapter_conclusion.tex")),
+                ("appendix.tex", include_str!("chapter_appendix.tex")),
+            ],
+            _ => &[],
+        }
+    }
+
+    /// The project settings a new project of this kind starts with
+    pub fn project_settings(&self) -> conf::ProjectSettings {
+        use DocumentTemplate::*;
+        match self {
+            // Letters have no bibliography
+            Letter => conf::ProjectSettings {
+                skip_bib: Some(true),
+                ..Default::default()
+            },
+            _ => conf::ProjectSettings::default(),
+        }
+    }
+}
+
+/// Load these packages in a main file's preamble, after those it loads
+/// already, and leaving out any it loads already
+pub fn with_packages<P: AsRef<str>>(main_file: &str, packages: &[P]) -> String {
+    let preamble = match main_file.find(r"\begin{document}") {
+        Some(end) => &main_file[..end],
+        None => main_file,
+    };
+    let loaded = crate::scan::loaded_packages(&crate::scan::strip_comments(preamble));
+    let mut lines = String::new();
+    for package in packages {
+        let package = package.as_ref();
+        let file_name = format!("{}.sty", package);
+        if !loaded.contains(&file_name) {
+            lines.push_str(&format!("\\usepackage{{{}}}\n", package));
+        }
+    }
+    if lines.is_empty() {
+        return main_file.to_string();
+    }
+    // After the line that loads the template's last package, or its class
+    let at = [r"\usepackage", r"\documentclass"]
+        .iter()
+        .find_map(|command| preamble.rfind(command))
+        .map_or(0, |start| match main_file[start..].find('\n') {
+            Some(len) => start + len + 1,
+            None => main_file.len(),
+        });
+    let mut filled = main_file[..at].to_string();
+    if !filled.is_empty() && !filled.ends_with('\n') {
+        filled.push('\n');
+    }
+    filled.push_str(&lines);
+    filled.push_str(&main_file[at..]);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_are_added_to_the_preamble() {
+        let main_file = "\\documentclass{article}\n\\usepackage{amsmath}\n\n\\begin{document}\n\\end{document}\n";
+        assert_eq!(
+            with_packages(main_file, &["amsmath", "hyperref", "cleveref"]),
+            "\\documentclass{article}\n\\usepackage{amsmath}\n\\usepackage{hyperref}\n\\usepackage{cleveref}\n\n\\begin{document}\n\\end{document}\n"
+        );
+        assert_eq!(
+            with_packages("\\documentclass{book}", &["hyperref"]),
+            "\\documentclass{book}\n\\usepackage{hyperref}\n"
+        );
+    }
+}
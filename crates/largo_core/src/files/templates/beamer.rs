@@ -0,0 +1,65 @@
+//! The scaffold of a Beamer presentation
+
+use std::fmt;
+
+/// A presentation's main file, with a title frame, an outline, and a section
+/// of frames to fill in
+#[derive(Debug, Clone, Copy)]
+pub struct BeamerTemplate<'a> {
+    theme: Option<&'a str>,
+}
+
+impl<'a> BeamerTemplate<'a> {
+    /// A presentation in a theme, or in Beamer's default one
+    pub fn new(theme: Option<&'a str>) -> Self {
+        Self { theme }
+    }
+}
+
+impl<'a> fmt::Display for BeamerTemplate<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, r"\documentclass{{beamer}}")?;
+        writeln!(f)?;
+        match self.theme {
+            Some(theme) => writeln!(f, r"\usetheme{{{}}}", theme)?,
+            None => writeln!(f, r"% \usetheme{{Madrid}}")?,
+        }
+        writeln!(f)?;
+        // Filled in with the rest of the placeholders
+        f.write_str("\\title{{{title}}}\n")?;
+        f.write_str("\\author{{{author}}}\n")?;
+        writeln!(f, r"\date{{\today}}")?;
+        writeln!(f)?;
+        writeln!(f, r"\begin{{document}}")?;
+        writeln!(f)?;
+        writeln!(f, r"\frame{{\titlepage}}")?;
+        writeln!(f)?;
+        writeln!(f, r"\begin{{frame}}{{Outline}}")?;
+        writeln!(f, r"\tableofcontents")?;
+        writeln!(f, r"\end{{frame}}")?;
+        writeln!(f)?;
+        writeln!(f, r"\section{{Introduction}}")?;
+        writeln!(f)?;
+        writeln!(f, r"\begin{{frame}}{{A slide}}")?;
+        writeln!(f, r"\begin{{itemize}}")?;
+        writeln!(f, r"\item Here is some content.")?;
+        writeln!(f, r"\item<2-> And here is some more, on the next overlay.")?;
+        writeln!(f, r"\end{{itemize}}")?;
+        writeln!(f, r"\end{{frame}}")?;
+        writeln!(f)?;
+        writeln!(f, r"\end{{document}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn themes_are_used() {
+        let metropolis = BeamerTemplate::new(Some("metropolis"));
+        assert!(metropolis.to_string().contains(r"\usetheme{metropolis}"));
+        let default = BeamerTemplate::new(None);
+        assert!(default.to_string().contains(r"% \usetheme{"));
+    }
+}
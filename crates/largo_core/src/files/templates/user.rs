@@ -0,0 +1,218 @@
+//! The user's own project templates, kept in `~/.largo/templates/<name>` or
+//! in git repositories, like `gh:someuser/ieee-template`. A template is a
+//! `template.toml` manifest alongside the files a new project starts with,
+//! which are copied into it with their `{{variables}}` filled in.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use typedir::{Extend, PathBuf as P, ReadFile};
+
+use crate::files::placeholders::Placeholders;
+use crate::{dirs, Result};
+
+/// The extensions of files read by TeX, whose placeholders' values are escaped
+const TEX_EXTENSIONS: &[&str] = &["tex", "ltx", "sty", "cls", "dtx", "ins"];
+
+/// The contents of a template's `template.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TemplateManifest {
+    /// Variables the template defines for itself, by their default values
+    #[serde(default)]
+    variables: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct UserTemplate {
+    dir: P<dirs::UserTemplateDir>,
+    manifest: TemplateManifest,
+    /// Whether `dir` is a clone, to be removed when it's no longer needed
+    cloned: bool,
+}
+
+/// The URL of a template kept in a git repository, which is either given in
+/// full or abbreviated, as in `gh:someuser/ieee-template`
+fn git_url(name: &str) -> Option<String> {
+    if let Some(repo) = name.strip_prefix("gh:") {
+        Some(format!("https://github.com/{}.git", repo))
+    } else if let Some(repo) = name.strip_prefix("gl:") {
+        Some(format!("https://gitlab.com/{}.git", repo))
+    } else if name.contains("://") {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+impl UserTemplate {
+    /// Find the template with this name, cloning it first if it's in a git
+    /// repository
+    pub fn load(name: &str) -> Result<Self> {
+        match git_url(name) {
+            Some(url) => Self::clone(name, &url),
+            None => Self::find(name),
+        }
+    }
+
+    /// Find the template with this name in the user's templates directory
+    fn find(name: &str) -> Result<Self> {
+        let mut components = Path::new(name).components();
+        let is_plain_name = matches!(
+            (components.next(), components.next()),
+            (Some(std::path::Component::Normal(_)), None)
+        );
+        if !is_plain_name {
+            return Err(anyhow!("`{}` is not a valid template name", name));
+        }
+        let templates_dir: P<dirs::TemplatesDir> =
+            dirs::LargoConfigDir::global_config()?.extend(());
+        let dir: P<dirs::UserTemplateDir> = templates_dir.clone().extend(name);
+        if !dir.is_dir() {
+            return Err(anyhow!(
+                "no template named `{}` in `{}`",
+                name,
+                templates_dir.display()
+            ));
+        }
+        Self::open(name, dir, false)
+    }
+
+    /// Clone a template into the cache, where it's kept until it's been used.
+    /// Its git history isn't copied into the new project.
+    fn clone(name: &str, url: &str) -> Result<Self> {
+        let cache_dir: P<dirs::TemplateCacheDir> =
+            dirs::LargoConfigDir::global_config()?.extend(()).extend(());
+        std::fs::create_dir_all(&cache_dir)?;
+        let clone_name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let dir: P<dirs::UserTemplateDir> =
+            cache_dir.extend(format!("{}.{}", clone_name, std::process::id()).as_str());
+        if dir.exists() {
+            dirs::remove_dir_all(&dir)?;
+        }
+        crate::dependencies::git::clone(url, &dir)?;
+        Self::open(name, dir, true)
+    }
+
+    fn open(name: &str, dir: P<dirs::UserTemplateDir>, cloned: bool) -> Result<Self> {
+        // Constructed first, so that a broken clone is still removed
+        let mut template = Self {
+            dir,
+            manifest: TemplateManifest::default(),
+            cloned,
+        };
+        let problems = template.dir.validate();
+        if !problems.is_empty() {
+            return Err(anyhow!(
+                "the template `{}` is broken:\n  {}",
+                name,
+                problems
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n  ")
+            ));
+        }
+        let manifest_file: P<dirs::TemplateManifestFile> = template.dir.clone().extend(());
+        let manifest = dirs::TemplateManifestFile::read_to_string(&manifest_file)?;
+        template.manifest = toml::from_str(&manifest)
+            .map_err(|err| anyhow!("failed to read `{}`: {}", manifest_file.display(), err))?;
+        Ok(template)
+    }
+
+    /// Give the template's own variables their default values
+    pub fn set_defaults(&self, placeholders: &mut Placeholders) {
+        for (key, value) in &self.manifest.variables {
+            placeholders.set_default(key, value);
+        }
+    }
+
+    /// Copy the template's files into a project, filling in its placeholders
+    pub fn instantiate(&self, root: &Path, placeholders: &Placeholders) -> Result<()> {
+        let mut files = Vec::new();
+        collect_files(&self.dir, Path::new(""), &mut files)?;
+        for relative in files {
+            let file: P<dirs::TemplateFile> = self.dir.clone().extend(relative.as_path());
+            let contents = std::fs::read(&file)?;
+            // Only text is substituted; anything else, like a figure, is
+            // copied as it is. Values are only escaped in TeX's own files.
+            let is_tex = relative
+                .extension()
+                .is_some_and(|ext| TEX_EXTENSIONS.iter().any(|tex| ext == *tex));
+            let contents = match String::from_utf8(contents) {
+                Ok(text) if is_tex => placeholders.fill(&text).into_bytes(),
+                Ok(text) => placeholders.fill_verbatim(&text).into_bytes(),
+                Err(err) => err.into_bytes(),
+            };
+            let dest = root.join(&relative);
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&dest)
+                .and_then(|mut dest| std::io::Write::write_all(&mut dest, &contents))
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::AlreadyExists => {
+                        anyhow!("file already exists: `{}`", dest.display())
+                    }
+                    _ => err.into(),
+                })?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UserTemplate {
+    fn drop(&mut self) {
+        if self.cloned {
+            let _ = dirs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// The files of a template, relative to its directory, leaving out its
+/// manifest and any git repository it's kept in
+fn collect_files(dir: &Path, relative: &Path, files: &mut Vec<std::path::PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir.join(relative))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let is_top_level = relative.as_os_str().is_empty();
+        if is_top_level && (name == dirs::TEMPLATE_MANIFEST_FILE || name == dirs::GIT_DIR) {
+            continue;
+        }
+        let relative = relative.join(&name);
+        // Links are followed, so that templates can share files
+        let meta = std::fs::metadata(entry.path())?;
+        if meta.is_dir() {
+            collect_files(dir, &relative, files)?;
+        } else if meta.is_file() {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_templates_are_recognized() {
+        assert_eq!(
+            git_url("gh:someuser/ieee-template").as_deref(),
+            Some("https://github.com/someuser/ieee-template.git")
+        );
+        assert_eq!(
+            git_url("https://example.com/thesis.git").as_deref(),
+            Some("https://example.com/thesis.git")
+        );
+        assert_eq!(git_url("thesis"), None);
+    }
+}
@@ -0,0 +1,326 @@
+//! Turning a directory of loose TeX sources into a largo project: finding its
+//! main file, working out how it's built, moving its sources into `src`, and
+//! writing a manifest for it. How it's built is read from magic comments,
+//! arara's directives, or latexmk's configuration, in that order.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use typedir::{CreateDir, Extend, FindRoot, PathBuf as P, WriteFile};
+
+use merge::Merge;
+
+use crate::conf::{ProfileSystemSettings, ProjectSettings, SystemSettings, TexEngine, TexFormat};
+use crate::{conf, dirs, Result};
+
+mod arara;
+mod latexmk;
+
+/// Extensions of the files an engine leaves behind, which stay where they are
+const AUXILIARY_EXTENSIONS: &[&str] = &[
+    "aux",
+    "bbl",
+    "bcf",
+    "blg",
+    "fdb_latexmk",
+    "fls",
+    "idx",
+    "ilg",
+    "ind",
+    "lof",
+    "log",
+    "lot",
+    "nav",
+    "out",
+    "run.xml",
+    "snm",
+    "synctex.gz",
+    "toc",
+    "vrb",
+    "xdv",
+];
+
+/// Extensions of the documents an engine produces
+const OUTPUT_EXTENSIONS: &[&str] = &["pdf", "dvi", "ps"];
+
+/// latexmk's configuration files, in the order it reads them
+const LATEXMKRC_FILES: &[&str] = &["latexmkrc", ".latexmkrc"];
+
+/// What was found out about an imported project
+#[derive(Debug)]
+pub struct Imported {
+    /// The main file, in the source directory
+    pub main_file: String,
+    pub system_settings: SystemSettings,
+    pub project_settings: ProjectSettings,
+    /// Everything that was moved into the source directory
+    pub moved: Vec<PathBuf>,
+}
+
+/// Make a project of the sources in `dir`, whose main file is either given or
+/// the one with a `\documentclass`
+pub fn import(dir: &Path, name: &str, main_file: Option<&str>) -> Result<Imported> {
+    let root = dirs::RootDir::at(dir.to_path_buf());
+    let manifest: P<dirs::ProjectConfigFile> = root.clone().extend(());
+    if manifest.exists() {
+        return Err(anyhow!("`{}` is already a largo project", dir.display()));
+    }
+    let src_dir: P<dirs::SrcDir> = root.clone().extend(());
+    if src_dir.exists() {
+        return Err(anyhow!(
+            "`{}` already exists; move it aside and try again",
+            src_dir.display()
+        ));
+    }
+    let main_file = match main_file {
+        Some(main_file) if dir.join(main_file).is_file() => main_file.to_string(),
+        Some(main_file) => {
+            return Err(anyhow!(
+                "no file named `{}` in `{}`",
+                main_file,
+                dir.display()
+            ))
+        }
+        None => find_main_file(dir)?,
+    };
+    let main_src = std::fs::read_to_string(dir.join(&main_file))?;
+    let mut converted = Converted::default();
+    if let Some(program) = magic_program(&main_src) {
+        converted.system = program_settings(&program)
+            .ok_or_else(|| anyhow!("`{}` asks for an unknown program, `{}`", main_file, program))?;
+    }
+    if let Some(arara) = arara::convert(&main_src) {
+        converted.merge_left(arara);
+    }
+    if let Some(rc) = read_latexmkrc(dir)? {
+        converted.merge_left(latexmk::convert(&rc)?);
+    }
+    let mut system_settings = SystemSettings::default();
+    converted.system.apply(&mut system_settings);
+    let project_settings = converted.project;
+    // Refused before anything is moved, rather than on the first build
+    if !crate::engines::is_supported(system_settings.tex_engine, system_settings.tex_format) {
+        return Err(anyhow!(
+            "`{}` is built with engine `{}` and format `{}`, which largo can't build with yet",
+            main_file,
+            system_settings.tex_engine,
+            system_settings.tex_format
+        ));
+    }
+
+    // Sources
+    let mut moved = Vec::new();
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?.collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    dirs::SrcDir::create_dir(&src_dir)?;
+    for entry in entries {
+        let name = PathBuf::from(entry.file_name());
+        if !is_source(&name, &main_file) {
+            continue;
+        }
+        std::fs::rename(entry.path(), src_dir.join(&name))?;
+        moved.push(name);
+    }
+
+    // Manifest
+    let mut config = conf::ProjectConfig::new(name);
+    if main_file != dirs::MAIN_FILE {
+        config.project.main = Some(main_file.as_str().into());
+    }
+    config.project.system_settings = system_settings.clone();
+    config.project.project_settings = project_settings.clone();
+    dirs::ProjectConfigFile::try_create(&manifest, &config)?;
+
+    // A repository's own ignore rules are left alone
+    let gitignore: P<dirs::Gitignore> = root.clone().extend(());
+    if !gitignore.exists() {
+        let user_rules = dirs::GitignoreTemplateFile::try_read()?;
+        dirs::Gitignore::create_new(&gitignore, crate::files::gitignore(user_rules.as_deref()))?;
+    }
+    let target_dir: P<dirs::TargetDir> = root.extend(());
+    dirs::try_create_target_dir(&target_dir)?;
+    Ok(Imported {
+        main_file,
+        system_settings,
+        project_settings,
+        moved,
+    })
+}
+
+/// How a project was built by another tool, as far as largo can follow it
+#[derive(Debug, Default)]
+struct Converted {
+    system: ProfileSystemSettings,
+    project: ProjectSettings,
+}
+
+impl Converted {
+    /// Fill in whatever these settings leave out from others
+    fn merge_left(&mut self, other: Self) {
+        self.system.merge_left(other.system);
+        self.project.merge_left(other.project);
+    }
+}
+
+/// The file with a `\documentclass`, or the likeliest of them
+fn find_main_file(dir: &Path) -> Result<String> {
+    let mut candidates = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "tex") && path.is_file() {
+            let src = std::fs::read_to_string(&path)?;
+            if has_documentclass(&src) {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                candidates.push(name);
+            }
+        }
+    }
+    candidates.sort();
+    // A `main.tex`, or a file named for its directory, is probably the main
+    // one; anything else is too ambiguous
+    let dir_name = dir
+        .file_name()
+        .map(|name| format!("{}.tex", name.to_string_lossy()));
+    match candidates.as_slice() {
+        [] => Err(anyhow!(
+            "no file in `{}` has a `\\documentclass`",
+            dir.display()
+        )),
+        [main_file] => Ok(main_file.clone()),
+        _ => candidates
+            .iter()
+            .find(|name| *name == dirs::MAIN_FILE || Some(*name) == dir_name.as_ref())
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "several files have a `\\documentclass`: {}; choose one with `--main`",
+                    candidates.join(", ")
+                )
+            }),
+    }
+}
+
+fn has_documentclass(src: &str) -> bool {
+    src.lines().any(|line| {
+        let line = line.split('%').next().unwrap_or_default();
+        line.contains("\\documentclass")
+    })
+}
+
+/// Whether a file at the top of the directory is one of its sources, rather
+/// than something of git's, latexmk's, or the engine's
+fn is_source(name: &Path, main_file: &str) -> bool {
+    let name = name.to_string_lossy();
+    let is_hidden = name.starts_with('.');
+    let is_largos = [
+        dirs::PROJECT_CONFIG_FILE,
+        dirs::LOCK_FILE,
+        dirs::TARGET_DIR,
+        dirs::SRC_DIR,
+    ]
+    .contains(&name.as_ref());
+    let is_auxiliary = AUXILIARY_EXTENSIONS
+        .iter()
+        .any(|ext| name.ends_with(&format!(".{}", ext)));
+    let main_stem = main_file.trim_end_matches(".tex");
+    let is_output = OUTPUT_EXTENSIONS
+        .iter()
+        .any(|ext| name == format!("{}.{}", main_stem, ext));
+    !(is_hidden
+        || is_largos
+        || is_auxiliary
+        || is_output
+        || LATEXMKRC_FILES.contains(&name.as_ref()))
+}
+
+/// The program asked for by a magic comment, like `% !TEX program = xelatex`,
+/// at the top of a file
+fn magic_program(src: &str) -> Option<String> {
+    src.lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with('%'))
+        .find_map(|line| {
+            let comment = line.trim_start_matches('%').trim_start();
+            let directive = comment
+                .get(..4)
+                .filter(|prefix| prefix.eq_ignore_ascii_case("!TEX"))
+                .map(|_| &comment[4..])?;
+            let (key, value) = directive.split_once('=')?;
+            let key = key.trim().to_ascii_lowercase();
+            (key == "program" || key == "ts-program").then(|| value.trim().to_ascii_lowercase())
+        })
+}
+
+/// The format and engine of a program, like `lualatex`
+fn program_settings(program: &str) -> Option<ProfileSystemSettings> {
+    let (tex_format, tex_engine) = match program {
+        "pdflatex" | "latex" => (TexFormat::Latex, TexEngine::Pdftex),
+        "xelatex" => (TexFormat::Latex, TexEngine::Xetex),
+        "lualatex" => (TexFormat::Latex, TexEngine::Luatex),
+        "pdftex" => (TexFormat::Tex, TexEngine::Pdftex),
+        "xetex" => (TexFormat::Tex, TexEngine::Xetex),
+        "luatex" => (TexFormat::Tex, TexEngine::Luatex),
+        "tex" => (TexFormat::Tex, TexEngine::Tex),
+        _ => return None,
+    };
+    Some(ProfileSystemSettings {
+        tex_format: Some(tex_format),
+        tex_engine: Some(tex_engine),
+        bib_engine: None,
+    })
+}
+
+/// The latexmk configuration in the directory, if there is one
+fn read_latexmkrc(dir: &Path) -> Result<Option<String>> {
+    for name in LATEXMKRC_FILES {
+        match std::fs::read_to_string(dir.join(name)) {
+            Ok(rc) => return Ok(Some(rc)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_comments_choose_programs() {
+        let src = "% !TEX TS-program = XeLaTeX\n% !TeX root = main.tex\n\\documentclass{article}";
+        assert_eq!(magic_program(src).as_deref(), Some("xelatex"));
+        assert!(matches!(
+            program_settings("xelatex"),
+            Some(ProfileSystemSettings {
+                tex_engine: Some(TexEngine::Xetex),
+                ..
+            })
+        ));
+        // Only comments at the top of a file are magic
+        assert_eq!(magic_program("\\relax\n% !TEX program = lualatex"), None);
+        assert!(has_documentclass("\\documentclass[11pt]{article}"));
+        assert!(!has_documentclass("% \\documentclass{article}"));
+    }
+
+    #[test]
+    fn unsupported_engines_are_refused() {
+        let dir = std::env::temp_dir().join(format!("largo-import-{}", std::process::id()));
+        let main = "% arara: xelatex\n\\documentclass{article}";
+        for (src, rc) in [
+            (main, None),
+            ("\\documentclass{article}", Some("$pdf_mode = 4;")),
+        ] {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("paper.tex"), src).unwrap();
+            if let Some(rc) = rc {
+                std::fs::write(dir.join(".latexmkrc"), rc).unwrap();
+            }
+            assert!(import(&dir, "paper", None).is_err());
+            // Nothing is moved
+            assert!(dir.join("paper.tex").is_file());
+            assert!(!dir.join(dirs::PROJECT_CONFIG_FILE).exists());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
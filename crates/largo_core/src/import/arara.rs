@@ -0,0 +1,110 @@
+//! Reading arara's directives, like `% arara: pdflatex: { shell: yes }`, which
+//! list the tools to run on a file, in order.
+
+use super::{program_settings, Converted};
+use crate::conf::BibEngine;
+
+/// The settings of a file's directives, if it has any. Since arara only runs
+/// what it's told to, a file with directives for an engine but not for a
+/// bibliography processor is built without a bibliography pass.
+pub(super) fn convert(src: &str) -> Option<Converted> {
+    let mut converted = Converted::default();
+    let mut has_engine = false;
+    let mut has_bib = false;
+    for (tool, options) in directives(src) {
+        match tool {
+            "biber" => {
+                has_bib = true;
+                converted.system.bib_engine = Some(BibEngine::Biber);
+            }
+            "bibtex" => has_bib = true,
+            _ => {
+                // Only the first engine is used, like a build's first run
+                let Some(system) = program_settings(tool).filter(|_| !has_engine) else {
+                    continue;
+                };
+                has_engine = true;
+                // A bibliography processor may have come first
+                converted.system.tex_format = system.tex_format;
+                converted.system.tex_engine = system.tex_engine;
+                for (key, value) in options {
+                    let value = matches!(value, "yes" | "true" | "on");
+                    match key {
+                        "shell" => converted.project.shell_escape = Some(value),
+                        "synctex" => converted.project.synctex = Some(value),
+                        "draft" => converted.project.draft_mode = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    if !has_engine {
+        return None;
+    }
+    if !has_bib {
+        converted.project.skip_bib = Some(true);
+    }
+    Some(converted)
+}
+
+/// Each tool in a directive, with its options
+fn directives(src: &str) -> impl Iterator<Item = (&str, Vec<(&str, &str)>)> {
+    src.lines().filter_map(|line| {
+        let comment = line.trim_start().strip_prefix('%')?.trim_start();
+        let directive = comment.strip_prefix("arara:")?.trim();
+        let end = directive
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(directive.len());
+        let (tool, rest) = directive.split_at(end);
+        // Options are in braces after a colon; anything else, like a
+        // condition, doesn't change how the tool is run
+        let options = rest
+            .trim_start()
+            .strip_prefix(':')
+            .and_then(|rest| {
+                let rest = rest.trim_start().strip_prefix('{')?;
+                Some(&rest[..rest.find('}')?])
+            })
+            .map(|options| {
+                options
+                    .split(',')
+                    .filter_map(|option| {
+                        let (key, value) = option.split_once(':')?;
+                        Some((
+                            key.trim(),
+                            value.trim().trim_matches(|c| c == '\'' || c == '"'),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some((tool, options))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arara_directives_are_converted() {
+        let src = "% arara: lualatex: { shell: yes, synctex: no }\n% arara: biber\n\
+                   % arara: lualatex if changed('bcf')\n\\documentclass{article}";
+        let converted = convert(src).unwrap();
+        assert!(matches!(
+            converted.system.tex_engine,
+            Some(crate::conf::TexEngine::Luatex)
+        ));
+        assert!(matches!(
+            converted.system.bib_engine,
+            Some(BibEngine::Biber)
+        ));
+        assert_eq!(converted.project.shell_escape, Some(true));
+        assert_eq!(converted.project.synctex, Some(false));
+        assert_eq!(converted.project.skip_bib, None);
+        let no_bib = convert("% arara: pdflatex\n").unwrap();
+        assert_eq!(no_bib.project.skip_bib, Some(true));
+        assert!(convert("\\documentclass{article}").is_none());
+    }
+}
@@ -0,0 +1,103 @@
+//! Reading a `.latexmkrc`. It's Perl, but it's almost always just a list of
+//! assignments, like `$pdf_mode = 5;`, and those are all that's read.
+
+use super::{program_settings, Converted};
+use crate::conf::OutputFormat;
+use anyhow::{anyhow, Result};
+
+pub(super) fn convert(rc: &str) -> Result<Converted> {
+    let mut converted = Converted::default();
+    let mut pdf_mode = None;
+    let mut dvi_mode = false;
+    let mut postscript_mode = false;
+    for (var, value) in assignments(rc) {
+        match var {
+            "$pdf_mode" => pdf_mode = Some(value),
+            "$dvi_mode" => dvi_mode = value == "1",
+            "$postscript_mode" => postscript_mode = value == "1",
+            // With no bibliography pass at all
+            "$bibtex_use" if value == "0" => converted.project.skip_bib = Some(true),
+            // Largo's own build directory is in `target`, so only an output
+            // directory that largo can copy into is kept
+            "$out_dir" if crate::dirs::check_output_dir(std::path::Path::new(value)).is_ok() => {
+                converted.project.output_dir = Some(value.into())
+            }
+            // The commands that run the engine, whose options are kept
+            "$latex" | "$pdflatex" | "$xelatex" | "$lualatex" => {
+                let options: Vec<&str> = value.split_whitespace().collect();
+                if options.contains(&"-shell-escape") || options.contains(&"--shell-escape") {
+                    converted.project.shell_escape = Some(true);
+                }
+                if options.contains(&"-synctex=1") || options.contains(&"--synctex=1") {
+                    converted.project.synctex = Some(true);
+                }
+            }
+            _ => {}
+        }
+    }
+    let program = match pdf_mode {
+        Some("1") => Some("pdflatex"),
+        // These run `latex`, and then make the PDF from the DVI file with
+        // `dvips` and `ps2pdf`, or `dvipdf`
+        Some(mode @ ("2" | "3")) => {
+            return Err(anyhow!(
+                "`$pdf_mode = {}` makes the PDF from a DVI file, which largo can't do yet",
+                mode
+            ))
+        }
+        Some("4") => Some("lualatex"),
+        Some("5") => Some("xelatex"),
+        _ if dvi_mode || postscript_mode => Some("latex"),
+        _ => None,
+    };
+    if let Some(system) = program.and_then(program_settings) {
+        converted.system = system;
+    }
+    // Without a PDF, latexmk stops at the DVI or PostScript file
+    if !matches!(pdf_mode, Some("1" | "2" | "3" | "4" | "5")) {
+        if postscript_mode {
+            converted.project.output_format = Some(OutputFormat::Ps);
+        } else if dvi_mode {
+            converted.project.output_format = Some(OutputFormat::Dvi);
+        }
+    }
+    Ok(converted)
+}
+
+/// Each variable assigned to, and the value assigned to it, without quotes
+fn assignments(rc: &str) -> impl Iterator<Item = (&str, &str)> {
+    rc.lines()
+        .filter_map(|line| line.split('#').next())
+        .flat_map(|line| line.split(';'))
+        .filter_map(|statement| {
+            let (var, value) = statement.split_once('=')?;
+            let var = var.trim();
+            let value = value.trim().trim_matches(|c| c == '\'' || c == '"');
+            var.starts_with('$').then_some((var, value))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latexmkrc_settings_are_converted() {
+        let rc = "# Build with XeLaTeX\n$pdf_mode = 5;\n$bibtex_use = 0;\n\
+                  $out_dir = 'build';\n$xelatex = 'xelatex -shell-escape %O %S';\n";
+        let converted = convert(rc).unwrap();
+        assert!(matches!(
+            converted.system.tex_engine,
+            Some(crate::conf::TexEngine::Xetex)
+        ));
+        assert_eq!(converted.project.skip_bib, Some(true));
+        assert_eq!(converted.project.shell_escape, Some(true));
+        assert_eq!(
+            converted.project.output_dir.as_deref(),
+            Some(std::path::Path::new("build"))
+        );
+        let dvi = convert("$dvi_mode = 1;").unwrap();
+        assert!(matches!(dvi.project.output_format, Some(OutputFormat::Dvi)));
+        assert!(convert("$pdf_mode = 3;").is_err());
+    }
+}
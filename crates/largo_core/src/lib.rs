@@ -1,11 +1,20 @@
 pub mod build;
 pub mod conf;
 pub mod dependencies;
+pub mod diagnostics;
 pub mod dirs;
 pub mod engines;
 pub mod files;
+pub mod import;
+pub mod platform;
+pub mod probe;
+pub mod reader;
+pub mod scan;
+pub mod synctex;
+pub mod trash;
 pub mod util;
 pub mod vars;
+pub mod wc;
 
 pub use anyhow::Error;
 pub use anyhow::Result;
@@ -0,0 +1,114 @@
+//! Papering over the differences between platforms' paths and executables,
+//! which are mostly Windows' differences from everyone else's.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+/// The longest path Windows' legacy APIs accept, including the terminator.
+/// Creating a directory leaves room for an 8.3 file name in it, so paths of
+/// directories are held to 12 characters fewer.
+const MAX_PATH: usize = 260;
+
+/// The extended-length form of an absolute Windows path too long for the
+/// legacy limit, like `\\?\C:\...` for `C:\...`, so that deep target
+/// directories can still be created and removed. Any other path is returned
+/// as it is, as is every path on other platforms.
+pub fn extended_length(path: &Path) -> Cow<'_, Path> {
+    use std::path::{Component, Prefix};
+    if path.as_os_str().len() < MAX_PATH - 12 || !path.is_absolute() {
+        return Cow::Borrowed(path);
+    }
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return Cow::Borrowed(path);
+    };
+    let mut extended = match prefix.kind() {
+        Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:", letter as char)),
+        Prefix::UNC(server, share) => {
+            let mut extended = std::ffi::OsString::from(r"\\?\UNC\");
+            extended.push(server);
+            extended.push(r"\");
+            extended.push(share);
+            PathBuf::from(extended)
+        }
+        // Already extended-length, or a device
+        _ => return Cow::Borrowed(path),
+    };
+    // Extended-length paths are taken literally, so they must be normalized
+    for component in components {
+        match component {
+            Component::RootDir => extended.push(r"\"),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                extended.pop();
+            }
+            Component::Normal(name) => extended.push(name),
+            Component::Prefix(_) => unreachable!("a path has at most one prefix"),
+        }
+    }
+    Cow::Owned(extended)
+}
+
+/// The file names an executable might have. On Windows, a name without an
+/// extension could be any of the names with an extension in `PATHEXT`, like
+/// `pdflatex.exe`; elsewhere, it's just the name.
+pub fn executable_names(exec: &Path) -> Vec<PathBuf> {
+    if !cfg!(target_family = "windows") || exec.extension().is_some() {
+        return vec![exec.to_path_buf()];
+    }
+    let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    pathext
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| {
+            let mut name = exec.as_os_str().to_os_string();
+            name.push(ext.to_ascii_lowercase());
+            PathBuf::from(name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_paths_are_left_alone() {
+        let path = Path::new("target/dev/build");
+        assert_eq!(extended_length(path), Cow::Borrowed(path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_paths_are_extended() {
+        let deep = "deps\\".repeat(60);
+        let path = PathBuf::from(format!(r"C:\proj\.\target\{}", deep));
+        let extended = extended_length(&path);
+        assert!(extended
+            .to_str()
+            .unwrap()
+            .starts_with(r"\\?\C:\proj\target\deps\"));
+        let unc = PathBuf::from(format!(r"\\server\share\target\{}", deep));
+        assert!(extended_length(&unc)
+            .to_str()
+            .unwrap()
+            .starts_with(r"\\?\UNC\server\share\target\deps\"));
+        // Already extended
+        let verbatim = PathBuf::from(format!(r"\\?\C:\target\{}", deep));
+        assert_eq!(
+            extended_length(&verbatim),
+            Cow::Borrowed(verbatim.as_path())
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn executables_have_extensions() {
+        let names = executable_names(Path::new("pdflatex"));
+        assert!(names.contains(&PathBuf::from("pdflatex.exe")));
+        assert_eq!(
+            executable_names(Path::new("pdflatex.exe")),
+            [PathBuf::from("pdflatex.exe")]
+        );
+    }
+}
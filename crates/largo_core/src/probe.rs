@@ -0,0 +1,151 @@
+//! Probing the TeX installation, for `largo env` and for anything else that
+//! needs to know what's installed.
+
+use anyhow::anyhow;
+
+use crate::conf::LargoConfig;
+use crate::Result;
+
+/// Variables that locate the trees kpathsea searches
+pub const KPATHSEA_VARS: &[&str] = &[
+    "TEXMFROOT",
+    "TEXMFDIST",
+    "TEXMFLOCAL",
+    "TEXMFHOME",
+    "TEXMFVAR",
+    "TEXMFCONFIG",
+];
+
+/// What we found out about one of the configured programs
+#[derive(Debug)]
+pub struct ProgramInfo<'c> {
+    /// The program's name in the configuration
+    pub name: &'static str,
+    /// The executable it's configured to run
+    pub exec: &'c str,
+    /// Where the executable is, if it could be found
+    pub path: Option<std::path::PathBuf>,
+    /// The first line of `<exec> --version`
+    pub version: Option<String>,
+}
+
+/// The first line of a program's `--version` output
+pub fn program_version(exec: &str) -> Result<String> {
+    let output = std::process::Command::new(exec)
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|err| anyhow!("failed to run `{} --version`: {}", exec, err))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or_default().to_string())
+}
+
+/// Find an executable the way the shell would: a path is taken as it is, and
+/// a bare name is looked up in `PATH`. On Windows, either may leave off the
+/// executable's extension.
+pub fn which(exec: &str) -> Option<std::path::PathBuf> {
+    let names = crate::platform::executable_names(std::path::Path::new(exec));
+    if std::path::Path::new(exec).components().count() > 1 {
+        return names.into_iter().find(|candidate| candidate.is_file());
+    }
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Every program in the configuration, and what we could find out about it
+pub fn probe_programs<'c>(conf: &'c LargoConfig<'c>) -> Vec<ProgramInfo<'c>> {
+    conf.build
+        .execs
+        .iter()
+        .map(|(name, exec)| {
+            let path = which(exec.as_ref());
+            let version = path
+                .as_ref()
+                .and_then(|path| program_version(&path.to_string_lossy()).ok());
+            ProgramInfo {
+                name,
+                exec: exec.as_ref(),
+                path,
+                version,
+            }
+        })
+        .collect()
+}
+
+/// The TeX distribution, as named in the programs' version strings
+pub fn distribution(programs: &[ProgramInfo]) -> Option<&'static str> {
+    let versions = || programs.iter().filter_map(|prog| prog.version.as_deref());
+    if versions().any(|version| version.contains("TeX Live")) {
+        Some("TeX Live")
+    } else if versions().any(|version| version.contains("MiKTeX")) {
+        Some("MiKTeX")
+    } else {
+        None
+    }
+}
+
+/// A package as installed in TeX Live
+#[derive(Debug)]
+pub struct InstalledPackage {
+    /// The TeX Live revision, like `67890`
+    pub revision: Option<String>,
+    /// The version in the package catalogue, like `1.2a`
+    pub version: Option<String>,
+}
+
+/// What `tlmgr` knows about an installed package, or `None` if it isn't
+/// installed or `tlmgr` can't be run
+pub fn installed_package(name: &str) -> Option<InstalledPackage> {
+    let output = std::process::Command::new("tlmgr")
+        .args([
+            "info",
+            "--only-installed",
+            "--data",
+            "name,revision,cat-version",
+        ])
+        .arg(name)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout
+        .lines()
+        .find(|line| line.split(',').next() == Some(name))?;
+    let mut fields = line.split(',').skip(1).map(|field| {
+        let field = field.trim();
+        (!field.is_empty()).then(|| field.to_string())
+    });
+    Some(InstalledPackage {
+        revision: fields.next().flatten(),
+        version: fields.next().flatten(),
+    })
+}
+
+/// Where kpathsea finds a file, like `tikz.sty`, or `None` if it can't or
+/// `kpsewhich` can't be run
+pub fn kpsewhich(file: &str) -> Option<std::path::PathBuf> {
+    let output = std::process::Command::new("kpsewhich")
+        .arg(file)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !path.is_empty()).then(|| path.into())
+}
+
+/// The value of a kpathsea variable, or `None` if it isn't set or `kpsewhich`
+/// can't be run
+pub fn kpathsea_var(var: &str) -> Option<String> {
+    let output = std::process::Command::new("kpsewhich")
+        .arg(format!("-var-value={}", var))
+        .stdin(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
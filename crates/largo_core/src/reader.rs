@@ -0,0 +1,140 @@
+//! Opening built documents in a reader
+
+use crate::{
+    conf::{DocConfig, ReaderCommand},
+    Result,
+};
+
+/// A position in a document's source, to show in the reader
+#[derive(Debug, Clone)]
+pub struct SourcePosition {
+    pub file: std::path::PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::str::FromStr for SourcePosition {
+    type Err = crate::Error;
+
+    /// Parse a position written like `main.tex:12`, or `main.tex:12:5` with a
+    /// column
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid position `{}`; expected `FILE:LINE[:COLUMN]`", s);
+        let (rest, last) = s.rsplit_once(':').ok_or_else(invalid)?;
+        let last: usize = last.parse().map_err(|_| invalid())?;
+        // The file may have colons of its own, as in `C:\main.tex:12`
+        let with_column = rest
+            .rsplit_once(':')
+            .and_then(|(file, line)| Some((file, line.parse().ok()?)));
+        let (file, line, column) = match with_column {
+            Some((file, line)) => (file, line, last),
+            None => (rest, last, 0),
+        };
+        if file.is_empty() {
+            return Err(invalid());
+        }
+        Ok(SourcePosition {
+            file: file.into(),
+            line,
+            column,
+        })
+    }
+}
+
+/// The platform's own program for opening a file with its default application
+fn system_opener() -> std::process::Command {
+    // As in `dirs::HomeDir::try_get`, an exhaustive if/else chain rather than
+    // `#[cfg(...)]`.
+    if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(target_family = "unix") {
+        std::process::Command::new("xdg-open")
+    } else if cfg!(target_family = "windows") {
+        // `start` is a shell builtin, and its first quoted argument is a title
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else {
+        unreachable!("target unsupported");
+    }
+}
+
+/// The configured reader's command line, with its placeholders filled in
+fn reader_command(
+    reader: &ReaderCommand,
+    path: &std::path::Path,
+    position: Option<&SourcePosition>,
+) -> std::process::Command {
+    let pdf = path.to_string_lossy();
+    // Readers match the source against the absolute paths SyncTeX records
+    let tex = position.map(|position| {
+        std::path::absolute(&position.file).unwrap_or_else(|_| position.file.clone())
+    });
+    let mut args: Vec<&str> = reader.args.iter().map(|arg| arg.as_ref()).collect();
+    if position.is_some() {
+        args.extend(reader.forward_args.iter().map(|arg| arg.as_ref()));
+    }
+    let mut cmd = std::process::Command::new(reader.command.as_ref());
+    for arg in &args {
+        let mut arg = arg.replace("{pdf}", &pdf);
+        if let (Some(position), Some(tex)) = (position, &tex) {
+            arg = arg
+                .replace("{tex}", &tex.to_string_lossy())
+                .replace("{line}", &position.line.to_string())
+                .replace("{column}", &position.column.to_string());
+        }
+        cmd.arg(arg);
+    }
+    if !args.iter().any(|arg| arg.contains("{pdf}")) {
+        cmd.arg(path);
+    }
+    cmd
+}
+
+/// Open a document with the configured reader, or the system default if none
+/// is configured. This doesn't wait for the reader to exit.
+pub fn open<P: AsRef<std::path::Path>>(conf: &DocConfig, path: P) -> Result<()> {
+    open_at(conf, path, None)
+}
+
+/// Like `open`, but showing a position in the source, if the reader is
+/// configured for forward search. Other readers just open the document.
+pub fn open_at<P: AsRef<std::path::Path>>(
+    conf: &DocConfig,
+    path: P,
+    position: Option<&SourcePosition>,
+) -> Result<()> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Err(anyhow::anyhow!("no document found at `{}`", path.display()));
+    }
+    let mut cmd = match conf.reader.as_ref().and_then(|reader| reader.command()) {
+        Some(reader) => reader_command(&reader, path, position),
+        None => {
+            let mut cmd = system_opener();
+            cmd.arg(path);
+            cmd
+        }
+    };
+    cmd.stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    cmd.spawn()
+        .map_err(|err| anyhow::anyhow!("failed to launch document reader: {}", err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_are_parsed() {
+        let position: SourcePosition = "src/main.tex:12:5".parse().unwrap();
+        assert_eq!(position.file, std::path::Path::new("src/main.tex"));
+        assert_eq!((position.line, position.column), (12, 5));
+        let position: SourcePosition = "main.tex:12".parse().unwrap();
+        assert_eq!((position.line, position.column), (12, 0));
+        assert!("main.tex".parse::<SourcePosition>().is_err());
+        assert!(":12".parse::<SourcePosition>().is_err());
+    }
+}
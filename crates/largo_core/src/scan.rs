@@ -0,0 +1,196 @@
+//! A lightweight scanner for LaTeX sources, which finds the files a document is
+//! assembled from without running TeX. It's only an approximation: it knows
+//! nothing of macros, so an `\input` hidden behind one goes unnoticed.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+
+/// Commands whose argument names another source file to be scanned
+const INCLUDE_COMMANDS: &[&str] = &["input", "include", "subfile"];
+
+/// Commands that load packages or classes, and the extension of the files
+/// they load
+const PACKAGE_COMMANDS: &[(&str, &str)] = &[
+    ("usepackage", "sty"),
+    ("RequirePackage", "sty"),
+    ("documentclass", "cls"),
+    ("LoadClass", "cls"),
+];
+
+/// A source file found by the scanner
+#[derive(Debug)]
+pub struct SourceFile {
+    /// Path relative to the source directory
+    pub path: PathBuf,
+    /// Contents, with comments removed
+    pub contents: String,
+}
+
+/// Remove `%` comments, leaving escaped `\%` alone
+pub fn strip_comments(src: &str) -> String {
+    let mut stripped = String::with_capacity(src.len());
+    for line in src.lines() {
+        let mut escaped = false;
+        let mut end = line.len();
+        for (i, c) in line.char_indices() {
+            match c {
+                '%' if !escaped => {
+                    end = i;
+                    break;
+                }
+                '\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+        stripped.push_str(&line[..end]);
+        stripped.push('\n');
+    }
+    stripped
+}
+
+/// The braced arguments of every occurrence of `\<command>{...}`
+pub fn command_args<'s>(src: &'s str, command: &str) -> Vec<&'s str> {
+    command_occurrences(src, command)
+        .into_iter()
+        .map(|(_, arg)| arg)
+        .collect()
+}
+
+/// Like `command_args`, but with the offset of each occurrence
+fn command_occurrences<'s>(src: &'s str, command: &str) -> Vec<(usize, &'s str)> {
+    let pattern = format!("\\{}", command);
+    let mut args = Vec::new();
+    let mut offset = 0;
+    while let Some(idx) = src[offset..].find(&pattern) {
+        let start = offset + idx;
+        offset = start + pattern.len();
+        let rest = &src[offset..];
+        // Don't match `\inputenc` when looking for `\input`
+        if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+        let Some(arg) = skip_optional_arg(rest.trim_start()).strip_prefix('{') else {
+            continue;
+        };
+        if let Some(end) = arg.find('}') {
+            args.push((start, arg[..end].trim()));
+        }
+    }
+    args
+}
+
+/// Skip past a `[...]` argument, if there is one
+fn skip_optional_arg(src: &str) -> &str {
+    match src.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((_, rest)) => rest.trim_start(),
+        None => src,
+    }
+}
+
+/// The packages and classes loaded in a comment-free stretch of LaTeX, as file
+/// names like `amsmath.sty`. `\usepackage{a,b}` loads both `a` and `b`.
+pub fn loaded_packages(src: &str) -> Vec<String> {
+    PACKAGE_COMMANDS
+        .iter()
+        .flat_map(|(command, ext)| {
+            command_args(src, command)
+                .into_iter()
+                .flat_map(|arg| arg.split(','))
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(move |name| format!("{}.{}", name, ext))
+        })
+        .collect()
+}
+
+/// The text between `\begin{document}` and `\end{document}`, or all of it in
+/// a file that doesn't contain the document environment.
+pub fn document_body(src: &str) -> &str {
+    let start = match src.find(r"\begin{document}") {
+        Some(idx) => idx + r"\begin{document}".len(),
+        None => 0,
+    };
+    let body = &src[start..];
+    match body.find(r"\end{document}") {
+        Some(end) => &body[..end],
+        None => body,
+    }
+}
+
+/// The main file and every file it includes, directly or indirectly, in the
+/// order they are first included. Included files that aren't in the source
+/// directory, like those from packages, are skipped.
+pub fn collect_sources(src_dir: &Path, main_file: &str) -> Result<Vec<SourceFile>> {
+    let mut sources = Vec::new();
+    let mut seen = HashSet::new();
+    collect_rec(src_dir, PathBuf::from(main_file), &mut seen, &mut sources)?;
+    Ok(sources)
+}
+
+fn collect_rec(
+    src_dir: &Path,
+    path: PathBuf,
+    seen: &mut HashSet<PathBuf>,
+    sources: &mut Vec<SourceFile>,
+) -> Result<()> {
+    if !seen.insert(path.clone()) {
+        return Ok(());
+    }
+    // Sources needn't be UTF-8, as in older documents using `inputenc` with
+    // Latin-1; the commands we look for are ASCII either way
+    let contents = match std::fs::read(src_dir.join(&path)) {
+        Ok(contents) => strip_comments(&String::from_utf8_lossy(&contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut included: Vec<(usize, PathBuf)> = INCLUDE_COMMANDS
+        .iter()
+        .flat_map(|command| command_occurrences(&contents, command))
+        .map(|(offset, arg)| {
+            let mut included_path = PathBuf::from(arg);
+            if included_path.extension().is_none() {
+                included_path.set_extension("tex");
+            }
+            (offset, included_path)
+        })
+        .collect();
+    included.sort_by_key(|(offset, _)| *offset);
+    sources.push(SourceFile { path, contents });
+    for (_, included_path) in included {
+        collect_rec(src_dir, included_path, seen, sources)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaded_packages_are_found() {
+        let src = "\\documentclass[11pt]{article}\n\\usepackage[utf8]{inputenc}\n\
+                   \\usepackage{amsmath, amssymb}\n\\RequirePackage{xcolor}[2020/01/01]\n\
+                   \\usepackagefoo{nope}\n";
+        assert_eq!(
+            loaded_packages(src),
+            [
+                "inputenc.sty",
+                "amsmath.sty",
+                "amssymb.sty",
+                "xcolor.sty",
+                "article.cls"
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_are_found() {
+        let src = strip_comments(
+            "\\includeonly{chapters/a}\n\\include{chapters/a}\n% \\include{chapters/b}\n\\input{c}",
+        );
+        assert_eq!(command_args(&src, "include"), ["chapters/a"]);
+        assert_eq!(command_args(&src, "input"), ["c"]);
+    }
+}
@@ -0,0 +1,87 @@
+//! Post-processing of SyncTeX data.
+//!
+//! Largo compiles `_start.tex` from within the build directory, so the input
+//! paths that the engine records are relative to the build directory, and the
+//! start file itself appears among them. Editors resolve these paths to jump
+//! between the document and its source, so we rewrite them to point into the
+//! source directory.
+
+use std::io::{BufRead, Write};
+
+use typedir::PathBuf as P;
+
+use crate::{dirs, Result};
+
+/// Rewrite the `Input:` records of a gzipped SyncTeX file in place
+pub fn relocate(
+    synctex_file: &P<dirs::BuildFile>,
+    build_dir: &P<dirs::BuildDir>,
+    main_file: &P<dirs::SrcFile>,
+) -> Result<()> {
+    let contents = {
+        let f = std::fs::File::open(synctex_file)?;
+        let mut contents = Vec::new();
+        let reader = std::io::BufReader::new(flate2::read::GzDecoder::new(f));
+        for line in reader.split(b'\n') {
+            let line = line?;
+            match relocate_line(&line, build_dir, main_file) {
+                Some(relocated) => contents.extend_from_slice(relocated.as_bytes()),
+                None => contents.extend_from_slice(&line),
+            }
+            contents.push(b'\n');
+        }
+        contents
+    };
+    let f = std::fs::File::create(synctex_file)?;
+    let mut encoder = flate2::write::GzEncoder::new(f, flate2::Compression::default());
+    encoder.write_all(&contents)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Relocate a single `Input:<tag>:<path>` record, if this line is one
+fn relocate_line(
+    line: &[u8],
+    build_dir: &std::path::Path,
+    main_file: &std::path::Path,
+) -> Option<String> {
+    let line = std::str::from_utf8(line).ok()?;
+    let rest = line.strip_prefix("Input:")?;
+    let (tag, path) = rest.split_once(':')?;
+    let path = normalize(&build_dir.join(path));
+    let path = if path == build_dir.join(dirs::START_FILE) {
+        main_file.to_path_buf()
+    } else {
+        path
+    };
+    Some(format!("Input:{}:{}", tag, path.display()))
+}
+
+/// Drop `.` components and repeated separators, which TeX leaves in place
+fn normalize(path: &std::path::Path) -> std::path::PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn input_records_are_relocated() {
+        let build_dir = std::path::Path::new("/proj/target/dev/build");
+        let main_file = std::path::Path::new("/proj/src/main.tex");
+        let relocate = |line: &str| relocate_line(line.as_bytes(), build_dir, main_file);
+        assert_eq!(
+            relocate("Input:1:./_start.tex").as_deref(),
+            Some("Input:1:/proj/src/main.tex")
+        );
+        assert_eq!(
+            relocate("Input:2:/proj/src//chapter.tex").as_deref(),
+            Some("Input:2:/proj/src/chapter.tex")
+        );
+        assert_eq!(relocate("Output:pdf"), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Moving files to the platform's trash, so that `largo clean --trash` can be
+//! undone. On macOS, this asks the Finder, and on Windows, the shell; elsewhere,
+//! files are moved into the Freedesktop trash in the user's home directory.
+
+use crate::Result;
+
+/// Move a file or directory to the trash
+pub fn move_to_trash(path: &std::path::Path) -> Result<()> {
+    let path = path.canonicalize()?;
+    platform_move_to_trash(&path)
+}
+
+#[cfg(target_os = "macos")]
+fn platform_move_to_trash(path: &std::path::Path) -> Result<()> {
+    let path = path
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    let script = format!(
+        "tell application \"Finder\" to delete POSIX file \"{}\"",
+        path
+    );
+    run_trash_command(std::process::Command::new("osascript").args(["-e", &script]))
+}
+
+#[cfg(windows)]
+fn platform_move_to_trash(path: &std::path::Path) -> Result<()> {
+    let path = path.to_string_lossy().replace('\'', "''");
+    let script = format!(
+        "Add-Type -AssemblyName Microsoft.VisualBasic; \
+         $p = '{}'; \
+         if (Test-Path -LiteralPath $p -PathType Container) {{ \
+         [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteDirectory($p, 'OnlyErrorDialogs', 'SendToRecycleBin') \
+         }} else {{ \
+         [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteFile($p, 'OnlyErrorDialogs', 'SendToRecycleBin') \
+         }}",
+        path
+    );
+    run_trash_command(std::process::Command::new("powershell").args([
+        "-NoProfile",
+        "-NonInteractive",
+        "-Command",
+        &script,
+    ]))
+}
+
+#[cfg(any(target_os = "macos", windows))]
+fn run_trash_command(cmd: &mut std::process::Command) -> Result<()> {
+    let output = cmd.output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to move to the trash: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn platform_move_to_trash(path: &std::path::Path) -> Result<()> {
+    use crate::dirs;
+    use anyhow::anyhow;
+    use typedir::{CreateDir, Extend, PathBuf as P, WriteFile};
+
+    let trash = dirs::TrashDir::try_get()?;
+    let files_dir: P<dirs::TrashFilesDir> = trash.clone().extend(());
+    let info_dir: P<dirs::TrashInfoDir> = trash.extend(());
+    dirs::TrashFilesDir::create_dir_all(&files_dir)?;
+    dirs::TrashInfoDir::create_dir_all(&info_dir)?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("cannot move `{}` to the trash", path.display()))?
+        .to_string_lossy();
+    let info = trash_info(path, chrono::Local::now());
+    for n in 0.. {
+        let trashed_name = match n {
+            0 => name.to_string(),
+            n => format!("{}.{}", name, n),
+        };
+        // Creating the info file claims the name, even against another
+        // program trashing a file of the same name at the same time
+        let info_file: P<dirs::TrashInfoFile> = info_dir
+            .clone()
+            .extend(format!("{}.trashinfo", trashed_name).as_str());
+        match dirs::TrashInfoFile::create_new(&info_file, &info) {
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            res => res?,
+        }
+        let trashed: P<dirs::TrashedFile> = files_dir.clone().extend(trashed_name.as_str());
+        if let Err(err) = std::fs::rename(path, &trashed) {
+            let _ = std::fs::remove_file(&info_file);
+            return Err(match err.kind() {
+                std::io::ErrorKind::CrossesDevices => anyhow!(
+                    "cannot move `{}` to the trash at `{}`, which is on another filesystem",
+                    path.display(),
+                    files_dir.display()
+                ),
+                _ => err.into(),
+            });
+        }
+        break;
+    }
+    Ok(())
+}
+
+/// The contents of the `.trashinfo` file for a path trashed at this time
+#[cfg(all(unix, not(target_os = "macos")))]
+fn trash_info(path: &std::path::Path, time: chrono::DateTime<chrono::Local>) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    // The path is percent-encoded, like the path of a URL
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encoded,
+        time.format("%Y-%m-%dT%H:%M:%S")
+    )
+}
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn trash_info_encodes_paths() {
+        let time = chrono::Local
+            .with_ymd_and_hms(2004, 8, 31, 22, 32, 8)
+            .unwrap();
+        let info = trash_info(std::path::Path::new("/home/me/my thesis/target"), time);
+        assert_eq!(
+            info,
+            "[Trash Info]\nPath=/home/me/my%20thesis/target\nDeletionDate=2004-08-31T22:32:08\n"
+        );
+    }
+}
@@ -20,3 +20,8 @@ pub async fn timed_async<U, O: std::future::Future<Output = U>, F: FnOnce() -> O
     let elapsed = end - start;
     (out, elapsed)
 }
+
+/// Lowercase hexadecimal representation of some bytes, such as a digest
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
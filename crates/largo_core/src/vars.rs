@@ -1,6 +1,13 @@
 //! TeX-build-time variables (macros, that is) defined by Largo.
 
-use crate::{conf::ProfileName, dirs};
+use anyhow::anyhow;
+
+use crate::{
+    conf::{BuildDate, EnvVars, ProfileName, ProjectMetadata, UserVars, VarValue},
+    dirs, Result,
+};
+
+use std::path::Path;
 
 use typedir::PathBuf as P;
 
@@ -8,30 +15,476 @@ use typedir::PathBuf as P;
 #[derive(Debug, Clone)]
 pub struct LargoVars<'a> {
     pub profile: ProfileName<'a>,
-    pub bibliography: Option<&'a str>,
+    /// Every profile the project has, each of which gets a conditional
+    pub profiles: Vec<ProfileName<'a>>,
+    pub project_name: String,
+    /// The file names of the bibliography files, which the bibliography
+    /// processor finds through `BIBINPUTS`
+    pub bibliography: Vec<String>,
     /// FIXME: ideally this should be borrowed, and no allocation necessary
     pub output_directory: P<dirs::BuildDir>,
+    pub metadata: ProjectMetadata<'a>,
+    /// The project's own variables, by macro name
+    pub user: Vec<(String, VarValue)>,
+    /// The commit the project was built from, if it's in a git repository
+    pub git: Option<GitInfo>,
+    pub build_date: BuildDate,
+}
+
+/// Where in its history a project in a git repository was built from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GitInfo {
+    /// The abbreviated hash of the commit checked out
+    pub commit: String,
+    /// Whether tracked files have changed since that commit. This is unknown
+    /// without git itself, when the repository is read directly.
+    pub dirty: Option<bool>,
+}
+
+/// How many digits of a commit's hash to show, as git does by default
+const SHORT_HASH_LEN: usize = 7;
+
+impl GitInfo {
+    /// The commit checked out in the repository containing `root`, if there
+    /// is one. Git is asked first; without git, the repository is read
+    /// directly.
+    pub fn read(root: &Path) -> Option<Self> {
+        let rev_parse = std::process::Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["rev-parse", "--short", "HEAD"])
+            .output();
+        let output = match rev_parse {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Self::read_repository(root)
+            }
+            Err(_) => return None,
+        };
+        // Not a repository, or one without commits
+        if !output.status.success() {
+            return None;
+        }
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let dirty = std::process::Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["status", "--porcelain", "--untracked-files=no"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| !output.stdout.is_empty());
+        Some(Self { commit, dirty })
+    }
+
+    /// Follow `HEAD` in the nearest `.git` directory to a commit
+    fn read_repository(root: &Path) -> Option<Self> {
+        let git_dir = root
+            .ancestors()
+            .map(|dir| dir.join(dirs::GIT_DIR))
+            .find(|git_dir| git_dir.is_dir())?;
+        let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+        let head = head.trim();
+        let hash = match head.strip_prefix("ref: ") {
+            Some(reference) => match std::fs::read_to_string(git_dir.join(reference)) {
+                Ok(hash) => hash.trim().to_string(),
+                // Refs that haven't changed in a while are packed together
+                Err(_) => std::fs::read_to_string(git_dir.join("packed-refs"))
+                    .ok()?
+                    .lines()
+                    .find_map(|line| {
+                        let (hash, name) = line.split_once(' ')?;
+                        (name == reference).then(|| hash.to_string())
+                    })?,
+            },
+            None => head.to_string(),
+        };
+        if hash.len() < SHORT_HASH_LEN || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(Self {
+            commit: hash[..SHORT_HASH_LEN].to_string(),
+            dirty: None,
+        })
+    }
+}
+
+/// The macro defined for a user variable: `short-title` becomes
+/// `LargoVarShortTitle`. TeX macro names can only contain letters, so the
+/// variable name can only contain letters, `-` and `_`.
+pub fn macro_name(var: &str) -> Result<String> {
+    if !var
+        .chars()
+        .all(|c| c.is_ascii_alphabetic() || c == '-' || c == '_')
+    {
+        return Err(anyhow!(
+            "variable name `{}` can only contain letters, `-` and `_`",
+            var
+        ));
+    }
+    camel_case(var)
+        .map(|name| format!("LargoVar{}", name))
+        .ok_or_else(|| anyhow!("variable name `{}` contains no letters", var))
+}
+
+/// The user's variables, by macro name. Two variables that would define the
+/// same macro, like `short-title` and `short_title`, are an error.
+pub fn user_macros(vars: &UserVars) -> Result<Vec<(String, VarValue)>> {
+    let mut macros: Vec<(String, VarValue)> = Vec::new();
+    let mut names: Vec<(String, &str)> = Vec::new();
+    for (var, value) in vars.iter() {
+        let name = macro_name(var)?;
+        if let Some((_, other)) = names.iter().find(|(seen, _)| *seen == name) {
+            return Err(anyhow!(
+                "variables `{}` and `{}` would both define `\\{}`; rename one of them",
+                other,
+                var,
+                name
+            ));
+        }
+        names.push((name.clone(), var));
+        macros.push((name, value.clone()));
+    }
+    Ok(macros)
+}
+
+/// A name made of words separated by `-` or `_`, as it's written in a macro
+/// name: `short-title` becomes `ShortTitle`. `None` if it can't be part of
+/// one, or is empty.
+fn camel_case(name: &str) -> Option<String> {
+    let mut camel = String::new();
+    for part in name.split(['-', '_']) {
+        if !part.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.push(first.to_ascii_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    (!camel.is_empty()).then_some(camel)
+}
+
+/// The environment variable for a macro: `LargoVarShortTitle` becomes
+/// `LARGO_VAR_SHORT_TITLE`
+fn env_name(macro_name: &str) -> String {
+    let mut name = String::new();
+    for c in macro_name.chars() {
+        if c.is_ascii_uppercase() && !name.is_empty() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_uppercase());
+    }
+    name
+}
+
+/// Escape a value so that it's typeset as it's written: `R&D_notes` becomes
+/// `R\&D\_notes`, rather than an error
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str(r"\textbackslash{}"),
+            '^' => escaped.push_str(r"\^{}"),
+            '~' => escaped.push_str(r"\textasciitilde{}"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Makes TeX's special characters, other than braces and backslashes, read
+/// as themselves, so that values like paths can be defined as they are and
+/// handed back to `\input` or `\addbibresource`
+const VERBATIM_CATCODES: &str = r"\catcode`\#=12\catcode`\$=12\catcode`\%=12\catcode`\&=12\catcode`\_=12\catcode`\^=12\catcode`\~=12\relax";
+
+/// A value as it's defined under [`VERBATIM_CATCODES`], where braces and
+/// backslashes still can't be read as themselves, so they're refused
+fn verbatim_value(value: &str) -> Result<&str> {
+    if value.contains(['{', '}', '\\']) {
+        return Err(anyhow!(
+            "`{}` can't be passed to TeX, because it contains a brace or a backslash",
+            value
+        ));
+    }
+    Ok(value)
+}
+
+/// A path as it's defined, with `/` for separators, which TeX understands on
+/// every platform
+fn path_value(path: &str) -> Result<String> {
+    verbatim_value(&path.replace('\\', "/")).map(str::to_string)
+}
+
+/// The bibliography files added one at a time, since `\addbibresource` only
+/// takes one
+fn bib_resources(bibliography: &[String]) -> Result<String> {
+    bibliography
+        .iter()
+        .map(|bib| Ok(format!(r"\addbibresource{{{}}}", path_value(bib)?)))
+        .collect()
+}
+
+/// A user variable's definition: escaped, unless it was given raw
+fn user_value(value: &VarValue) -> String {
+    match value {
+        VarValue::Text(text) => escape(text),
+        VarValue::Raw { raw } => raw.clone(),
+    }
 }
 
 // For use in `LargoVars::to_defs`
 macro_rules! write_lv {
     ($defs:expr, $var:expr, $val:expr) => {
-        write!($defs, r#"\def\Largo{}{{{}}}"#, $var, $val).expect("internal error");
+        writeln!($defs, r#"\def\Largo{}{{{}}}"#, $var, $val).expect("internal error");
     };
 }
 
 impl<'a> LargoVars<'a> {
-    pub fn to_defs(self) -> String {
+    /// The definitions of the variables, one to a line. Values are escaped,
+    /// except for raw user variables, so that any string from the
+    /// configuration is safe to define.
+    pub fn to_defs(&self) -> Result<String> {
         use std::fmt::Write;
         let mut defs = String::new();
         {
             let defs = &mut defs;
-            write_lv!(defs, "Profile", &self.profile);
-            if let Some(bib) = self.bibliography {
-                write_lv!(defs, "Bibliography", bib);
+            write_lv!(defs, "Profile", escape(self.profile.as_ref()));
+            write_lv!(defs, "ProjectName", escape(&self.project_name));
+            // Paths are defined in a group of their own, where their
+            // characters are read as themselves, so they're defined globally.
+            // The group is kept to one line, where line ends can't add spaces.
+            write!(defs, r"\begingroup{}", VERBATIM_CATCODES).expect("internal error");
+            let output_directory = path_value(&self.output_directory.display().to_string())?;
+            write!(defs, r"\gdef\LargoOutputDirectory{{{}}}", output_directory)
+                .expect("internal error");
+            if !self.bibliography.is_empty() {
+                let bibliography = path_value(&self.bibliography.join(","))?;
+                write!(defs, r"\gdef\LargoBibliography{{{}}}", bibliography)
+                    .expect("internal error");
+                let resources = bib_resources(&self.bibliography)?;
+                write!(defs, r"\gdef\LargoAddBibResources{{{}}}", resources)
+                    .expect("internal error");
+            }
+            defs.push_str("\\endgroup\n");
+            write_lv!(defs, "BuildDate", self.build_date);
+            if let Some(version) = &self.metadata.version {
+                write_lv!(defs, "Version", escape(version));
+                write_lv!(defs, "ProjectVersion", escape(version));
+            }
+            if let Some(git) = &self.git {
+                write_lv!(defs, "GitCommit", git.commit);
+                if let Some(dirty) = git.dirty {
+                    write_lv!(defs, "GitDirty", if dirty { "-dirty" } else { "" });
+                }
+            }
+            if !self.metadata.authors.is_empty() {
+                let authors: Vec<_> = self.metadata.authors.iter().map(|a| escape(a)).collect();
+                write_lv!(defs, "Authors", authors.join(r" \and "));
+            }
+            if let Some(description) = &self.metadata.description {
+                write_lv!(defs, "Description", escape(description));
+            }
+            if let Some(license) = &self.metadata.license {
+                write_lv!(defs, "License", escape(license));
+            }
+            for (name, value) in &self.user {
+                writeln!(defs, r#"\def\{}{{{}}}"#, name, user_value(value))
+                    .expect("internal error");
+            }
+        }
+        Ok(defs)
+    }
+
+    /// The variables as they're given to the build's commands, like the
+    /// bibliography processor: `\LargoOutputDirectory` is
+    /// `LARGO_OUTPUT_DIRECTORY`, and `\LargoVarShortTitle` is
+    /// `LARGO_VAR_SHORT_TITLE`. Values aren't escaped, since they aren't read
+    /// by TeX.
+    pub fn to_env(&self) -> EnvVars {
+        let mut env = vec![
+            ("PROFILE", self.profile.to_string()),
+            ("PROJECT_NAME", self.project_name.clone()),
+            (
+                "OUTPUT_DIRECTORY",
+                self.output_directory.display().to_string(),
+            ),
+            ("BUILD_DATE", self.build_date.to_string()),
+        ];
+        if !self.bibliography.is_empty() {
+            env.push(("BIBLIOGRAPHY", self.bibliography.join(",")));
+        }
+        if let Some(version) = &self.metadata.version {
+            env.push(("VERSION", version.to_string()));
+            env.push(("PROJECT_VERSION", version.to_string()));
+        }
+        if let Some(git) = &self.git {
+            env.push(("GIT_COMMIT", git.commit.clone()));
+            if let Some(dirty) = git.dirty {
+                env.push(("GIT_DIRTY", if dirty { "-dirty" } else { "" }.to_string()));
+            }
+        }
+        if !self.metadata.authors.is_empty() {
+            env.push(("AUTHORS", self.metadata.authors.join(", ")));
+        }
+        if let Some(description) = &self.metadata.description {
+            env.push(("DESCRIPTION", description.to_string()));
+        }
+        if let Some(license) = &self.metadata.license {
+            env.push(("LICENSE", license.to_string()));
+        }
+        let env = env
+            .into_iter()
+            .map(|(name, value)| (format!("LARGO_{}", name), value));
+        let user = self.user.iter().map(|(name, value)| {
+            let value = match value {
+                VarValue::Text(text) => text.clone(),
+                VarValue::Raw { raw } => raw.clone(),
+            };
+            (env_name(name), value)
+        });
+        env.chain(user).collect()
+    }
+
+    /// The `largo-vars` package, which defines the variables, and a
+    /// conditional for each of those that may be left undefined, like
+    /// `\ifLargoBibliography`
+    pub fn to_package(&self) -> Result<String> {
+        use std::fmt::Write;
+        let mut package = String::new();
+        writeln!(package, r"\NeedsTeXFormat{{LaTeX2e}}")?;
+        writeln!(
+            package,
+            r"\ProvidesPackage{{{}}}[Variables of this Largo build]",
+            dirs::LARGO_VARS_PACKAGE
+        )?;
+        package.push_str(&self.to_defs()?);
+        let git_dirty = self.git.as_ref().and_then(|git| git.dirty);
+        let conditionals = [
+            ("Bibliography", !self.bibliography.is_empty()),
+            ("Version", self.metadata.version.is_some()),
+            ("Git", self.git.is_some()),
+            ("GitDirty", git_dirty == Some(true)),
+        ];
+        for (name, set) in conditionals {
+            writeln!(package, r"\newif\ifLargo{}", name)?;
+            if set {
+                writeln!(package, r"\Largo{}true", name)?;
             }
-            write_lv!(defs, "OutputDirectory", &self.output_directory.display());
         }
-        defs
+        // The profile is compared as a string, so that `\IfLargoProfile`
+        // works for any profile, whatever its name
+        writeln!(
+            package,
+            r"\begingroup{}\xdef\Largo@profile{{\detokenize{{{}}}}}\endgroup",
+            VERBATIM_CATCODES,
+            verbatim_value(self.profile.as_ref())?
+        )?;
+        writeln!(
+            package,
+            r"\newcommand\IfLargoProfile[1]{{\edef\Largo@tmp{{\detokenize{{#1}}}}\ifx\Largo@tmp\Largo@profile\expandafter\@firstoftwo\else\expandafter\@secondoftwo\fi}}"
+        )?;
+        for profile in &self.profiles {
+            let Some(name) = camel_case(profile.as_ref()) else {
+                continue;
+            };
+            writeln!(package, r"\newif\ifLargoProfile{}", name)?;
+            if *profile == self.profile {
+                writeln!(package, r"\LargoProfile{}true", name)?;
+            }
+        }
+        writeln!(package, r"\endinput")?;
+        Ok(package)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macro_names() {
+        assert_eq!(macro_name("institution").unwrap(), "LargoVarInstitution");
+        assert_eq!(macro_name("short-title").unwrap(), "LargoVarShortTitle");
+        assert_eq!(macro_name("print_run").unwrap(), "LargoVarPrintRun");
+        assert!(macro_name("edition2").is_err());
+        assert!(macro_name("-").is_err());
+        assert_eq!(
+            camel_case("release-candidate").as_deref(),
+            Some("ReleaseCandidate")
+        );
+        assert_eq!(camel_case("ci2"), None);
+        assert_eq!(env_name("LargoVarShortTitle"), "LARGO_VAR_SHORT_TITLE");
+        let vars: UserVars = toml::from_str(
+            "short-title = \"A\"\n\
+             short_title = \"B\"",
+        )
+        .unwrap();
+        assert!(user_macros(&vars).is_err());
+    }
+
+    #[test]
+    fn values_are_escaped() {
+        assert_eq!(escape("R&D_notes, 100%"), r"R\&D\_notes, 100\%");
+        assert_eq!(
+            escape(r"~{#}\^"),
+            r"\textasciitilde{}\{\#\}\textbackslash{}\^{}"
+        );
+        assert_eq!(
+            path_value(r"bib\refs_2024.bib").unwrap(),
+            "bib/refs_2024.bib"
+        );
+        assert!(path_value("refs}.bib").is_err());
+        assert!(verbatim_value(r"\relax").is_err());
+        assert_eq!(
+            user_value(&VarValue::Raw {
+                raw: r"\textbf{DRAFT}".to_string()
+            }),
+            r"\textbf{DRAFT}"
+        );
+    }
+
+    #[test]
+    fn bibliographies_are_added_one_at_a_time() {
+        let bibliography = ["refs.bib".to_string(), r"bib\more_refs.bib".to_string()];
+        assert_eq!(
+            bib_resources(&bibliography).unwrap(),
+            r"\addbibresource{refs.bib}\addbibresource{bib/more_refs.bib}"
+        );
+    }
+
+    #[test]
+    fn repositories_are_read_without_git() {
+        let root = std::env::temp_dir().join(format!("largo-git-{}", std::process::id()));
+        let git_dir = root.join(dirs::GIT_DIR);
+        std::fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::write(
+            git_dir.join("packed-refs"),
+            "# pack-refs with: peeled fully-peeled sorted\n\
+             0123456789abcdef0123456789abcdef01234567 refs/heads/main\n",
+        )
+        .unwrap();
+        let packed = GitInfo::read_repository(&root.join("src"));
+        std::fs::write(
+            git_dir.join("refs/heads/main"),
+            "fedcba9876543210fedcba9876543210fedcba98\n",
+        )
+        .unwrap();
+        let loose = GitInfo::read_repository(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(packed.unwrap().commit, "0123456");
+        assert_eq!(
+            loose,
+            Some(GitInfo {
+                commit: "fedcba9".to_string(),
+                dirty: None
+            })
+        );
     }
 }
@@ -0,0 +1,151 @@
+//! Word counts of a document's text, in the spirit of `texcount`. Markup, math,
+//! and the arguments of commands like `\label` and `\cite` aren't counted.
+
+use std::path::{Path, PathBuf};
+
+use crate::{scan, Result};
+
+/// Commands whose arguments are never part of the text
+const SKIPPED_ARG_COMMANDS: &[&str] = &[
+    "begin",
+    "end",
+    "label",
+    "ref",
+    "eqref",
+    "pageref",
+    "cite",
+    "citep",
+    "citet",
+    "input",
+    "include",
+    "subfile",
+    "usepackage",
+    "documentclass",
+    "includegraphics",
+    "bibliography",
+    "bibliographystyle",
+    "url",
+];
+
+#[derive(Debug)]
+pub struct FileWords {
+    /// Path relative to the source directory
+    pub path: PathBuf,
+    pub words: usize,
+}
+
+/// Count the words in each file of the document whose entry point is
+/// `main_file`, following its `\input`s and `\include`s.
+pub fn count_words(src_dir: &Path, main_file: &str) -> Result<Vec<FileWords>> {
+    let sources = scan::collect_sources(src_dir, main_file)?;
+    let counts = sources
+        .into_iter()
+        .map(|source| FileWords {
+            words: count_text(scan::document_body(&source.contents)),
+            path: source.path,
+        })
+        .collect();
+    Ok(counts)
+}
+
+/// Count the words in a comment-free stretch of LaTeX
+pub fn count_text(src: &str) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                in_word = false;
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    name.push(c);
+                }
+                if !name.is_empty() {
+                    if SKIPPED_ARG_COMMANDS.contains(&name.as_str()) {
+                        skip_args(&mut chars);
+                    }
+                    continue;
+                }
+                // A control symbol, like `\%`, or the start of `\[ ... \]` or
+                // `\( ... \)` math
+                match chars.next() {
+                    Some('[') => skip_math(&mut chars, ']'),
+                    Some('(') => skip_math(&mut chars, ')'),
+                    _ => (),
+                }
+            }
+            '$' => {
+                in_word = false;
+                // Skip to the closing `$`, or `$$`
+                let display = chars.next_if_eq(&'$').is_some();
+                while let Some(c) = chars.next() {
+                    if c == '\\' {
+                        chars.next();
+                    } else if c == '$' {
+                        if display {
+                            chars.next_if_eq(&'$');
+                        }
+                        break;
+                    }
+                }
+            }
+            c if c.is_alphanumeric() => {
+                if !in_word {
+                    words += 1;
+                    in_word = true;
+                }
+            }
+            // Apostrophes and hyphens join the parts of a word
+            '\'' | '-' if in_word => (),
+            _ => in_word = false,
+        }
+    }
+    words
+}
+
+/// Skip to the end of math opened with `\[` or `\(`
+fn skip_math(chars: &mut std::iter::Peekable<std::str::Chars>, close: char) {
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.next_if_eq(&close).is_some() {
+            return;
+        }
+    }
+}
+
+/// Skip any optional and required arguments following a command
+fn skip_args(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let close = match chars.peek() {
+            Some('{') => '}',
+            Some('[') => ']',
+            _ => return,
+        };
+        let open = chars.next().unwrap();
+        let mut depth = 1;
+        for c in chars.by_ref() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_is_not_counted() {
+        let src = r"\section{Two words} Here's some \emph{well-known} text, with $x + y$
+            math\footnote{and a note}. \label{sec:one} See \cite[p.~3]{knuth84}.";
+        // Two words / Here's some well-known text with / math and a note / See
+        assert_eq!(count_text(src), 12);
+    }
+}
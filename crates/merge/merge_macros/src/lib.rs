@@ -1,3 +1,6 @@
+// `darling`'s generated code for `#[darling(default)]` trips this lint
+#![allow(clippy::manual_unwrap_or_default)]
+
 extern crate proc_macro;
 
 use quote::quote;
@@ -12,8 +15,8 @@ pub fn derive_command(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     let derive_input = parse_macro_input!(input as DeriveInput);
     let output = derive_command_inner(derive_input);
     match output {
-        Ok(ts) => ts.into(),
-        Err(err) => syn::Error::to_compile_error(&err.into()),
+        Ok(ts) => ts,
+        Err(err) => syn::Error::to_compile_error(&err),
     }
     .into()
 }
@@ -20,12 +20,9 @@ impl<T> Merge for Option<T> {
     }
 
     fn merge_right(&mut self, other: Self) -> &mut Self {
-        match other {
-            Some(_) => {
-                *self = other;
-            }
-            None => (),
-        };
+        if other.is_some() {
+            *self = other;
+        }
         self
     }
 }
@@ -64,12 +61,12 @@ impl<K: Ord, V: Merge> Merge for std::collections::BTreeMap<K, V> {
 
 impl<T> Merge for Vec<T> {
     fn merge_left(&mut self, other: Self) -> &mut Self {
-        self.extend(other.into_iter());
+        self.extend(other);
         self
     }
 
     fn merge_right(&mut self, other: Self) -> &mut Self {
-        self.extend(other.into_iter());
+        self.extend(other);
         self
     }
 }
@@ -106,7 +103,18 @@ impl<K: std::hash::Hash + Eq + PartialEq, V: Merge> Merge for std::collections::
     }
 }
 
-impl<'a> Merge for &'a str {
+impl Merge for &str {
+    fn merge_left(&mut self, _: Self) -> &mut Self {
+        self
+    }
+
+    fn merge_right(&mut self, other: Self) -> &mut Self {
+        *self = other;
+        self
+    }
+}
+
+impl<B: ToOwned + ?Sized> Merge for std::borrow::Cow<'_, B> {
     fn merge_left(&mut self, _: Self) -> &mut Self {
         self
     }
@@ -117,7 +125,7 @@ impl<'a> Merge for &'a str {
     }
 }
 
-impl<'a> Merge for &'a std::path::PathBuf {
+impl Merge for &std::path::PathBuf {
     fn merge_left(&mut self, _: Self) -> &mut Self {
         self
     }
@@ -149,6 +157,7 @@ merge_basic_types! {
     u8, u16, u32, u64, u128,
     i8, i16, i32, i64, i128,
     (),
+    String,
 }
 
 #[cfg(test)]
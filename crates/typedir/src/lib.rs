@@ -1,5 +1,7 @@
 //! Utilities for building strongly-typed directory structures
 
+#![forbid(unsafe_code)]
+
 use std::marker::PhantomData;
 
 /// This module mimics `#[sealed]` traits, which are not yet implemented in
@@ -17,14 +19,40 @@ pub trait Link {}
 impl<T> Link for T {}
 
 pub trait Child<P: Node, L: Link>: Node {
-    fn link<'a>(l: &'a L) -> &'a std::path::Path;
+    fn link(l: &L) -> &std::path::Path;
 }
 
 pub trait Extend<L: Link, T>: __sealed::Extend<L, T> {
     fn extend(self, link: L) -> T;
 }
 
-#[repr(C)]
+/// A node declared inside another in `typedir!` with a constant link, which
+/// can be navigated back up to with `PathBuf::parent`. A parametric link can
+/// have any number of components, or be absolute, so it can't be undone from
+/// the path alone; nor can a node shared by several parents tell which one
+/// it's under. Neither kind of node has a parent.
+pub trait HasParent: Node {
+    type Parent: Node;
+
+    /// How many components the link from the parent has
+    fn link_depth() -> usize;
+}
+
+/// Implemented once for every type, and again for those with a parent, so
+/// that naming it for a node with a parent is ambiguous. A `use` link checks
+/// this, so that a node with a parent can't be shared.
+#[doc(hidden)]
+pub trait __NoParent<A> {
+    fn check() {}
+}
+
+impl<T> __NoParent<()> for T {}
+
+#[doc(hidden)]
+pub struct __WithParent;
+
+impl<T: HasParent> __NoParent<__WithParent> for T {}
+
 #[derive(Clone, Debug)]
 pub struct PathBuf<N: Node> {
     path: std::path::PathBuf,
@@ -40,6 +68,56 @@ impl<N: Node> PathBuf<N> {
     }
 }
 
+impl<N: Node> PathBuf<N> {
+    /// This path, relative to another node's, with `..` for each component of
+    /// the other that isn't shared. This is worked out from the paths alone,
+    /// so both should be absolute, or relative to the same directory, and
+    /// neither should contain `..`; canonicalize them first if need be.
+    pub fn relative_to<M: Node>(&self, other: &PathBuf<M>) -> std::path::PathBuf {
+        relative_path(&self.path, &other.path)
+    }
+
+    /// The canonical, absolute form of this path, with every symbolic link
+    /// resolved. Like `Path::canonicalize`, this fails if the path doesn't
+    /// exist.
+    pub fn canonicalize(&self) -> std::io::Result<Self> {
+        Ok(Self {
+            path: self.path.canonicalize()?,
+            m: PhantomData,
+        })
+    }
+}
+
+fn relative_path(path: &std::path::Path, base: &std::path::Path) -> std::path::PathBuf {
+    let mut path = path.components().peekable();
+    let mut base = base.components().peekable();
+    while let (Some(p), Some(b)) = (path.peek(), base.peek()) {
+        if p != b {
+            break;
+        }
+        path.next();
+        base.next();
+    }
+    base.map(|_| std::path::Component::ParentDir)
+        .chain(path)
+        .collect()
+}
+
+impl<N: HasParent> PathBuf<N> {
+    /// The path of this node's parent: the inverse of `Extend`. This shadows
+    /// `Path::parent`, which is still there through `as_ref`.
+    pub fn parent(&self) -> PathBuf<N::Parent> {
+        let mut path = self.path.clone();
+        for _ in 0..N::link_depth() {
+            path.pop();
+        }
+        PathBuf {
+            path,
+            m: PhantomData,
+        }
+    }
+}
+
 impl<L, P, C> __sealed::Extend<L, PathBuf<C>> for PathBuf<P>
 where
     L: Link,
@@ -62,13 +140,298 @@ where
     }
 }
 
-#[repr(C)]
+/// A typed path is serialized as the path itself. Deserializing one can't
+/// check that the path really is a path to such a node, so only deserialize
+/// paths that were serialized from the same node type.
+#[cfg(feature = "serde")]
+impl<N: Node> serde::Serialize for PathBuf<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.path.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, N: Node> serde::Deserialize<'de> for PathBuf<N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self {
+            path: std::path::PathBuf::deserialize(deserializer)?,
+            m: PhantomData,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a, N: Node> serde::Serialize for PathRef<'a, N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.guard.path.serialize(serializer)
+    }
+}
+
+/// A file node that can be read. Like the other filesystem operations, this is
+/// opt-in: a node declares it in `typedir!`, as in `node Config: ReadFile;`.
+pub trait ReadFile: Node {
+    fn read<P: AsPath<Self>>(path: &P) -> std::io::Result<Vec<u8>> {
+        std::fs::read(&**path)
+    }
+
+    fn read_to_string<P: AsPath<Self>>(path: &P) -> std::io::Result<String> {
+        std::fs::read_to_string(&**path)
+    }
+}
+
+/// A file node that can be written
+pub trait WriteFile: Node {
+    /// Write a file, replacing whatever was there
+    fn write<P: AsPath<Self>, C: AsRef<[u8]>>(path: &P, contents: C) -> std::io::Result<()> {
+        std::fs::write(&**path, contents)
+    }
+
+    /// Write a file, replacing whatever was there all at once, so that nobody
+    /// reads it half-written
+    fn replace<P: AsPath<Self>, C: AsRef<[u8]>>(path: &P, contents: C) -> std::io::Result<()> {
+        let staging = write_staging(path, contents.as_ref())?;
+        std::fs::rename(&staging, &**path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&staging);
+        })
+    }
+
+    /// Write a file that mustn't exist yet, failing with `AlreadyExists` if
+    /// it does. The file appears with all of its contents at once: they're
+    /// written off to the side and linked into place, which fails rather than
+    /// replacing a file someone else has created in the meantime.
+    fn create_new<P: AsPath<Self>, C: AsRef<[u8]>>(path: &P, contents: C) -> std::io::Result<()> {
+        let staging = write_staging(path, contents.as_ref())?;
+        let linked = std::fs::hard_link(&staging, &**path);
+        let _ = std::fs::remove_file(&staging);
+        match linked {
+            // Some filesystems have no hard links
+            Err(err) if err.kind() == std::io::ErrorKind::Unsupported => {
+                use std::io::Write;
+                let mut file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&**path)?;
+                file.write_all(contents.as_ref())
+            }
+            linked => linked,
+        }
+    }
+}
+
+/// Write the contents of a file to a new file next to it, named uniquely to
+/// this process and call
+fn write_staging(path: &std::path::Path, contents: &[u8]) -> std::io::Result<std::path::PathBuf> {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    let mut staging_name = std::ffi::OsString::from(".");
+    staging_name.push(name);
+    staging_name.push(format!(
+        ".{}.{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let staging = path.with_file_name(staging_name);
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&staging)?;
+    if let Err(err) = file.write_all(contents).and_then(|()| file.sync_all()) {
+        let _ = std::fs::remove_file(&staging);
+        return Err(err);
+    }
+    Ok(staging)
+}
+
+/// A directory node that can be created
+pub trait CreateDir: Node {
+    fn create_dir<P: AsPath<Self>>(path: &P) -> std::io::Result<()> {
+        std::fs::create_dir(&**path)
+    }
+
+    /// Create the directory and any of its parents that don't exist
+    fn create_dir_all<P: AsPath<Self>>(path: &P) -> std::io::Result<()> {
+        std::fs::create_dir_all(&**path)
+    }
+}
+
+/// A node that must exist wherever its parent does, which `validate` reports
+/// if it doesn't. Declared like a filesystem operation, as in
+/// `node Config: ReadFile + Required;`.
+pub trait Required: Node {}
+
+/// What a node is on the filesystem, going by what it's declared with: a
+/// node with children, or that can be created as a directory, is a
+/// directory, and one that can be read or written is a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+    /// Nothing is declared that says which
+    Any,
+}
+
+/// Something wrong with a path, according to the tree it's declared in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A required node doesn't exist
+    Missing(std::path::PathBuf, Kind),
+    /// A directory node is something other than a directory
+    NotADir(std::path::PathBuf),
+    /// A file node is something other than a file
+    NotAFile(std::path::PathBuf),
+    /// The path couldn't be looked at
+    Unreadable(std::path::PathBuf, std::io::ErrorKind),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing(path, Kind::Dir) => {
+                write!(f, "directory `{}` is missing", path.display())
+            }
+            Problem::Missing(path, _) => write!(f, "`{}` is missing", path.display()),
+            Problem::NotADir(path) => write!(f, "`{}` is not a directory", path.display()),
+            Problem::NotAFile(path) => write!(f, "`{}` is not a file", path.display()),
+            Problem::Unreadable(path, kind) => {
+                write!(f, "cannot read `{}`: {}", path.display(), kind)
+            }
+        }
+    }
+}
+
+/// A node that can be checked against the filesystem. This is implemented by
+/// `typedir!` for every node.
+pub trait Validate: Node {
+    const KIND: Kind;
+    const REQUIRED: bool;
+
+    /// Check the children of a node at `path` that have constant links.
+    /// Parametric children can't be enumerated, so they aren't checked.
+    fn validate_children(path: &std::path::Path, problems: &mut Vec<Problem>);
+}
+
+impl<N: Validate> PathBuf<N> {
+    /// Check that this path is what its node is declared to be, and that
+    /// every required node beneath it exists, returning whatever is wrong
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        __validate::<N>(&self.path, &mut problems);
+        problems
+    }
+}
+
+#[doc(hidden)]
+pub fn __validate<N: Validate>(path: &std::path::Path, problems: &mut Vec<Problem>) {
+    let meta = match std::fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            if N::REQUIRED {
+                problems.push(Problem::Missing(path.to_path_buf(), N::KIND));
+            }
+            return;
+        }
+        Err(err) => {
+            problems.push(Problem::Unreadable(path.to_path_buf(), err.kind()));
+            return;
+        }
+    };
+    match N::KIND {
+        Kind::Dir if !meta.is_dir() => problems.push(Problem::NotADir(path.to_path_buf())),
+        Kind::File if !meta.is_file() => problems.push(Problem::NotAFile(path.to_path_buf())),
+        Kind::Dir => N::validate_children(path, problems),
+        _ => {}
+    }
+}
+
+/// A root node that's found by searching upwards from a directory, the way a
+/// project is found by its manifest.
+pub trait FindRoot: Node {
+    /// The file or directory whose presence marks a root
+    const MARKER: &'static str;
+
+    /// The typed path of a root that's been found. Roots can't be constructed
+    /// outside the crate that declares them, so that crate has to do it.
+    fn at(path: std::path::PathBuf) -> PathBuf<Self>;
+
+    /// Whether `dir` is a root. By default, it's one if it contains the
+    /// marker, but a root can ask more of it, like what the marker says.
+    fn is_root(dir: &std::path::Path) -> bool {
+        dir.join(Self::MARKER).exists()
+    }
+
+    /// The nearest root at or above `dir`
+    fn find_from(dir: &std::path::Path) -> Option<PathBuf<Self>> {
+        find_upward(dir, Self::is_root).map(|root| Self::at(root.to_path_buf()))
+    }
+}
+
+/// The nearest of `dir` and its ancestors that satisfies `is_root`. The search
+/// stops at the edge of the filesystem `dir` is on, beyond which a marker
+/// probably belongs to someone else.
+pub fn find_upward(
+    dir: &std::path::Path,
+    mut is_root: impl FnMut(&std::path::Path) -> bool,
+) -> Option<&std::path::Path> {
+    let dir_device = device(dir);
+    dir.ancestors()
+        .take_while(|ancestor| dir_device.is_none() || device(ancestor) == dir_device)
+        .find(|ancestor| is_root(ancestor))
+}
+
+/// The device a file is on, where there's such a thing
+#[cfg(unix)]
+fn device(path: &std::path::Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|meta| meta.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_path: &std::path::Path) -> Option<u64> {
+    None
+}
+
+/// A path borrowed from a `PathBuf`, or from another `PathRef`, with a link
+/// pushed onto it. When it's dropped, the path is put back as it was before,
+/// so that the path it was borrowed from can be used again.
 #[derive(Debug)]
 pub struct PathRef<'a, N: Node> {
-    path: &'a mut std::path::PathBuf,
+    guard: Truncate<'a>,
     m: PhantomData<N>,
 }
 
+/// Puts a borrowed path back as it was when it was borrowed. This is kept
+/// apart from `PathRef`, which has no `Drop` impl of its own, so that a
+/// `PathRef` can be extended by value into another that shares its guard.
+#[derive(Debug)]
+struct Truncate<'a> {
+    path: &'a mut std::path::PathBuf,
+    /// The path as it was borrowed. Popping components wouldn't do: an
+    /// absolute link replaces the whole path, and one like `./a` doesn't
+    /// count its `.` among the components.
+    original: std::path::PathBuf,
+}
+
+impl<'a> Drop for Truncate<'a> {
+    fn drop(&mut self) {
+        std::mem::swap(self.path, &mut self.original);
+    }
+}
+
+impl<'a, N: Node> PathRef<'a, N> {
+    fn push<L: Link, C: Child<N, L>>(path: &'a mut std::path::PathBuf, link: &L) -> PathRef<'a, C> {
+        let original = path.clone();
+        path.push(C::link(link));
+        PathRef {
+            guard: Truncate { path, original },
+            m: PhantomData,
+        }
+    }
+}
+
 impl<'a1, 'a2, N1, N2, L> __sealed::Extend<L, PathRef<'a2, N2>> for &'a2 mut PathRef<'a1, N1>
 where
     'a1: 'a2,
@@ -85,59 +448,57 @@ where
     L: Link,
 {
     fn extend(self, link: L) -> PathRef<'a2, N2> {
-        self.path.push(N2::link(&link));
-        // SAFETY: TODO
-        unsafe {
-            let ptr = self.path as *mut std::path::PathBuf;
-            PathRef {
-                path: (&mut *ptr),
-                m: PhantomData,
-            }
-        }
+        PathRef::<N1>::push(self.guard.path, &link)
     }
 }
 
-impl<'a, N1, N2, L> __sealed::Extend<L, PathRef<'a, N2>> for &'a mut PathBuf<N1>
+impl<'a, N1, N2, L> __sealed::Extend<L, PathRef<'a, N2>> for PathRef<'a, N1>
 where
     N1: Node,
     N2: Node + Child<N1, L>,
     L: Link,
 {
 }
-impl<'a, N1, N2, L> Extend<L, PathRef<'a, N2>> for &'a mut PathBuf<N1>
+/// Extending a `PathRef` by value gives one that, when dropped, cuts the path
+/// back to what it was before *either* link was pushed
+impl<'a, N1, N2, L> Extend<L, PathRef<'a, N2>> for PathRef<'a, N1>
 where
     N1: Node,
     N2: Node + Child<N1, L>,
     L: Link,
 {
     fn extend(self, link: L) -> PathRef<'a, N2> {
-        self.path.push(N2::link(&link));
-        // SAFETY: TODO
-        unsafe {
-            let ptr = (&mut self.path) as *mut std::path::PathBuf;
-            PathRef {
-                path: (&mut *ptr),
-                m: PhantomData,
-            }
+        self.guard.path.push(N2::link(&link));
+        PathRef {
+            guard: self.guard,
+            m: PhantomData,
         }
     }
 }
 
-impl<'a, N: Node> std::ops::Drop for PathRef<'a, N> {
-    fn drop(&mut self) {
-        self.path.pop();
+impl<'a, N1, N2, L> __sealed::Extend<L, PathRef<'a, N2>> for &'a mut PathBuf<N1>
+where
+    N1: Node,
+    N2: Node + Child<N1, L>,
+    L: Link,
+{
+}
+impl<'a, N1, N2, L> Extend<L, PathRef<'a, N2>> for &'a mut PathBuf<N1>
+where
+    N1: Node,
+    N2: Node + Child<N1, L>,
+    L: Link,
+{
+    fn extend(self, link: L) -> PathRef<'a, N2> {
+        PathRef::<N1>::push(&mut self.path, &link)
     }
 }
 
+/// Another name for `path!`
 #[macro_export]
 macro_rules! dir {
-    ($root:expr) => { $root };
-    ($root:expr => $sub:ty $(=>$($tail:tt)*)*) => {
-        {
-            let r = <$sub>::from(dir!($root));
-            dir!(r $(/$($tail)*)*)
-        }
-
+    ($($args:tt)*) => {
+        $crate::path!($($args)*)
     };
 }
 
@@ -171,19 +532,19 @@ impl<'a, N: Node> std::ops::Deref for PathRef<'a, N> {
     type Target = std::path::Path;
 
     fn deref(&self) -> &Self::Target {
-        &self.path
+        self.guard.path
     }
 }
 
 impl<'a, N: Node> AsRef<std::path::Path> for PathRef<'a, N> {
     fn as_ref(&self) -> &std::path::Path {
-        &self.path
+        self.guard.path
     }
 }
 
 impl<'a, N: Node> std::borrow::Borrow<std::path::Path> for PathRef<'a, N> {
     fn borrow(&self) -> &std::path::Path {
-        &self.path
+        self.guard.path
     }
 }
 
@@ -202,80 +563,263 @@ impl<'a, N: Node> __sealed::AsPath<N> for PathRef<'a, N> {}
 impl<'a, N: Node> AsPath<N> for PathRef<'a, N> {}
 
 // An internal helper macro for assembling subdirectories in a context
-// of their parent
+// of their parent. The attributes before each node, including its doc
+// comments, are collected in brackets before it's matched: matching them
+// with a `$link:expr` right after them would be ambiguous.
+#[doc(hidden)]
 #[macro_export]
 macro_rules! __parent_ctx {
-    ($Parent:ident / ) => {};
-    ($Parent:ident / $link:expr => node $Name:ident $({$($subdirs:tt)*})?; $($tail:tt)*) => {
+    // A node linked to with `use`, which mustn't have a parent of its own
+    (@shared [$(#[$attr:meta])*] $Name:ident) => {
+        $(#[$attr])*
+        const _: fn() = || <$Name as $crate::__NoParent<_>>::check();
+    };
+    ($Parent:ident [$(#[$attr:meta])*] / ) => {};
+    ($Parent:ident [$(#[$attr:meta])*] / #[$next:meta] $($tail:tt)*) => {
+        $crate::__parent_ctx!($Parent [$(#[$attr])* #[$next]] / $($tail)*);
+    };
+    // A node declared elsewhere, which is also a child of this one
+    ($Parent:ident [$(#[$attr:meta])*] / $link:expr => use $Name:ident; $($tail:tt)*) => {
+        $(#[$attr])*
+        impl $crate::Child<$Parent, ()> for $Name {
+            fn link(_: &()) -> &::std::path::Path { ($link).as_ref() }
+        }
+
+        $crate::__parent_ctx!(@shared [$(#[$attr])*] $Name);
+        $crate::__parent_ctx!($Parent [] / $($tail)*);
+    };
+    ($Parent:ident [$(#[$attr:meta])*] / forall $x:ident : $type:ty , $e:expr => use $Name:ident; $($tail:tt)*) => {
+        $(#[$attr])*
+        impl $crate::Child<$Parent, $type> for $Name {
+            fn link<'a>($x: &'a $type) -> &'a ::std::path::Path { ($e).as_ref() }
+        }
 
-        $crate::typedir!(node $Name;);
+        $crate::__parent_ctx!(@shared [$(#[$attr])*] $Name);
+        $crate::__parent_ctx!($Parent [] / $($tail)*);
+    };
+    ($Parent:ident [$(#[$attr:meta])*] / $link:expr => node $Name:ident $(: $op:ident $(+ $ops:ident)*)? $({$($subdirs:tt)*})?; $($tail:tt)*) => {
+
+        $crate::typedir!(@child $(#[$attr])* node $Name $(: $op $(+ $ops)*)? [$($($subdirs)*)?]);
 
+        $(#[$attr])*
         impl $crate::Child<$Parent, ()> for $Name {
             fn link(_: &()) -> &::std::path::Path { ($link).as_ref() }
         }
 
+        $(#[$attr])*
+        impl $crate::HasParent for $Name {
+            type Parent = $Parent;
+            fn link_depth() -> usize {
+                <Self as $crate::Child<$Parent, ()>>::link(&()).components().count()
+            }
+        }
+
         // Children have *this* node as parent
-        $crate::__parent_ctx!($Name / $($($subdirs)*)?);
+        $crate::__parent_ctx!($Name [] / $($($subdirs)*)?);
 
         // Continue in the tail with the same parent context
-        $crate::__parent_ctx!($Parent / $($tail)*);
+        $crate::__parent_ctx!($Parent [] / $($tail)*);
     };
-    ($Parent:ident / forall $x:ident : $type:ty , $e:expr => node $Name:ident $({$($subdirs:tt)*})?; $($tail:tt)*) => {
+    ($Parent:ident [$(#[$attr:meta])*] / forall $x:ident : $type:ty , $e:expr => node $Name:ident $(: $op:ident $(+ $ops:ident)*)? $({$($subdirs:tt)*})?; $($tail:tt)*) => {
 
-        $crate::typedir!(node $Name;);
+        $crate::typedir!(@child $(#[$attr])* node $Name $(: $op $(+ $ops)*)? [$($($subdirs)*)?]);
 
+        $(#[$attr])*
         impl $crate::Child<$Parent, $type> for $Name {
             fn link<'a>($x: &'a $type) -> &'a ::std::path::Path { ($e).as_ref() }
         }
 
         // Children have *this* node as parent
-        $crate::__parent_ctx!($Name / $($($subdirs)*)?);
+        $crate::__parent_ctx!($Name [] / $($($subdirs)*)?);
 
         // Continue in the tail with the same parent context
-        $crate::__parent_ctx!($Parent / $($tail)*);
+        $crate::__parent_ctx!($Parent [] / $($tail)*);
     };
 }
 
+// An internal helper macro for declaring a node: its type, its filesystem
+// operations, and how it's validated
+#[doc(hidden)]
 #[macro_export]
-macro_rules! typedir {
-    // Empty `tt`: nothing to do!
-    () => {};
-    // Node with subnodes
-    (node $Name:ident $({$($subdirs:tt)*})?; $($tail:tt)*) => {
+macro_rules! __node {
+    ([$(#[$attr:meta])+] $Name:ident [$($ops:ident)*] [$($subdirs:tt)*]) => {
         #[derive(Debug, Clone, Copy)]
-        /// Newtype for typesafe handling of project directory structure
+        $(#[$attr])+
         pub struct $Name(());
 
+        $(#[$attr])+
         impl $crate::Node for $Name {}
 
+        $crate::__impl_ops!([$(#[$attr])+] $Name; $($ops)*);
+
+        $(#[$attr])+
+        impl $crate::Validate for $Name {
+            const KIND: $crate::Kind = $crate::__kind!([$($subdirs)*] $($ops)*);
+            const REQUIRED: bool = $crate::__required!($($ops)*);
+
+            #[allow(unused_variables)]
+            fn validate_children(
+                path: &::std::path::Path,
+                problems: &mut ::std::vec::Vec<$crate::Problem>,
+            ) {
+                $crate::__validate_children!($Name, path, problems [] $($subdirs)*);
+            }
+        }
+    };
+}
+
+// An internal helper macro for implementing a node's filesystem operations,
+// one at a time
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_ops {
+    ([$(#[$attr:meta])*] $Name:ident;) => {};
+    ([$(#[$attr:meta])*] $Name:ident; $op:ident $($ops:ident)*) => {
+        $(#[$attr])*
+        impl $crate::$op for $Name {}
+
+        $crate::__impl_ops!([$(#[$attr])*] $Name; $($ops)*);
+    };
+}
+
+// An internal helper macro for working out a node's kind from its children,
+// in brackets, and its filesystem operations
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __kind {
+    ([$($subdirs:tt)+] $($ops:ident)*) => { $crate::Kind::Dir };
+    ([] CreateDir $($ops:ident)*) => { $crate::Kind::Dir };
+    ([] ReadFile $($ops:ident)*) => { $crate::Kind::File };
+    ([] WriteFile $($ops:ident)*) => { $crate::Kind::File };
+    ([] $op:ident $($ops:ident)*) => { $crate::__kind!([] $($ops)*) };
+    ([]) => { $crate::Kind::Any };
+}
+
+// An internal helper macro for whether `Required` is among a node's
+// operations
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __required {
+    () => { false };
+    (Required $($ops:ident)*) => { true };
+    ($op:ident $($ops:ident)*) => { $crate::__required!($($ops)*) };
+}
+
+// An internal helper macro for validating the children of a node with
+// constant links. Like `__parent_ctx`, it collects each node's attributes
+// before matching it, leaving out doc comments, which can't go on the
+// statements it generates.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __validate_children {
+    ($Parent:ident, $path:ident, $problems:ident [$(#[$attr:meta])*]) => {};
+    ($Parent:ident, $path:ident, $problems:ident [$(#[$attr:meta])*] #[doc = $doc:expr] $($tail:tt)*) => {
+        $crate::__validate_children!($Parent, $path, $problems [$(#[$attr])*] $($tail)*);
+    };
+    ($Parent:ident, $path:ident, $problems:ident [$(#[$attr:meta])*] #[$next:meta] $($tail:tt)*) => {
+        $crate::__validate_children!($Parent, $path, $problems [$(#[$attr])* #[$next]] $($tail)*);
+    };
+    ($Parent:ident, $path:ident, $problems:ident [$(#[$attr:meta])*] forall $x:ident : $type:ty , $e:expr => $kw:ident $Name:ident $(: $op:ident $(+ $ops:ident)*)? $({$($subdirs:tt)*})?; $($tail:tt)*) => {
+        $crate::__validate_children!($Parent, $path, $problems [] $($tail)*);
+    };
+    ($Parent:ident, $path:ident, $problems:ident [$(#[$attr:meta])*] $link:expr => $kw:ident $Name:ident $(: $op:ident $(+ $ops:ident)*)? $({$($subdirs:tt)*})?; $($tail:tt)*) => {
+        $(#[$attr])*
+        $crate::__validate::<$Name>(
+            &$path.join(<$Name as $crate::Child<$Parent, ()>>::link(&())),
+            $problems,
+        );
+        $crate::__validate_children!($Parent, $path, $problems [] $($tail)*);
+    };
+}
+
+/// Declare a tree of nodes. Attributes and doc comments before a node are
+/// put on its type and on every impl generated for it, so that a `#[cfg]`
+/// leaves out all of them; its children need the same `#[cfg]`. A node
+/// without any attributes gets a generic doc comment.
+///
+/// A node can be the child of more than one parent: it's declared once, at the
+/// top level, and linked to from each of its parents with `use`, as in
+/// `DEPS => use Deps;`. Its children are the same under every parent. Since
+/// it can't tell which parent it's under, it has no `PathBuf::parent`, and
+/// linking to a node declared within a parent is an error.
+#[macro_export]
+macro_rules! typedir {
+    // Empty `tt`: nothing to do!
+    () => {};
+    // A node declared within its parent, whose children's links are left to
+    // the parent context, but which still needs them to validate itself
+    (@child node $($tail:tt)*) => {
+        $crate::typedir!(
+            @child
+            /// Newtype for typesafe handling of project directory structure
+            node $($tail)*
+        );
+    };
+    (@child $(#[$attr:meta])+ node $Name:ident $(: $op:ident $(+ $ops:ident)*)? [$($subdirs:tt)*]) => {
+        $crate::__node!([$(#[$attr])+] $Name [$($op $($ops)*)?] [$($subdirs)*]);
+    };
+    // Node without attributes
+    (node $($tail:tt)*) => {
+        $crate::typedir!(
+            /// Newtype for typesafe handling of project directory structure
+            node $($tail)*
+        );
+    };
+    // Node with subnodes, and the filesystem operations it supports, like
+    // `node Config: ReadFile + WriteFile;`
+    ($(#[$attr:meta])+ node $Name:ident $(: $op:ident $(+ $ops:ident)*)? $({$($subdirs:tt)*})?; $($tail:tt)*) => {
+        $crate::__node!([$(#[$attr])+] $Name [$($op $($ops)*)?] [$($($subdirs)*)?]);
+
         // Children have *this* node as parent
-        $($crate::__parent_ctx!($Name / $($subdirs)*);)?
+        $($crate::__parent_ctx!($Name [] / $($subdirs)*);)?
 
         // Continue in the tail
         $crate::typedir!($($tail)*);
     };
 }
 
+// An internal helper macro for extending a path by a chain of nodes, each
+// into a `$Path<Node>`
+#[doc(hidden)]
 #[macro_export]
-macro_rules! path {
-    ($root:expr) => {
+macro_rules! __chain {
+    ($Path:ident, $root:expr) => {
         $root
     };
-    ($root:expr => $segment:ty) => {
-        $crate::Extend::<_, $crate::PathBuf<$segment>>::extend($root, ())
+    // Parametric links are matched first: a `ty` can't be followed by
+    // parentheses, so the node's path is matched one identifier at a time
+    ($Path:ident, $root:expr => $($segment:ident)::+ ($link:expr) $(=> $($tail:tt)+)?) => {
+        $crate::__chain!(
+            $Path,
+            $crate::Extend::<_, $crate::$Path<$($segment)::+>>::extend($root, $link)
+            $(=> $($tail)+)?
+        )
     };
-    ($root:expr => $segment:ty => $($tail:tt)*) => {
-        $crate::path!($crate::path!($root:expr => $segment:ty) => $($tail::tt)*)
+    ($Path:ident, $root:expr => $segment:ty $(=> $($tail:tt)+)?) => {
+        $crate::__chain!(
+            $Path,
+            $crate::Extend::<_, $crate::$Path<$segment>>::extend($root, ())
+            $(=> $($tail)+)?
+        )
     };
 }
 
+/// Extend an owned path by a chain of nodes, like `path!(root => A => B)`. A
+/// node with a parametric link takes it in parentheses, as in
+/// `path!(root => Target => Profile(&name))`.
 #[macro_export]
-macro_rules! pathref {
-    ($root:expr => $segment:ty) => {
-        $crate::Extend::<_, $crate::PathRef<$segment>>::extend(&mut $root, ())
+macro_rules! path {
+    ($($args:tt)*) => {
+        $crate::__chain!(PathBuf, $($args)*)
     };
-    ($root:expr => $segment:ty => $($tail:tt)*) => {
-        $crate::pathref!($crate::pathref!($root:expr => $segment:ty) => $($tail::tt)*)
+}
+
+/// Borrow a path, extended by a chain of nodes, as a `PathRef`. This takes
+/// the same segments as `path!`.
+#[macro_export]
+macro_rules! pathref {
+    ($root:expr => $($tail:tt)+) => {
+        $crate::__chain!(PathRef, &mut $root => $($tail)+)
     };
 }
 
@@ -287,20 +831,40 @@ mod tests {
     mod proj {
         use super::*;
 
-        pub const ROOT: &'static str = "/my/root/path";
-        pub const SRC: &'static str = "src";
-        pub const MAIN_RS: &'static str = "main.rs";
-        pub const TARGET: &'static str = "target";
+        pub const ROOT: &str = "/my/root/path";
+        pub const SRC: &str = "src";
+        pub const MAIN_RS: &str = "main.rs";
+        pub const TARGET: &str = "target";
+        pub const NESTED: &str = "nested/deeper";
+        pub const SHARED: &str = "shared";
 
         typedir! {
             node Root {
-                SRC => node Src {
-                    MAIN_RS => node MainRs;
-                };
+                SRC => use Src;
+                /// Build outputs
                 TARGET => node Target {
+                    /// One profile's outputs
                     forall s: &str, s => node Profile;
+                    /// Outputs anywhere else, even outside the target
+                    forall s: &std::path::Path, s => node Output {
+                        NESTED => node OutputNested;
+                    };
+                };
+                #[cfg(any())]
+                MISSING => node Missing: ReadFile;
+                SHARED => node Shared {
+                    MAIN_RS => use MainRs;
                 };
             };
+
+            /// Sources, which can be nested
+            node Src: CreateDir + Required {
+                MAIN_RS => use MainRs;
+                forall s: &str, s => use Src;
+                NESTED => node Nested;
+            };
+
+            node MainRs: ReadFile + WriteFile;
         }
 
         impl P<Root> {
@@ -309,10 +873,23 @@ mod tests {
             pub fn init() -> Self {
                 Self::new(Root(()), ROOT)
             }
+
+            pub fn at(path: &std::path::Path) -> Self {
+                Self::new(Root(()), path)
+            }
+        }
+
+        /// This crate's root, as a stand-in for a project's
+        impl FindRoot for Root {
+            const MARKER: &'static str = "Cargo.toml";
+
+            fn at(path: std::path::PathBuf) -> P<Self> {
+                P::new(Root(()), path)
+            }
         }
     }
 
-    use *;
+    use proj::*;
 
     macro_rules! assert_path_eq {
         ($path:expr, $string:expr) => {
@@ -365,13 +942,192 @@ mod tests {
         assert_path_eq!(root, ROOT);
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn pathref_undoes_links_with_several_components() {
+        let mut root = P::<Root>::init();
+        {
+            let mut src: R<Src> = (&mut root).extend(());
+            {
+                let nested: R<Nested> = (&mut src).extend(());
+                assert_path_eq!(nested, &format!("{}/{}/{}", ROOT, SRC, NESTED));
+            }
+            assert_path_eq!(src, &format!("{}/{}", ROOT, SRC));
+        }
+        assert_path_eq!(root, ROOT);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pathref_undoes_absolute_and_dotted_links() {
+        let mut root = P::<Root>::init();
+        let target = format!("{}/{}", ROOT, TARGET);
+        {
+            let mut target_ref: R<Target> = (&mut root).extend(());
+            for link in ["/elsewhere/out", "./out", "../out"] {
+                {
+                    let _output: R<Output> = (&mut target_ref).extend(std::path::Path::new(link));
+                }
+                assert_path_eq!(target_ref, &target);
+            }
+        }
+        assert_path_eq!(root, ROOT);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pathref_macro_chains_segments() {
+        let mut root = P::<Root>::init();
+        {
+            let profile = pathref!(root => Target => Profile("someprofile"));
+            assert_path_eq!(profile, &format!("{}/{}/{}", ROOT, TARGET, "someprofile"));
+        }
+        assert_path_eq!(root, ROOT);
+        let src = dir!(root => Src);
+        assert_path_eq!(src, &format!("{}/{}", ROOT, SRC));
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "serde"))]
+    fn paths_round_trip_through_serde() {
+        let mut root = P::<Root>::init();
+        let main_rs = path!(root.clone() => Src => MainRs);
+        let json = serde_json::to_string(&main_rs).unwrap();
+        assert_eq!(json, format!("\"{}/{}/{}\"", ROOT, SRC, MAIN_RS));
+        let parsed: P<MainRs> = serde_json::from_str(&json).unwrap();
+        assert_path_eq!(parsed, &format!("{}/{}/{}", ROOT, SRC, MAIN_RS));
+        let src = pathref!(root => Src);
+        assert_eq!(
+            serde_json::to_string(&src).unwrap(),
+            format!("\"{}/{}\"", ROOT, SRC)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn parents_undo_links() {
+        let root = P::<Root>::init();
+        let nested = path!(root.clone() => Src => Nested);
+        let src: P<Src> = nested.parent();
+        assert_path_eq!(src, &format!("{}/{}", ROOT, SRC));
+        // A parametric link may have several components, or none of the
+        // target's, but the links beneath it are still undone
+        let output = std::path::Path::new("/some/output");
+        let nested = path!(root => Target => Output(output) => OutputNested);
+        let output: P<Output> = nested.parent();
+        assert_path_eq!(output, "/some/output");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn nodes_can_have_several_parents() {
+        let root = P::<Root>::init();
+        let main_rs = path!(root.clone() => Shared => MainRs);
+        assert_path_eq!(main_rs, &format!("{}/{}/{}", ROOT, SHARED, MAIN_RS));
+        let main_rs = path!(root => Src => Src("sub") => MainRs);
+        assert_path_eq!(main_rs, &format!("{}/{}/sub/{}", ROOT, SRC, MAIN_RS));
+        // Neither has a parent, which each `use` checks when it's compiled
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn relative_paths_between_nodes() {
+        let root = P::<Root>::init();
+        let main_rs = path!(root.clone() => Src => MainRs);
+        let profile = path!(root.clone() => Target => Profile("dev"));
+        assert_path_eq!(main_rs.relative_to(&root), &format!("{}/{}", SRC, MAIN_RS));
+        assert_path_eq!(
+            main_rs.relative_to(&profile),
+            &format!("../../{}/{}", SRC, MAIN_RS)
+        );
+        assert_path_eq!(root.relative_to(&main_rs), "../..");
+        assert_path_eq!(root.relative_to(&root), "");
+    }
+
+    #[test]
+    fn nodes_have_kinds() {
+        assert_eq!(Src::KIND, Kind::Dir);
+        assert_eq!(MainRs::KIND, Kind::File);
+        assert_eq!(Root::KIND, Kind::Dir);
+        // A node with children is a directory, wherever it's declared
+        assert_eq!(Target::KIND, Kind::Dir);
+        assert_eq!(Profile::KIND, Kind::Any);
+        const { assert!(Src::REQUIRED) };
+        const { assert!(!Target::REQUIRED) };
+    }
+
+    #[test]
+    fn missing_required_nodes_are_reported() {
+        // This crate has a source directory, but no `src/src`
+        let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert!(P::<Root>::at(manifest_dir).validate().is_empty());
+        let root = P::<Root>::at(&manifest_dir.join(SRC));
+        assert_eq!(
+            root.validate(),
+            [Problem::Missing(
+                manifest_dir.join(SRC).join(SRC),
+                Kind::Dir
+            )]
+        );
+        // A root that doesn't exist has nothing in it, but isn't required
+        assert!(P::<Root>::init().validate().is_empty());
+    }
+
+    #[test]
+    fn roots_are_found_upwards() {
+        let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let root = Root::find_from(&manifest_dir.join(SRC)).unwrap();
+        assert_eq!(root.as_ref(), manifest_dir);
+        assert_eq!(
+            find_upward(&manifest_dir.join(SRC), |dir| dir.ends_with(SRC)),
+            Some(manifest_dir.join(SRC).as_path())
+        );
+        assert_eq!(find_upward(manifest_dir, |_| false), None);
+    }
+
+    #[test]
+    fn files_are_created_whole_and_only_once() {
+        let dir = std::env::temp_dir().join(format!("typedir-create-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src: P<Src> = P::<Root>::at(&dir).extend(());
+        Src::create_dir_all(&src).unwrap();
+        let file: P<MainRs> = src.clone().extend(());
+        MainRs::create_new(&file, "first").unwrap();
+        let err = MainRs::create_new(&file, "second").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        MainRs::replace(&file, "third").unwrap();
+        assert_eq!(MainRs::read_to_string(&file).unwrap(), "third");
+        // Nothing is left on the side
+        assert_eq!(std::fs::read_dir(&src).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nodes_declare_their_operations() {
+        fn readable<N: ReadFile>() {}
+        fn writable<N: WriteFile>() {}
+        fn creatable<N: CreateDir>() {}
+        readable::<MainRs>();
+        writable::<MainRs>();
+        creatable::<Src>();
+    }
+
     #[test]
     #[cfg(unix)]
     fn simple_parametric_paths_work() {
         let root = P::<Root>::init();
         let target = path!(root => Target);
-        // No macro for this yet
         let profile: P<Profile> = target.extend("someprofile");
         assert_path_eq!(profile, &format!("{}/{}/{}", ROOT, TARGET, "someprofile"));
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn path_macro_chains_segments() {
+        let main_rs = path!(P::<Root>::init() => Src => MainRs);
+        assert_path_eq!(main_rs, &format!("{}/{}/{}", ROOT, SRC, MAIN_RS));
+        let name = String::from("someprofile");
+        let profile = path!(P::<Root>::init() => proj::Target => proj::Profile(name.as_str()));
+        assert_path_eq!(profile, &format!("{}/{}/{}", ROOT, TARGET, "someprofile"));
+    }
 }
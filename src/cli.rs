@@ -1,12 +1,25 @@
 use clap::{Parser, ValueEnum};
 
 use largo_core::{build, conf, dirs, files, Result};
+use typedir::{ReadFile, WriteFile};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     command: Subcommand,
+    /// Operate on the project containing this directory, as if run there
+    #[arg(short = 'C', global = true, value_name = "DIR")]
+    directory: Option<std::path::PathBuf>,
+    /// Operate on the project with this `largo.toml`
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "directory")]
+    manifest_path: Option<std::path::PathBuf>,
+    #[command(flatten)]
+    term: TermFlags,
+    /// Never use the network, like `offline = true` in `[registry]`: every
+    /// dependency must already be in the lockfile
+    #[arg(long, global = true)]
+    offline: bool,
     /// Print the parsed cli options and exit
     #[cfg(debug_assertions)]
     #[arg(long)]
@@ -19,6 +32,33 @@ enum Subcommand {
     Create(CreateSubcommand),
     #[command(flatten)]
     Project(ProjectSubcommand),
+    /// Get and set options in the global configuration
+    #[command(subcommand)]
+    Config(ConfigSubcommand),
+    /// Print the path to the enclosing project's `largo.toml`
+    LocateProject {
+        /// Print the path to the workspace's manifest instead
+        #[arg(long)]
+        workspace: bool,
+    },
+    /// Report the TeX installation and configuration that largo finds
+    Env,
+    /// Rewrite a project's deprecated configuration in place
+    Fix {
+        /// Rename and move deprecated keys in `largo.toml`
+        #[arg(long, required = true)]
+        manifest: bool,
+    },
+    /// Describe an error or warning code at length
+    Explain {
+        /// A code like `E0001`
+        code: String,
+    },
+    /// Run a sequence of commands defined in an `[alias]` table
+    Run {
+        /// The name of the alias
+        alias: String,
+    },
     #[cfg(debug_assertions)]
     /// Print the Largo configuration
     DebugLargo,
@@ -30,19 +70,137 @@ enum CreateSubcommand {
     Init(InitSubcommand),
     /// Create a largo project in a new directory
     New(InitSubcommand),
+    /// Make a largo project of a directory of loose TeX sources, moving them
+    /// into `src`
+    Import {
+        /// The directory to import; defaults to the current one
+        dir: Option<std::path::PathBuf>,
+        /// The project's name; defaults to the directory's
+        #[arg(long)]
+        name: Option<String>,
+        /// The main file, if more than one has a `\documentclass`
+        #[arg(long)]
+        main: Option<String>,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum ConfigSubcommand {
+    /// Print the value of an option
+    Get {
+        /// A dotted key, like `doc.reader`
+        key: String,
+    },
+    /// Set an option in `~/.largo/config.toml`
+    Set {
+        /// A dotted key, like `doc.reader`
+        key: String,
+        value: String,
+    },
+    /// Print every option that has a value
+    List,
 }
 
 #[derive(Debug, clap::Subcommand)]
 enum ProjectSubcommand {
     /// Build the current project
     Build(BuildSubcommand),
+    /// Check the current project for errors quickly, without producing a
+    /// document
+    Check(BuildSubcommand),
+    /// Open the last built document, without building it
+    View {
+        #[arg(short = 'p', long)]
+        /// Overrides the default build profile if set
+        profile: Option<String>,
+        /// View the release profile's document; short for `--profile release`
+        #[arg(long, conflicts_with = "profile")]
+        release: bool,
+        /// View the named `[[document]]` instead of the first one
+        #[arg(long)]
+        doc: Option<String>,
+        /// Show this position in the source, like `main.tex:12`, if the
+        /// reader is configured for forward search
+        #[arg(long, value_name = "FILE:LINE[:COLUMN]")]
+        at: Option<largo_core::reader::SourcePosition>,
+    },
     /// Erase the build directory
     Clean {
+        #[arg(short = 'p', long)]
+        /// Only erase this profile's outputs
+        profile: Option<String>,
+        /// Erase the build cache shared by all projects instead
+        #[arg(long, conflicts_with_all = ["profile", "deps", "doc"])]
+        cache: bool,
+        /// Only erase fetched dependencies
+        #[arg(long)]
+        deps: bool,
+        /// Only erase built documents
+        #[arg(long)]
+        doc: bool,
+        /// List what would be erased, without erasing it
         #[arg(long)]
+        dry_run: bool,
+        /// Move to the trash instead of erasing
+        #[arg(long)]
+        trash: bool,
+    },
+    /// Build every document in the tests directory and check for errors
+    Test {
+        /// Only run tests whose names contain this string
+        filter: Option<String>,
+        #[arg(short = 'p', long)]
+        /// Overrides the default build profile if set
+        profile: Option<String>,
+        /// Also fail tests whose documents produce warnings
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+    /// List the available profiles and the settings in effect in each
+    Profiles,
+    /// Count the words in a document
+    Wc {
+        /// Count the named `[[document]]` instead of the first one
+        #[arg(long)]
+        doc: Option<String>,
+    },
+    /// Update dependencies as recorded in the lockfile
+    Update {
+        /// Only update this dependency
+        name: Option<String>,
+        /// List the dependencies that have newer versions, without updating
+        /// them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Find dependencies the sources don't load, and packages they load that
+    /// aren't dependencies
+    Audit {
+        #[arg(short = 'p', long)]
+        /// Look for packages installed in this profile instead of the default
+        profile: Option<String>,
+    },
+    /// Remove a dependency from the project
+    Remove {
+        /// The name of the dependency
+        name: String,
+        /// Also delete any files fetched for the dependency
+        #[arg(long)]
+        purge: bool,
+    },
+    /// Generate a standalone TeX project. For now, this writes a `Makefile`
+    /// that builds the project as largo does.
+    Eject {
+        #[arg(short = 'p', long)]
+        /// Build as this profile does, rather than the default one
         profile: Option<String>,
+        /// Build as the release profile does; short for `--profile release`
+        #[arg(long, conflicts_with = "profile")]
+        release: bool,
+        /// Build the named `[[document]]` instead of the first one
+        #[arg(long)]
+        doc: Option<String>,
     },
-    /// Generate a standalone TeX project
-    Eject,
     #[cfg(debug_assertions)]
     /// Print the project configuration
     DebugProject,
@@ -52,6 +210,153 @@ enum ProjectSubcommand {
     DebugBuild(BuildSubcommand),
 }
 
+/// Options for terminal output, which override `LARGO_TERM_QUIET`,
+/// `LARGO_TERM_VERBOSE`, and `LARGO_TERM_COLOR`, and the `[term]` table of the
+/// global configuration. `NO_COLOR` turns color off unless something else
+/// asks for it.
+#[derive(Debug, clap::Args)]
+struct TermFlags {
+    /// Print only errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print all of the TeX engine's output
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// When to color output
+    #[arg(long, global = true, value_enum, value_name = "WHEN")]
+    color: Option<Color>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl From<Color> for conf::TermColor {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Auto => conf::TermColor::Auto,
+            Color::Always => conf::TermColor::Always,
+            Color::Never => conf::TermColor::Never,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// How much to print, and whether to color it
+#[derive(Debug, Clone, Copy)]
+struct Term {
+    verbosity: Verbosity,
+    color: conf::TermColor,
+}
+
+/// A boolean environment variable, if it's set
+fn env_flag(name: &str) -> Result<Option<bool>> {
+    match std::env::var(name) {
+        Ok(value) => match value.as_str() {
+            "true" | "1" => Ok(Some(true)),
+            "false" | "0" => Ok(Some(false)),
+            _ => Err(anyhow::anyhow!("invalid value `{}` for `{}`", value, name)),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+impl Term {
+    /// Settle the terminal options: flags take precedence over environment
+    /// variables, which take precedence over the global configuration.
+    fn resolve(flags: &TermFlags, conf: &conf::TermConfig) -> Result<Self> {
+        let quiet = env_flag("LARGO_TERM_QUIET")?;
+        let verbose = env_flag("LARGO_TERM_VERBOSE")?;
+        let verbosity = if flags.quiet {
+            Verbosity::Quiet
+        } else if flags.verbose {
+            Verbosity::Verbose
+        } else if quiet.unwrap_or(conf.quiet) {
+            Verbosity::Quiet
+        } else if verbose.unwrap_or(conf.verbose) {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        };
+        // See https://no-color.org: any non-empty value counts
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let color = match (flags.color, std::env::var("LARGO_TERM_COLOR")) {
+            (Some(color), _) => color.into(),
+            (None, Ok(color)) => color.parse()?,
+            (None, Err(_)) if no_color && matches!(conf.color, conf::TermColor::Auto) => {
+                conf::TermColor::Never
+            }
+            (None, Err(_)) => conf.color,
+        };
+        Ok(Self { verbosity, color })
+    }
+
+    fn color_choice(&self, is_terminal: bool) -> termcolor::ColorChoice {
+        match self.color {
+            conf::TermColor::Auto if is_terminal => termcolor::ColorChoice::Auto,
+            conf::TermColor::Auto | conf::TermColor::Never | conf::TermColor::Bool(false) => {
+                termcolor::ColorChoice::Never
+            }
+            conf::TermColor::Always | conf::TermColor::Bool(true) => termcolor::ColorChoice::Always,
+        }
+    }
+
+    fn stdout(&self) -> termcolor::StandardStream {
+        use std::io::IsTerminal;
+        let choice = self.color_choice(std::io::stdout().is_terminal());
+        termcolor::StandardStream::stdout(choice)
+    }
+
+    fn stderr(&self) -> termcolor::StandardStream {
+        use std::io::IsTerminal;
+        let choice = self.color_choice(std::io::stderr().is_terminal());
+        termcolor::StandardStream::stderr(choice)
+    }
+
+    /// Print a warning to stderr
+    fn warn(&self, msg: &str) -> Result<()> {
+        self.labeled("warning", termcolor::Color::Yellow, msg)
+    }
+
+    /// Print a note to stderr
+    fn note(&self, msg: &str) -> Result<()> {
+        self.labeled("note", termcolor::Color::Cyan, msg)
+    }
+
+    fn labeled(&self, label: &str, color: termcolor::Color, msg: &str) -> Result<()> {
+        use std::io::Write;
+        use termcolor::WriteColor;
+        let mut stderr = self.stderr();
+        stderr.set_color(
+            termcolor::ColorSpec::new()
+                .set_fg(Some(color))
+                .set_bold(true),
+        )?;
+        write!(stderr, "{}", label)?;
+        stderr.reset()?;
+        writeln!(stderr, ": {}", msg)?;
+        Ok(())
+    }
+
+    /// How much of the engine's output a build should pass on
+    fn build_verbosity(&self) -> build::Verbosity {
+        match self.verbosity {
+            Verbosity::Quiet => build::Verbosity::Info(build::LogLevel::Error),
+            Verbosity::Normal => build::Verbosity::Info(build::LogLevel::Warning),
+            Verbosity::Verbose => build::Verbosity::Noisy,
+        }
+    }
+}
+
 #[derive(Debug, Clone, ValueEnum)]
 enum TexFormat {
     Tex,
@@ -66,12 +371,56 @@ pub enum TexEngine {
     Luatex,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Template {
+    Article,
+    Beamer,
+    Book,
+    Report,
+    Chapters,
+    Letter,
+    Ieee,
+    Acm,
+}
+
+impl From<Template> for files::templates::DocumentTemplate {
+    fn from(template: Template) -> Self {
+        use files::templates::DocumentTemplate;
+        match template {
+            Template::Article => DocumentTemplate::Article,
+            Template::Beamer => DocumentTemplate::Beamer,
+            Template::Book => DocumentTemplate::Book,
+            Template::Report => DocumentTemplate::Report,
+            Template::Chapters => DocumentTemplate::Chapters,
+            Template::Letter => DocumentTemplate::Letter,
+            Template::Ieee => DocumentTemplate::Ieee,
+            Template::Acm => DocumentTemplate::Acm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Editor {
+    /// Visual Studio Code, with the LaTeX Workshop extension
+    Vscode,
+    Texstudio,
+}
+
+impl From<Editor> for files::editors::Editor {
+    fn from(editor: Editor) -> Self {
+        match editor {
+            Editor::Vscode => files::editors::Editor::Vscode,
+            Editor::Texstudio => files::editors::Editor::TeXstudio,
+        }
+    }
+}
+
 #[derive(Debug, Parser)]
 #[clap(group(
     clap::ArgGroup::new("type")
         .multiple(false)
         .args(&["package", "class"])
-        .conflicts_with("doc")
+        .conflicts_with_all(["doc", "template", "title", "author"])
 ))]
 struct InitSubcommand {
     // TODO: should probably be a `PathBuf`
@@ -79,6 +428,10 @@ struct InitSubcommand {
     /// Create a (La)TeX package.
     #[arg(long)]
     package: bool,
+    /// Write the package in docstrip's `.dtx` format, with a `.ins` file to
+    /// generate it from, and its documentation alongside.
+    #[arg(long, requires = "package", conflicts_with = "beamer")]
+    dtx: bool,
     /// Create a (La)TeX class.
     #[arg(long)]
     class: bool,
@@ -90,16 +443,42 @@ struct InitSubcommand {
         default_value_if("class", "true", "false")
     )]
     doc: bool,
-    /// Create a Beamer project. If the `--package` flag is passed, create an
-    /// empty Beamer template.
-    #[clap(skip)]
-    _beamer: bool,
+    /// Create a Beamer presentation. If the `--package` flag is passed, create
+    /// an empty Beamer theme instead.
+    #[arg(long, conflicts_with_all = ["class", "template"])]
+    beamer: bool,
+    /// The kind of document to scaffold: `article`, `beamer`, `book`,
+    /// `report`, `chapters`, `letter`, `ieee`, or `acm`, or the name of one of your own
+    /// templates in `~/.largo/templates`
+    #[arg(long)]
+    template: Option<String>,
+    /// The theme of a Beamer presentation, like `Madrid` or `metropolis`
+    #[arg(long)]
+    theme: Option<String>,
+    /// The document's title, which is asked for if it isn't given
+    #[arg(long)]
+    title: Option<String>,
+    /// The document's author, which is asked for if it isn't given
+    #[arg(long)]
+    author: Option<String>,
     #[arg(long, value_enum)]
     /// Overrides the default TeX format if set
     system: Option<TexFormat>,
     #[arg(long, value_enum)]
     /// Overrides the default TeX engine if set
     engine: Option<TexEngine>,
+    /// Set the project up to be built with largo from this editor
+    #[arg(long, value_enum)]
+    editor: Option<Editor>,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+enum MessageFormat {
+    /// Human-readable, colored status lines
+    #[default]
+    Human,
+    /// One JSON object per line
+    Json,
 }
 
 #[derive(Debug, Parser)]
@@ -107,9 +486,35 @@ struct BuildSubcommand {
     #[arg(short = 'p', long)]
     /// Overrides the default build profile if set
     profile: Option<String>,
-    /// Print output from TeX engine
-    #[arg(short = 'v', long)]
-    verbose: bool,
+    /// Build in the release profile; short for `--profile release`
+    #[arg(long, conflicts_with = "profile")]
+    release: bool,
+    /// Build the named `[[document]]` instead of the first one
+    #[arg(long)]
+    doc: Option<String>,
+    /// Open the document in a reader after a successful build
+    #[arg(long)]
+    open: bool,
+    /// Show this position in the source, like `main.tex:12`, if the reader
+    /// is configured for forward search
+    #[arg(long, value_name = "FILE:LINE[:COLUMN]", requires = "open")]
+    at: Option<largo_core::reader::SourcePosition>,
+    /// Report how long each phase of the build took
+    #[arg(long)]
+    timings: bool,
+    /// The format of build messages
+    #[arg(long, value_enum, default_value_t)]
+    message_format: MessageFormat,
+    /// Require the lockfile to be up to date
+    #[arg(long)]
+    locked: bool,
+    /// Download CTAN dependencies even if they're already installed
+    #[arg(long)]
+    force_vendor: bool,
+    /// Add the CTAN packages suggested for files the engine couldn't find to
+    /// the dependencies
+    #[arg(long)]
+    fix: bool,
 }
 
 impl Cli {
@@ -120,71 +525,456 @@ impl Cli {
             println!("{:#?}", self);
             return Ok(());
         }
-        self.command.execute()
+        let location = match (self.directory, self.manifest_path) {
+            (Some(dir), _) => dirs::ProjectLocation::Dir(dir),
+            (None, Some(manifest)) => dirs::ProjectLocation::Manifest(manifest),
+            (None, None) => dirs::ProjectLocation::CurrentDir,
+        };
+        self.command.execute(&location, &self.term, self.offline)
     }
 }
 
 impl InitSubcommand {
-    fn project_kind(&self) -> dirs::ProjectKind {
+    fn project_kind(&self) -> Result<dirs::ProjectKind> {
         use dirs::ProjectKind::*;
-        if self.doc {
-            Document
+        Ok(if self.doc && self.beamer {
+            Document(files::templates::DocumentTemplate::Beamer)
+        } else if self.doc {
+            match self.template.as_deref() {
+                None => Document(Default::default()),
+                // The built-in templates take precedence over the user's own
+                Some(name) => match Template::from_str(name, true) {
+                    Ok(template) => Document(template.into()),
+                    Err(_) => User(files::templates::user::UserTemplate::load(name)?),
+                },
+            }
+        } else if self.package && self.beamer {
+            BeamerTheme
+        } else if self.package && self.dtx {
+            DtxPackage
         } else if self.package {
             Package
         } else if self.class {
             Class
         } else {
             unreachable!()
-        }
+        })
     }
 
-    fn execute(self, path: std::path::PathBuf) -> Result<()> {
+    fn execute(self, path: std::path::PathBuf, conf: &conf::LargoConfig) -> Result<()> {
+        let kind = self.project_kind()?;
+        self.init(kind, path, conf)
+    }
+
+    fn init(
+        self,
+        kind: dirs::ProjectKind,
+        path: std::path::PathBuf,
+        conf: &conf::LargoConfig,
+    ) -> Result<()> {
+        let is_presentation = matches!(
+            kind,
+            dirs::ProjectKind::Document(files::templates::DocumentTemplate::Beamer)
+        );
+        if self.theme.is_some() && !is_presentation {
+            return Err(anyhow::anyhow!(
+                "`--theme` only applies to Beamer presentations"
+            ));
+        }
+        // Only documents have titles and authors
+        let (title, author) = match kind {
+            dirs::ProjectKind::Document(_) | dirs::ProjectKind::User(_) => (
+                match self.title {
+                    Some(title) => Some(title),
+                    None => prompt("Title", Some(&self.name))?,
+                },
+                match self.author {
+                    Some(author) => Some(author),
+                    None => prompt("Author", None)?,
+                },
+            ),
+            _ => (None, None),
+        };
         let new_project = dirs::NewProject {
             name: self.name.as_str(),
-            kind: self.project_kind(),
+            kind,
+            theme: self.theme.as_deref(),
+            title: title.as_deref(),
+            author: author.as_deref(),
+            editor: self.editor.map(Into::into),
+            packages: &conf.init.packages,
         };
         new_project.init(path)
     }
 }
 
+/// Ask for a value at the terminal, if there is one. There's no answer if
+/// there isn't, or if it's left blank for the default.
+fn prompt(question: &str, default: Option<&str>) -> Result<Option<String>> {
+    use std::io::{IsTerminal, Write};
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return Ok(None);
+    }
+    let mut stderr = std::io::stderr();
+    match default {
+        Some(default) => write!(stderr, "{} [{}]: ", question, default)?,
+        None => write!(stderr, "{}: ", question)?,
+    }
+    stderr.flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok((!answer.is_empty()).then(|| answer.to_string()))
+}
+
 impl CreateSubcommand {
-    fn execute(self) -> Result<()> {
+    fn execute(self, base_dir: std::path::PathBuf, conf: &conf::LargoConfig) -> Result<()> {
         match self {
-            CreateSubcommand::Init(subcmd) => {
-                let path = std::env::current_dir().unwrap();
-                subcmd.execute(path)
-            }
+            CreateSubcommand::Init(subcmd) => subcmd.execute(base_dir, conf),
             CreateSubcommand::New(subcmd) => {
-                std::fs::create_dir(&subcmd.name)?;
-                // FIXME This unnecessary clone is an artifact of these commands
-                // not being factored quite right
-                let name = subcmd.name.clone();
-                subcmd.execute(std::path::PathBuf::from(name))
+                let path = base_dir.join(&subcmd.name);
+                // Look for the template first, so that a missing one doesn't
+                // leave an empty directory behind
+                let kind = subcmd.project_kind()?;
+                std::fs::create_dir(&path)?;
+                subcmd.init(kind, path, conf)
+            }
+            CreateSubcommand::Import { dir, name, main } => {
+                let dir = match dir {
+                    Some(dir) => base_dir.join(dir),
+                    None => base_dir,
+                };
+                let name = match name {
+                    Some(name) => name,
+                    None => dir
+                        .canonicalize()?
+                        .file_name()
+                        .ok_or_else(|| anyhow::anyhow!("can't name a project at the root"))?
+                        .to_string_lossy()
+                        .into_owned(),
+                };
+                let imported = largo_core::import::import(&dir, &name, main.as_deref())?;
+                println!(
+                    "Imported `{}`, moving {} files into `{}`",
+                    imported.main_file,
+                    imported.moved.len(),
+                    dirs::SRC_DIR
+                );
+                Ok(())
             }
         }
     }
 }
 
 impl BuildSubcommand {
+    fn profile(&self) -> Result<Option<conf::ProfileName<'_>>> {
+        match &self.profile {
+            Some(p) => Ok(Some(p.as_str().try_into()?)),
+            None if self.release => Ok(Some(conf::RELEASE_PROFILE.try_into()?)),
+            None => Ok(None),
+        }
+    }
+
     fn try_to_build<'c>(
         &'c self,
         project: conf::Project<'c>,
         conf: &'c conf::LargoConfig,
+        term: Term,
+        check: bool,
+        input_dirs: Vec<std::path::PathBuf>,
     ) -> Result<build::BuildRunner<'c>> {
-        let profile = match &self.profile {
-            Some(p) => Some(p.as_str().try_into()?),
+        let document = match &self.doc {
+            Some(d) => Some(d.as_str().try_into()?),
             None => None,
         };
-        let verbosity = if self.verbose {
-            build::Verbosity::Noisy
-        } else {
-            build::Verbosity::Silent
-        };
         build::BuildBuilder::new(conf, project)
-            .with_profile(profile)
-            .with_verbosity(verbosity)
+            .with_profile(self.profile()?)
+            .with_document(document)
+            .with_verbosity(term.build_verbosity())
+            .with_check(check)
+            .with_timings(self.timings)
+            .with_input_dirs(input_dirs)
             .try_finish()
     }
+
+    /// Lock a project's dependencies, and install them for a profile
+    async fn install_dependencies(
+        &self,
+        project: &conf::Project<'_>,
+        profile: &conf::ProfileName<'_>,
+        conf: &conf::LargoConfig<'_>,
+        term: Term,
+    ) -> Result<()> {
+        let progress = |info: build::LargoInfo<'_>| show_download(term, self.message_format, info);
+        let deps_layout = project.config.project.deps_layout.unwrap_or_default();
+        let target_dir = typedir::path!(project.root.clone() => dirs::TargetDir);
+        let deps_dir = dirs::DepsDir::for_profile(&target_dir, profile, deps_layout);
+        largo_core::dependencies::lock::ensure(
+            &project.root,
+            &project.config.dependencies,
+            &deps_dir,
+            self.locked,
+            self.force_vendor,
+            &conf.registry,
+            &progress,
+        )
+        .await?;
+        let notes = largo_core::dependencies::install(
+            &project.root,
+            profile,
+            deps_layout,
+            &project.config.dependencies,
+            self.force_vendor,
+            &conf.registry,
+            &progress,
+        )
+        .await?;
+        for note in notes {
+            term.note(&note)?;
+        }
+        Ok(())
+    }
+
+    /// Build the largo projects this one depends on, with their own
+    /// dependencies, returning their build directories. They're built in the
+    /// release profile, whichever profile this one is built in: what's used
+    /// of them is their finished output.
+    async fn build_dependencies<'c>(
+        &'c self,
+        project: &conf::Project<'_>,
+        conf: &'c conf::LargoConfig<'_>,
+        term: Term,
+    ) -> Result<Vec<std::path::PathBuf>> {
+        let mut input_dirs = Vec::new();
+        let profile = self
+            .profile()?
+            .unwrap_or_else(|| conf.default_profile.clone());
+        let release: conf::ProfileName = conf::RELEASE_PROFILE.try_into()?;
+        let deps = largo_core::dependencies::largo_dependencies(project, &profile, &conf.registry)?;
+        for dep in deps {
+            let name = dep.config.project.name.to_string();
+            self.install_dependencies(&dep, &release, conf, term)
+                .await?;
+            let mut build_runner = build::BuildBuilder::new(conf, dep)
+                .with_profile(Some(release.clone()))
+                .with_verbosity(term.build_verbosity())
+                .with_input_dirs(input_dirs.clone())
+                .try_finish()?;
+            if !build_runner.has_main_file() {
+                continue;
+            }
+            let mut build_info = build_runner.run().await?;
+            // A dependency's missing files are for its own authors to add
+            self.report(&mut build_info, term, &mut Vec::new()).await?;
+            if build_info.failed() {
+                return Err(anyhow::anyhow!("could not build dependency `{}`", name));
+            }
+            drop(build_info);
+            input_dirs.push(build_runner.build_dir().to_path_buf());
+        }
+        Ok(input_dirs)
+    }
+
+    /// Print the progress of a build as it happens, returning the code of the
+    /// first error that has one
+    async fn report(
+        &self,
+        build_info: &mut build::BuildOutput<'_>,
+        term: Term,
+        missing_files: &mut Vec<String>,
+    ) -> Result<Option<&'static str>> {
+        use std::io::Write;
+        use tokio_stream::StreamExt;
+        let mut error_code = None;
+        loop {
+            let info = tokio::select! {
+                info = build_info.next() => info,
+                _ = tokio::signal::ctrl_c() => {
+                    build_info.cancel().await?;
+                    return Err(anyhow::anyhow!("build interrupted"));
+                }
+            };
+            let Some(info) = info else {
+                break;
+            };
+            let info = info?;
+            if let build::BuildInfo::EngineInfo(engine_info) = &info {
+                if engine_info.is_error() {
+                    error_code = error_code.or(engine_info.code());
+                }
+                if let largo_core::engines::EngineInfo::Error { msg, .. } = engine_info {
+                    if let Some(file) = largo_core::diagnostics::missing_file(msg) {
+                        if !missing_files.iter().any(|missing| missing == file) {
+                            missing_files.push(file.to_string());
+                        }
+                    }
+                }
+            }
+            match self.message_format {
+                MessageFormat::Human => {
+                    // Quiet builds only report errors
+                    if term.verbosity == Verbosity::Quiet
+                        && matches!(info, build::BuildInfo::LargoInfo(_))
+                    {
+                        continue;
+                    }
+                    let mut stdout = term.stdout();
+                    BuildInfo(info).write(&mut stdout)?;
+                    writeln!(&mut stdout)?;
+                }
+                MessageFormat::Json => {
+                    let mut stdout = std::io::stdout().lock();
+                    serde_json::to_writer(&mut stdout, &info)?;
+                    writeln!(&mut stdout)?;
+                }
+            }
+        }
+        Ok(error_code)
+    }
+
+    async fn execute(
+        &self,
+        project: conf::Project<'_>,
+        conf: &conf::LargoConfig<'_>,
+        term: Term,
+        check: bool,
+    ) -> Result<()> {
+        if check && self.open {
+            return Err(anyhow::anyhow!(
+                "there is no document to open after a check"
+            ));
+        }
+        // Checks also look for a broken project layout
+        if check {
+            let problems = project.root.validate();
+            if !problems.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "the project's layout is broken:\n  {}",
+                    problems
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("\n  ")
+                ));
+            }
+        }
+        let profile = self
+            .profile()?
+            .unwrap_or_else(|| conf.default_profile.clone());
+        self.install_dependencies(&project, &profile, conf, term)
+            .await?;
+        // Run this inside an async runtime
+        let input_dirs = self.build_dependencies(&project, conf, term).await?;
+        let root = project.root.clone();
+        let mut build_runner = self.try_to_build(project, conf, term, check, input_dirs)?;
+        let mut build_info = build_runner.run().await?;
+        let mut missing_files = Vec::new();
+        // The first error with a code, to suggest explaining
+        let error_code = self
+            .report(&mut build_info, term, &mut missing_files)
+            .await?;
+        if let (MessageFormat::Human, Some(code)) = (&self.message_format, error_code) {
+            println!(
+                "For more information about this error, try `largo explain {}`.",
+                code
+            );
+        }
+        self.suggest_dependencies(&root, conf, term, &missing_files)
+            .await?;
+        if self.open && !build_info.failed() {
+            drop(build_info);
+            largo_core::reader::open_at(&conf.doc, build_runner.artifact(), self.at.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl BuildSubcommand {
+    /// Suggest a CTAN package for each style or class file the engine
+    /// couldn't find, or with `--fix`, add it to the dependencies
+    async fn suggest_dependencies(
+        &self,
+        root: &typedir::PathBuf<dirs::RootDir>,
+        conf: &conf::LargoConfig<'_>,
+        term: Term,
+        missing_files: &[String],
+    ) -> Result<()> {
+        for file in missing_files {
+            let suggestion =
+                match largo_core::dependencies::suggest_dependency(file, &conf.registry).await {
+                    Ok(Some(suggestion)) => suggestion,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        term.warn(&format!("cannot look up `{}` on CTAN: {:#}", file, err))?;
+                        continue;
+                    }
+                };
+            let name: conf::DependencyName = suggestion.name.as_str().try_into()?;
+            if !self.fix {
+                term.note(&format!(
+                    "`{}` may be in the CTAN package `{}` ({}); add it with \
+                     `{} = \"*\"` in `[dependencies]`, or build again with `--fix`",
+                    file, name, suggestion.caption, name
+                ))?;
+            } else if largo_core::dependencies::add_dependency(root, &name)? {
+                term.note(&format!(
+                    "added the CTAN package `{}` to the dependencies for `{}`; \
+                     build again to use it",
+                    name, file
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The width of download progress bars, in characters
+const PROGRESS_BAR_WIDTH: u64 = 25;
+
+/// Show the progress of a dependency download. A download shouldn't fail
+/// because its progress couldn't be shown, so errors here are ignored.
+fn show_download(term: Term, format: MessageFormat, info: build::LargoInfo<'_>) {
+    let _ = try_show_download(term, format, info);
+}
+
+fn try_show_download(
+    term: Term,
+    format: MessageFormat,
+    info: build::LargoInfo<'_>,
+) -> std::io::Result<()> {
+    use std::io::{IsTerminal, Write};
+    match format {
+        MessageFormat::Human => {
+            if term.verbosity == Verbosity::Quiet {
+                return Ok(());
+            }
+            let finished = matches!(info, build::LargoInfo::Downloaded { .. });
+            // Without a terminal to redraw the line in, only finished
+            // downloads are shown
+            let interactive = std::io::stdout().is_terminal();
+            if !(interactive || finished) {
+                return Ok(());
+            }
+            let mut stdout = term.stdout();
+            if interactive {
+                write!(&mut stdout, "\r")?;
+            }
+            LargoInfo(&info).write(&mut stdout)?;
+            if interactive {
+                // Clear whatever is left of a longer line
+                write!(&mut stdout, "\x1b[K")?;
+            }
+            if finished {
+                writeln!(&mut stdout)?;
+            }
+            stdout.flush()
+        }
+        MessageFormat::Json => {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer(&mut stdout, &info)?;
+            writeln!(&mut stdout)
+        }
+    }
 }
 
 // Wrapper structs for info from core
@@ -210,7 +1000,12 @@ impl<'c> LargoInfo<'c> {
         match &self.0 {
             Compiling { .. } => "Compiling",
             Running { .. } => "Running",
+            Downloading { .. } => "Downloading",
+            Downloaded { .. } => "Downloaded",
+            Fresh { .. } => "Fresh",
+            PhaseTimings { .. } => "Timings",
             Finished { .. } => "Finished",
+            Undefined { .. } => "Undefined",
         }
     }
 }
@@ -232,14 +1027,72 @@ impl<'c> LargoInfo<'c> {
         match info {
             Compiling {
                 project,
-                version: _,
+                version: Some(version),
+                root,
+            } => write!(w, "{} v{} ({})", project, version, root.display()),
+            Compiling {
+                project,
+                version: None,
                 root,
             } => write!(w, "{} ({})", project, root.display()),
             Running { exec } => write!(w, "{}", exec,),
+            Downloading {
+                name,
+                bytes,
+                total: Some(total),
+            } => {
+                let filled = (bytes * PROGRESS_BAR_WIDTH / total.max(&1)).min(PROGRESS_BAR_WIDTH);
+                write!(
+                    w,
+                    "{} [{:<width$}] {}/{} KiB",
+                    name,
+                    "=".repeat(filled as usize),
+                    bytes / 1024,
+                    total / 1024,
+                    width = PROGRESS_BAR_WIDTH as usize
+                )
+            }
+            Downloading {
+                name,
+                bytes,
+                total: None,
+            } => write!(w, "{} {} KiB", name, bytes / 1024),
+            Downloaded { name, bytes } => write!(w, "{} ({} KiB)", name, bytes / 1024),
+            Fresh { fingerprint } => write!(w, "cached build {}", &fingerprint[..12]),
+            PhaseTimings { timings } => {
+                for (i, timing) in timings.iter().enumerate() {
+                    if i > 0 {
+                        write!(w, ", ")?;
+                    }
+                    write!(w, "{} {:.2}s", timing.phase, timing.duration.as_secs_f32())?;
+                }
+                Ok(())
+            }
             Finished {
                 profile_name,
                 duration,
             } => write!(w, "`{}` in {:.2}s", profile_name, duration.as_secs_f32()),
+            Undefined {
+                references,
+                citations,
+            } => {
+                let lists = [("reference", references), ("citation", citations)];
+                let lists = lists.iter().filter(|(_, keys)| !keys.is_empty());
+                for (i, (kind, keys)) in lists.enumerate() {
+                    if i > 0 {
+                        write!(w, "; ")?;
+                    }
+                    let plural = if keys.len() == 1 { "" } else { "s" };
+                    write!(w, "{} {}{}: ", keys.len(), kind, plural)?;
+                    for (j, key) in keys.iter().enumerate() {
+                        if j > 0 {
+                            write!(w, ", ")?;
+                        }
+                        write!(w, "`{}`", key)?;
+                    }
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -251,12 +1104,75 @@ impl<'c> EngineInfo<'c> {
     {
         use largo_core::engines::EngineInfo;
         match &self.0 {
-            EngineInfo::Error { line, msg } => {
+            EngineInfo::Error { line, msg, code } => {
                 w.set_color(termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Red)))?;
-                write!(w, "error [{}]", line)?;
+                match code {
+                    Some(code) => write!(w, "error[{}]", code)?,
+                    None => write!(w, "error")?,
+                }
                 w.reset()?;
                 write!(w, ": {}", msg)?;
+                if let Some(line) = line {
+                    write!(w, " (line {})", line)?;
+                }
             }
+            EngineInfo::Warning { msg, code } => {
+                w.set_color(termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Yellow)))?;
+                match code {
+                    Some(code) => write!(w, "warning[{}]", code)?,
+                    None => write!(w, "warning")?,
+                }
+                w.reset()?;
+                write!(w, ": {}", msg)?;
+            }
+            EngineInfo::Output { line } => write!(w, "{}", line)?,
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of building one test document
+struct TestOutcome<'t> {
+    name: &'t str,
+    errors: Vec<largo_core::engines::EngineInfo>,
+    warnings: usize,
+    deny_warnings: bool,
+}
+
+impl<'t> TestOutcome<'t> {
+    fn passed(&self) -> bool {
+        self.errors.is_empty() && !(self.deny_warnings && self.warnings > 0)
+    }
+
+    fn write<W>(&self, w: &mut W) -> std::result::Result<(), std::io::Error>
+    where
+        W: std::io::Write + termcolor::WriteColor,
+    {
+        w.set_color(
+            termcolor::ColorSpec::new()
+                .set_fg(Some(termcolor::Color::Green))
+                .set_bold(true),
+        )?;
+        write!(w, "{: >12} ", "Testing")?;
+        w.reset()?;
+        write!(w, "{} ... ", self.name)?;
+        if self.passed() {
+            w.set_color(termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Green)))?;
+            write!(w, "ok")?;
+        } else {
+            w.set_color(termcolor::ColorSpec::new().set_fg(Some(termcolor::Color::Red)))?;
+            write!(w, "FAILED")?;
+        }
+        w.reset()?;
+        match self.warnings {
+            0 => (),
+            1 => write!(w, " (1 warning)")?,
+            n => write!(w, " ({} warnings)", n)?,
+        }
+        for error in &self.errors {
+            writeln!(w)?;
+            write!(w, "{: >12} ", "")?;
+            EngineInfo(error).write(w)?;
         }
         Ok(())
     }
@@ -267,68 +1183,321 @@ impl ProjectSubcommand {
         &self,
         project: conf::Project<'_>,
         conf: &conf::LargoConfig<'_>,
+        term: Term,
     ) -> Result<()> {
         use ProjectSubcommand::*;
         match self {
-            Build(subcmd) => {
-                use std::io::Write;
-                use tokio_stream::StreamExt;
-                // Run this inside an async runtime
-                let mut build_runner = subcmd.try_to_build(project, conf)?;
-                let mut build_info = build_runner.run().await?;
-                while let Some(info) = build_info.next().await {
-                    let mut stdout =
-                        termcolor::StandardStream::stdout(termcolor::ColorChoice::Auto);
-                    BuildInfo(info?).write(&mut stdout)?;
-                    writeln!(&mut stdout, "")?;
+            Build(subcmd) => subcmd.execute(project, conf, term, false).await,
+            Check(subcmd) => subcmd.execute(project, conf, term, true).await,
+            View {
+                profile,
+                release,
+                doc,
+                at,
+            } => {
+                let profile: Option<conf::ProfileName> = match profile {
+                    Some(p) => Some(p.as_str().try_into()?),
+                    None if *release => Some(conf::RELEASE_PROFILE.try_into()?),
+                    None => None,
+                };
+                let document: Option<conf::DocumentName> = match doc {
+                    Some(d) => Some(d.as_str().try_into()?),
+                    None => None,
+                };
+                let artifact = build::BuildBuilder::new(conf, project)
+                    .with_profile(profile.clone())
+                    .with_document(document)
+                    .try_artifact()?;
+                if !artifact.exists() {
+                    let profile = profile.unwrap_or_else(|| conf.default_profile.clone());
+                    return Err(anyhow::anyhow!(
+                        "the document hasn't been built in profile `{}` yet; \
+                         run `largo build{}` first",
+                        profile,
+                        if profile == conf.default_profile {
+                            String::new()
+                        } else {
+                            format!(" --profile {}", profile)
+                        }
+                    ));
                 }
-                Ok::<(), largo_core::Error>(())
+                largo_core::reader::open_at(&conf.doc, artifact, at.as_ref())
             }
             // the `Project` is (reasonable) proof that it is a valid project:
             // the manifest file parses. It's *reasonably* safe to delete a
             // directory if `proj` is constructed.
-            Clean { profile } => {
-                let root = project.root;
-                let mut target_dir = typedir::path!(root => dirs::TargetDir);
+            Clean {
+                cache: true,
+                dry_run,
+                trash,
+                ..
+            } => {
+                let cache_dir = dirs::BuildCacheDir::global()?;
+                if *dry_run {
+                    println!("Would remove {}", cache_dir.display());
+                    return Ok(());
+                }
+                if *trash {
+                    return dirs::Removal::Dir(cache_dir.to_path_buf()).try_trash();
+                }
+                dirs::remove_dir_all(&cache_dir)
+            }
+            Clean {
+                profile,
+                deps,
+                doc,
+                dry_run,
+                trash,
+                ..
+            } => {
+                let root = project.root.clone();
+                let target_dir = typedir::path!(root => dirs::TargetDir);
                 let cwd = std::env::current_dir().expect("no current directory");
 
-                if !cwd.starts_with(&target_dir) {
+                if cwd.starts_with(&target_dir) {
                     return Err(anyhow::anyhow!(
                         "currently within `{}`, not deleting",
                         &target_dir.display()
                     ));
                 }
+                if !target_dir.exists() {
+                    return Ok(());
+                }
+                dirs::TargetDir::try_check_tag(&target_dir)?;
 
-                // Check the correctness of the cache tag
-                let expected = files::CACHEDIR_TAG_SIGNATURE;
-                let contents = {
-                    let cache_tag_file = typedir::pathref!(target_dir => dirs::CachedirTagFile);
-                    std::fs::read_to_string(&cache_tag_file)
+                let profile: Option<conf::ProfileName> = match profile {
+                    Some(p) => Some(p.as_str().try_into()?),
+                    None => None,
+                };
+                let opts = dirs::CleanOptions {
+                    deps: *deps,
+                    docs: *doc,
                 };
-                let sig = contents.as_ref().and_then(|c| Ok(c.get(0..expected.len())));
-                match sig {
-                    Ok(Some(sig)) if sig == expected => (),
-                    _ => {
+                let layout = dirs::DocLayout::new(&project.config);
+                let removals = dirs::TargetDir::try_plan_clean(
+                    &project.root,
+                    &layout,
+                    profile.as_ref(),
+                    opts,
+                )?;
+                for removal in removals {
+                    if *dry_run {
+                        println!("Would remove {}", removal.path().display());
+                    } else if *trash {
+                        removal.try_trash()?;
+                    } else {
+                        removal.try_remove()?;
+                    }
+                }
+                Ok(())
+            }
+            Test {
+                filter,
+                profile,
+                deny_warnings,
+            } => {
+                use std::io::Write;
+                use tokio_stream::StreamExt;
+                let root = project.root.clone();
+                let tests_dir = typedir::path!(root => dirs::TestsDir);
+                let mut tests = dirs::TestsDir::try_list(&tests_dir)?;
+                if let Some(filter) = filter {
+                    tests.retain(|test| test.contains(filter.as_str()));
+                }
+                let profile: Option<conf::ProfileName> = match profile {
+                    Some(p) => Some(p.as_str().try_into()?),
+                    None => None,
+                };
+                let mut stdout = term.stdout();
+                let mut failed = 0;
+                for test in &tests {
+                    let mut build_runner = build::BuildBuilder::new(conf, project.clone())
+                        .with_profile(profile.clone())
+                        .with_verbosity(build::Verbosity::Info(build::LogLevel::Error))
+                        .with_test(Some(test.as_str()))
+                        .try_finish()?;
+                    let mut build_info = build_runner.run().await?;
+                    let mut errors = Vec::new();
+                    while let Some(info) = build_info.next().await {
+                        if let build::BuildInfo::EngineInfo(info) = info? {
+                            if info.is_error() {
+                                errors.push(info);
+                            }
+                        }
+                    }
+                    let outcome = TestOutcome {
+                        name: test.trim_end_matches(".tex"),
+                        errors,
+                        warnings: build_info.warnings(),
+                        deny_warnings: *deny_warnings,
+                    };
+                    if !outcome.passed() {
+                        failed += 1;
+                    }
+                    outcome.write(&mut stdout)?;
+                    writeln!(&mut stdout)?;
+                }
+                writeln!(
+                    &mut stdout,
+                    "test result: {} passed; {} failed",
+                    tests.len() - failed,
+                    failed
+                )?;
+                if failed > 0 {
+                    return Err(anyhow::anyhow!(
+                        "{} of {} tests failed",
+                        failed,
+                        tests.len()
+                    ));
+                }
+                Ok(())
+            }
+            Profiles => {
+                for (name, profile) in project.config.all_profiles().iter() {
+                    if *name == conf.default_profile {
+                        println!("{} (default)", name);
+                    } else {
+                        println!("{}", name);
+                    }
+                    let profile = project.config.effective_profile(profile.clone());
+                    for (key, value) in profile.settings()? {
+                        println!("    {} = {}", key, value);
+                    }
+                }
+                Ok(())
+            }
+            Wc { doc } => {
+                let doc: Option<conf::DocumentName> = match doc {
+                    Some(d) => Some(d.as_str().try_into()?),
+                    None => None,
+                };
+                let main_file = project.config.main_file(doc)?;
+                let root = project.root.clone();
+                let src_dir = typedir::path!(root => dirs::SrcDir);
+                let counts = largo_core::wc::count_words(&src_dir, &main_file)?;
+                for count in &counts {
+                    println!("{: >8} {}", count.words, count.path.display());
+                }
+                let total: usize = counts.iter().map(|count| count.words).sum();
+                println!("{: >8} total", total);
+                Ok(())
+            }
+            Update { name, dry_run } => {
+                let name: Option<conf::DependencyName> = match name {
+                    Some(name) => Some(name.as_str().try_into()?),
+                    None => None,
+                };
+                if *dry_run {
+                    let outdated = largo_core::dependencies::lock::outdated(
+                        &project.root,
+                        &project.config.dependencies,
+                        name.as_ref(),
+                        &conf.registry,
+                    )
+                    .await?;
+                    if outdated.is_empty() {
+                        println!("All dependencies are up to date");
+                    }
+                    for dep in outdated {
+                        let version = |v: Option<String>| v.unwrap_or_else(|| "?".to_string());
+                        print!(
+                            "{} {} -> {}",
+                            dep.name,
+                            version(dep.locked),
+                            version(dep.latest)
+                        );
+                        if !dep.compatible {
+                            print!(" (doesn't meet the version requirement)");
+                        }
+                        println!();
+                    }
+                    return Ok(());
+                }
+                largo_core::dependencies::lock::update(
+                    &project.root,
+                    &project.config.dependencies,
+                    name.as_ref(),
+                    &conf.registry,
+                    &|info: build::LargoInfo<'_>| show_download(term, MessageFormat::Human, info),
+                )
+                .await
+            }
+            Audit { profile } => {
+                let profile: conf::ProfileName = match profile {
+                    Some(p) => p.as_str().try_into()?,
+                    None => conf.default_profile.clone(),
+                };
+                let audit = largo_core::dependencies::audit::audit(
+                    &project.root,
+                    &profile,
+                    project.config.project.deps_layout.unwrap_or_default(),
+                    &project.config.dependencies,
+                )?;
+                for name in &audit.unused {
+                    term.warn(&format!(
+                        "dependency `{}` is declared, but no package it provides is loaded",
+                        name
+                    ))?;
+                }
+                for name in &audit.undeclared {
+                    term.warn(&format!(
+                        "package `{}` is loaded, but isn't a dependency or installed",
+                        name
+                    ))?;
+                }
+                if audit.is_empty() {
+                    println!("No problems found");
+                    return Ok(());
+                }
+                Err(anyhow::anyhow!(
+                    "found {} unused and {} undeclared dependencies",
+                    audit.unused.len(),
+                    audit.undeclared.len()
+                ))
+            }
+            Remove { name, purge } => {
+                let name: conf::DependencyName = name.as_str().try_into()?;
+                largo_core::dependencies::remove_dependency(&project.root, &name, *purge)
+            }
+            Eject {
+                profile,
+                release,
+                doc,
+            } => {
+                let profile: Option<conf::ProfileName> = match profile {
+                    Some(p) => Some(p.as_str().try_into()?),
+                    None if *release => Some(conf::RELEASE_PROFILE.try_into()?),
+                    None => None,
+                };
+                let document: Option<conf::DocumentName> = match doc {
+                    Some(d) => Some(d.as_str().try_into()?),
+                    None => None,
+                };
+                build::check_ejectable(&project.config.dependencies)?;
+                let root = project.root.clone();
+                let build_runner = build::BuildBuilder::new(conf, project)
+                    .with_profile(profile)
+                    .with_document(document)
+                    .try_finish()?;
+                let makefile = build_runner.makefile()?;
+                let makefile_path = typedir::path!(root => dirs::Makefile);
+                // Only a Makefile that was ejected before is replaced
+                match dirs::Makefile::read_to_string(&makefile_path) {
+                    Ok(old) if !old.starts_with(build::MAKEFILE_HEADER) => {
                         return Err(anyhow::anyhow!(
-                            "invalid cache signature, not deleting `{}`",
-                            target_dir.display()
+                            "`{}` already exists; move it aside and try again",
+                            makefile_path.display()
                         ));
                     }
-                }
-
-                // Now actually delete the directory
-                match &profile {
-                    Some(profile) => {
-                        let profile: largo_core::conf::ProfileName = profile.as_str().try_into()?;
-                        use typedir::Extend;
-                        let profile_dir: typedir::PathBuf<dirs::ProfileTargetDir> =
-                            target_dir.extend(&profile);
-                        dirs::remove_dir_all(&profile_dir)
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(err.into())
                     }
-                    None => dirs::remove_dir_all(&target_dir),
+                    _ => (),
                 }
+                dirs::Makefile::write(&makefile_path, makefile)?;
+                println!("Wrote `{}`", makefile_path.display());
+                Ok(())
             }
-            Eject => todo!(),
             // This subcommand only exists in debug builds
             #[cfg(debug_assertions)]
             DebugProject => {
@@ -338,7 +1507,7 @@ impl ProjectSubcommand {
             // This subcommand only exists in debug builds
             #[cfg(debug_assertions)]
             DebugBuild(subcmd) => {
-                let build = subcmd.try_to_build(project, conf)?;
+                let build = subcmd.try_to_build(project, conf, term, false, Vec::new())?;
                 println!("{:#?}", build);
                 Ok(())
             }
@@ -346,29 +1515,215 @@ impl ProjectSubcommand {
     }
 }
 
-impl Subcommand {
+impl ConfigSubcommand {
     fn execute(self) -> Result<()> {
-        // We start the async runtime here because we get the config files here,
-        // and they have bounded lifetimes. This isn't the only solution; for
-        // example, we could instead inline the construction of the config data
-        // (and thereby read those files asynchronously).
-        conf::with_config(|conf, proj| {
-            tokio::runtime::Builder::new_multi_thread()
-                .enable_all()
-                .build()
-                .unwrap()
-                .block_on(async {
-                    match self {
-                        Subcommand::Create(subcmd) => subcmd.execute(),
-                        Subcommand::Project(subcmd) => match proj {
-                            Some(proj) => subcmd.execute(proj, conf).await,
-                            None => Err(anyhow::anyhow!("no enclosing project found")),
-                        },
-                        // This subcommand only exists in debug builds
-                        #[cfg(debug_assertions)]
-                        Subcommand::DebugLargo => Ok(println!("{:#?}", &conf)),
+        match self {
+            ConfigSubcommand::Get { key } => match conf::global::get(&key)? {
+                Some(value) => println!("{}", value),
+                None => return Err(anyhow::anyhow!("`{}` is not set", key)),
+            },
+            ConfigSubcommand::Set { key, value } => conf::global::set(&key, &value)?,
+            ConfigSubcommand::List => {
+                for (key, value) in conf::global::list()? {
+                    println!("{} = {}", key, value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print what we know about the environment, for bug reports
+fn print_env(conf: &conf::LargoConfig, proj: Option<&conf::Project>) -> Result<()> {
+    use largo_core::probe;
+    println!("largo {}", env!("CARGO_PKG_VERSION"));
+
+    println!("\nConfiguration:");
+    let config_file = dirs::LargoConfigFile::global()?;
+    println!("    global   {}", config_file.display());
+    match proj {
+        Some(proj) => {
+            let root = proj.root.clone();
+            let manifest = typedir::path!(root => dirs::ProjectConfigFile);
+            println!("    project  {}", manifest.display());
+        }
+        None => println!("    project  (none)"),
+    }
+
+    println!("\nPrograms:");
+    let programs = probe::probe_programs(conf);
+    let width = programs
+        .iter()
+        .map(|prog| prog.name.len())
+        .max()
+        .unwrap_or(0);
+    for prog in &programs {
+        match &prog.path {
+            Some(path) => println!(
+                "    {:width$}  {}  {}",
+                prog.name,
+                path.display(),
+                prog.version.as_deref().unwrap_or("(unknown version)"),
+            ),
+            None => println!("    {:width$}  `{}` not found", prog.name, prog.exec),
+        }
+    }
+
+    println!(
+        "\nDistribution: {}",
+        probe::distribution(&programs).unwrap_or("unknown")
+    );
+
+    println!("\nKpathsea:");
+    let width = probe::KPATHSEA_VARS
+        .iter()
+        .map(|var| var.len())
+        .max()
+        .unwrap_or(0);
+    for var in probe::KPATHSEA_VARS {
+        let value = probe::kpathsea_var(var);
+        println!(
+            "    {:width$}  {}",
+            var,
+            value.as_deref().unwrap_or("(unset)")
+        );
+    }
+    Ok(())
+}
+
+/// Run each step of an alias in turn, stopping at the first that fails. The
+/// project's aliases take precedence over the global ones.
+fn run_alias(
+    name: &str,
+    conf: &conf::LargoConfig,
+    proj: Option<&conf::Project>,
+    base_dir: std::path::PathBuf,
+) -> Result<()> {
+    use clap::CommandFactory;
+    let steps = proj
+        .and_then(|proj| proj.config.alias.get(name))
+        .or_else(|| conf.alias.get(name))
+        .ok_or_else(|| anyhow::anyhow!("no alias named `{}`", name))?;
+    let cwd = match proj {
+        Some(proj) => proj.root.to_path_buf(),
+        None => base_dir,
+    };
+    let largo = std::env::current_exe()?;
+    let cli = Cli::command();
+    for step in steps {
+        let words = shell_words::split(step).map_err(|err| {
+            anyhow::anyhow!("invalid step `{}` in alias `{}`: {}", step, name, err)
+        })?;
+        let Some((first, args)) = words.split_first() else {
+            continue;
+        };
+        let mut cmd = if cli.find_subcommand(first).is_some() {
+            let mut cmd = std::process::Command::new(&largo);
+            cmd.arg(first);
+            cmd
+        } else {
+            std::process::Command::new(first)
+        };
+        let status = cmd
+            .args(args)
+            .current_dir(&cwd)
+            .status()
+            .map_err(|err| anyhow::anyhow!("failed to run `{}`: {}", first, err))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "step `{}` of alias `{}` failed ({})",
+                step,
+                name,
+                status
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl Subcommand {
+    fn execute(
+        self,
+        location: &dirs::ProjectLocation,
+        flags: &TermFlags,
+        offline: bool,
+    ) -> Result<()> {
+        // Neither of these should depend on the configuration being readable
+        match self {
+            Subcommand::Config(subcmd) => return subcmd.execute(),
+            Subcommand::Explain { code } => {
+                let diag = largo_core::diagnostics::lookup(&code)
+                    .ok_or_else(|| anyhow::anyhow!("no diagnostic with code `{}`", code))?;
+                println!("{}: {}\n", diag.code, diag.summary);
+                print!("{}", diag.explanation);
+                return Ok(());
+            }
+            // A project that isn't in a workspace is its own workspace
+            Subcommand::LocateProject { workspace } => {
+                let roots = location
+                    .find_roots()?
+                    .ok_or_else(|| anyhow::anyhow!("no enclosing project found"))?;
+                let manifest = match roots.workspace {
+                    Some(root) if workspace => typedir::path!(root => dirs::ProjectConfigFile),
+                    _ => typedir::path!((roots.project) => dirs::ProjectConfigFile),
+                };
+                println!("{}", manifest.display());
+                return Ok(());
+            }
+            // A manifest that needs fixing may not be readable at all
+            Subcommand::Fix { manifest: _ } => {
+                let root = location
+                    .find_root()?
+                    .ok_or_else(|| anyhow::anyhow!("no enclosing project found"))?;
+                let changes = conf::migrate::fix_manifest(&root)?;
+                if changes.is_empty() {
+                    println!("Nothing to fix in {}", dirs::PROJECT_CONFIG_FILE);
+                }
+                for change in changes {
+                    println!("Fixed: {}", change);
+                }
+                return Ok(());
+            }
+            _ => (),
+        }
+        let base_dir = location.base_dir()?;
+        let conf::Config {
+            global: mut conf,
+            project: proj,
+            warnings,
+        } = conf::load(location)?;
+        conf.registry.offline |= offline;
+        let conf = &conf;
+        let term = Term::resolve(flags, &conf.term)?;
+        for warning in &warnings {
+            term.warn(warning)?;
+        }
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                match self {
+                    Subcommand::Create(subcmd) => subcmd.execute(base_dir, conf),
+                    Subcommand::Config(_)
+                    | Subcommand::Explain { .. }
+                    | Subcommand::LocateProject { .. }
+                    | Subcommand::Fix { .. } => {
+                        unreachable!()
                     }
-                })
-        })?
+                    Subcommand::Env => print_env(conf, proj.as_ref()),
+                    Subcommand::Run { alias } => run_alias(&alias, conf, proj.as_ref(), base_dir),
+                    Subcommand::Project(subcmd) => match proj {
+                        Some(proj) => subcmd.execute(proj, conf, term).await,
+                        None => Err(anyhow::anyhow!("no enclosing project found")),
+                    },
+                    // This subcommand only exists in debug builds
+                    #[cfg(debug_assertions)]
+                    Subcommand::DebugLargo => {
+                        println!("{:#?}", &conf);
+                        Ok(())
+                    }
+                }
+            })
     }
 }